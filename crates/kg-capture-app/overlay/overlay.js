@@ -0,0 +1,348 @@
+// Browser-source lyric overlay. The app serves this page on the same port as
+// its WebSocket, which sends the timeline, playback positions and states, the
+// lyric window's appearance and the timing offset as JSON messages.
+"use strict";
+
+// Same as the app's playback clock: reports are extrapolated for a short
+// while, and small backward corrections hold instead of rewinding.
+const MAX_EXTRAPOLATION_MS = 300;
+const SNAP_THRESHOLD_MS = 250;
+const RECONNECT_DELAY_MS = 1000;
+// As in the lyric window, the clock holds still in these states, and the
+// lyrics are hidden once the song is stopped or over.
+const FROZEN_STATES = ["Paused", "Stopped", "SongEnded"];
+const HIDDEN_STATES = ["Stopped", "SongEnded"];
+const PREVIOUS_DIM = 0.55;
+const CANDIDATE_DIM = 0.78;
+const KEYED_OUTLINE_WIDTH = 2;
+const FADING_TRANSITIONS = ["crossfade", "scale_in", "fade"];
+const EASINGS = {
+  linear: "linear",
+  ease_out: "cubic-bezier(0.33, 1, 0.68, 1)",
+  ease_in_out: "cubic-bezier(0.65, 0, 0.35, 1)",
+};
+
+const lyrics = document.getElementById("lyrics");
+const state = {
+  appearance: null,
+  offsetMs: 0,
+  timeline: null,
+  anchor: null,
+  playbackState: null,
+  displayedMs: 0,
+  shown: undefined,
+  words: [],
+};
+
+function connect() {
+  const socket = new WebSocket(`ws://${location.host}/`);
+  socket.onmessage = (event) => receive(JSON.parse(event.data));
+  socket.onclose = () => setTimeout(connect, RECONNECT_DELAY_MS);
+}
+
+function receive(message) {
+  switch (message.type) {
+    case "appearance":
+      state.appearance = message.appearance;
+      applyAppearance(message.appearance);
+      state.shown = undefined;
+      break;
+    case "timing_offset":
+      state.offsetMs = message.offset_ms;
+      break;
+    case "timeline":
+      state.timeline = message.timeline;
+      state.anchor = null;
+      state.playbackState = null;
+      state.shown = undefined;
+      break;
+    case "cleared":
+      state.timeline = null;
+      state.anchor = null;
+      state.playbackState = null;
+      break;
+    case "playback":
+      observe(message.playback);
+      break;
+    case "playback_state":
+      if (state.timeline && message.timeline_id === state.timeline.id) {
+        state.playbackState = message.state;
+      }
+      break;
+  }
+}
+
+function observe(playback) {
+  if (!state.timeline || playback.timeline_id !== state.timeline.id) {
+    return;
+  }
+  const now = performance.now();
+  const predicted = positionAt(now);
+  state.anchor = {
+    positionMs: playback.position_ms,
+    currentLine: playback.current_line,
+    receivedAt: now,
+  };
+  const holds =
+    predicted !== null &&
+    playback.position_ms < predicted &&
+    predicted - playback.position_ms < SNAP_THRESHOLD_MS;
+  state.displayedMs = holds ? predicted : playback.position_ms;
+}
+
+function positionAt(now) {
+  if (!state.anchor) {
+    return null;
+  }
+  if (FROZEN_STATES.includes(state.playbackState)) {
+    return state.anchor.positionMs;
+  }
+  const elapsed = Math.min(now - state.anchor.receivedAt, MAX_EXTRAPOLATION_MS);
+  return state.anchor.positionMs + elapsed;
+}
+
+function frame(now) {
+  requestAnimationFrame(frame);
+  if (!state.timeline || !state.appearance || HIDDEN_STATES.includes(state.playbackState)) {
+    lyrics.replaceChildren();
+    state.shown = undefined;
+    return;
+  }
+  const position = positionAt(now);
+  if (position !== null) {
+    state.displayedMs = Math.max(state.displayedMs, position);
+  }
+  const timeMs = state.anchor ? state.displayedMs + state.offsetMs : -Infinity;
+  const current = currentLine(state.timeline.lines);
+  if (current !== state.shown) {
+    const previous = state.shown;
+    state.shown = current;
+    build(current);
+    if (previous !== undefined && previous !== null && current === previous + 1) {
+      animate();
+    }
+  }
+  paint(timeMs);
+}
+
+// The line the hook reported, followed forward as the clock passes the next
+// line's start. With a timing offset the reported line no longer applies, so
+// the line is found from the shifted position instead.
+function currentLine(lines) {
+  if (!state.anchor) {
+    return null;
+  }
+  if (state.offsetMs !== 0) {
+    return locate(lines, state.displayedMs + state.offsetMs);
+  }
+  let index = state.anchor.currentLine;
+  if (index === null || index >= lines.length) {
+    return null;
+  }
+  while (index + 1 < lines.length && lines[index + 1].start_ms <= state.displayedMs) {
+    index += 1;
+  }
+  return index;
+}
+
+// Like WeSing, a finished line stays current until the next one starts.
+function locate(lines, timeMs) {
+  for (let index = lines.length - 1; index >= 0; index -= 1) {
+    if (lines[index].start_ms <= timeMs) {
+      return index;
+    }
+  }
+  return null;
+}
+
+function applyAppearance(appearance) {
+  const chromaKey = appearance.background_mode === "chroma_key";
+  const background = {
+    solid: appearance.background,
+    transparent: "transparent",
+    chroma_key: appearance.chroma_key_color,
+  }[appearance.background_mode];
+  const root = document.documentElement.style;
+  root.setProperty("--background", background);
+  root.setProperty("--text", appearance.text);
+  root.setProperty("--highlight", appearance.highlight);
+  root.setProperty("--previous", dim(appearance.text, PREVIOUS_DIM));
+  root.setProperty("--candidate", dim(appearance.text, CANDIDATE_DIM));
+  root.setProperty("--font-family", appearance.font_family ? `"${appearance.font_family}"` : "system-ui");
+  root.setProperty("--active-size", `${appearance.active_font_size}px`);
+  root.setProperty("--candidate-size", `${appearance.candidate_font_size}px`);
+  root.setProperty("--align", appearance.alignment);
+  root.setProperty("--wipe-direction", appearance.layout === "vertical" ? "to bottom" : "to right");
+  // As in the lyric window, a chroma key always gets an opaque outline as a
+  // matte between the glyphs' anti-aliased edges and the key.
+  const outlineWidth = chromaKey
+    ? Math.max(appearance.outline_width, KEYED_OUTLINE_WIDTH)
+    : appearance.outline_width;
+  root.setProperty("--outline-width", `${outlineWidth * 2}px`);
+  root.setProperty("--outline-color", appearance.outline_color);
+
+  // A chroma key keeps hard edges, as in the lyric window.
+  const effects = [];
+  const shadowBlur = chromaKey ? 0 : appearance.shadow_blur;
+  if (appearance.shadow_offset > 0 || shadowBlur > 0) {
+    const offset = appearance.shadow_offset;
+    effects.push(`drop-shadow(${offset}px ${offset}px ${shadowBlur}px ${appearance.shadow_color})`);
+  }
+  if (!chromaKey && appearance.glow_radius > 0) {
+    effects.push(`drop-shadow(0 0 ${appearance.glow_radius}px ${appearance.glow_color})`);
+  }
+  root.setProperty("--effects", effects.join(" ") || "none");
+
+  lyrics.className = [
+    { stacked: "", two_line: "two-line", vertical: "vertical" }[appearance.layout],
+    appearance.highlight_mode === "wipe" ? "wipe" : "",
+  ].join(" ");
+}
+
+function build(current) {
+  const appearance = state.appearance;
+  const lines = state.timeline.lines;
+  const children = [];
+  state.words = [];
+  if (appearance.layout === "two_line") {
+    // Even lines on the top row, odd ones below.
+    const rows = [null, null];
+    const first = current ?? 0;
+    for (let index = first; index < Math.min(first + 2, lines.length); index += 1) {
+      rows[index % 2] = index;
+    }
+    for (const index of rows) {
+      children.push(index === null ? plainLine("", "active") : sungLine(lines[index], index === current));
+    }
+  } else if (current === null) {
+    children.push(plainLine("等待第一句歌词…", "waiting"));
+    for (const line of lines.slice(0, appearance.candidate_line_count)) {
+      children.push(plainLine(line.text, "candidate"));
+    }
+  } else {
+    if (appearance.show_previous_line && current > 0) {
+      children.push(plainLine(lines[current - 1].text, "previous"));
+    }
+    children.push(sungLine(lines[current], true));
+    for (const line of lines.slice(current + 1, current + 1 + appearance.candidate_line_count)) {
+      children.push(plainLine(line.text, "candidate"));
+    }
+  }
+  lyrics.replaceChildren(...children);
+}
+
+function plainLine(text, kind) {
+  const element = document.createElement("div");
+  element.className = `line ${kind}`;
+  element.textContent = text;
+  return element;
+}
+
+// The active line, split into timed words. Untimed lines are split into
+// characters that share the line's progress.
+function sungLine(line, active) {
+  const element = document.createElement("div");
+  element.className = "line active";
+  const words = line.words.length > 0
+    ? line.words.map((word) => ({ text: word.text, startMs: word.start_ms, durationMs: word.duration_ms }))
+    : Array.from(line.text, (text) => ({ text }));
+  for (const word of words) {
+    const span = document.createElement("span");
+    span.className = "word";
+    span.textContent = word.text;
+    element.append(span);
+    if (active) {
+      state.words.push({ ...word, span, line });
+    }
+  }
+  return element;
+}
+
+function paint(timeMs) {
+  const appearance = state.appearance;
+  state.words.forEach((word, position) => {
+    let progress;
+    if (word.startMs === undefined) {
+      const line = word.line;
+      const lineProgress = line.duration_ms > 0 ? (timeMs - line.start_ms) / line.duration_ms : 1;
+      progress = clamp(clamp(lineProgress, 0, 1) * state.words.length - position, 0, 1);
+    } else if (word.durationMs > 0) {
+      progress = clamp((timeMs - word.startMs) / word.durationMs, 0, 1);
+    } else {
+      progress = timeMs >= word.startMs ? 1 : 0;
+    }
+    switch (appearance.highlight_mode) {
+      case "wipe":
+        word.span.style.setProperty("--progress", `${progress * 100}%`);
+        break;
+      case "blend":
+        word.span.style.color = mix(appearance.text, appearance.highlight, progress);
+        break;
+      default:
+        word.span.style.color = progress > 0 ? appearance.highlight : appearance.text;
+    }
+  });
+}
+
+function animate() {
+  const appearance = state.appearance;
+  if (appearance.transition === "off" || appearance.transition_ms <= 0) {
+    return;
+  }
+  // As in the lyric window, a chroma key gets no fading transitions.
+  const fades = appearance.layout === "two_line" || FADING_TRANSITIONS.includes(appearance.transition);
+  if (fades && appearance.background_mode === "chroma_key") {
+    return;
+  }
+  const timing = { duration: appearance.transition_ms, easing: EASINGS[appearance.easing] };
+  const active = lyrics.querySelector(".active");
+  if (appearance.layout === "two_line") {
+    const upcoming = Array.from(lyrics.children).find((row) => !row.contains(state.words[0]?.span));
+    upcoming?.animate([{ opacity: 0 }, { opacity: 1 }], timing);
+    return;
+  }
+  switch (appearance.transition) {
+    case "scroll_up": {
+      const vertical = appearance.layout === "vertical";
+      const slot = vertical ? active.offsetWidth + 14 : active.offsetHeight + 14;
+      const from = vertical ? `translateX(${-slot}px)` : `translateY(${slot}px)`;
+      lyrics.animate([{ transform: from }, { transform: "none" }], timing);
+      break;
+    }
+    case "crossfade":
+      lyrics.animate([{ opacity: 0 }, { opacity: 1 }], timing);
+      break;
+    case "scale_in":
+      active.animate([{ opacity: 0, transform: "scale(0.8)" }, { opacity: 1, transform: "none" }], timing);
+      break;
+    case "fade":
+      // The finished line dims from its sung color where it was sung.
+      lyrics.querySelector(".previous")?.animate([{ color: appearance.highlight }, {}], timing);
+      break;
+  }
+}
+
+function dim(hex, factor) {
+  const [red, green, blue] = channels(hex).map((channel) => Math.round(channel * factor));
+  return `rgb(${red}, ${green}, ${blue})`;
+}
+
+function mix(from, to, amount) {
+  const start = channels(from);
+  const end = channels(to);
+  const [red, green, blue] = start.map((channel, index) =>
+    Math.round(channel + (end[index] - channel) * amount));
+  return `rgb(${red}, ${green}, ${blue})`;
+}
+
+function channels(hex) {
+  const value = parseInt(hex.replace("#", ""), 16);
+  return [(value >> 16) & 0xff, (value >> 8) & 0xff, value & 0xff];
+}
+
+function clamp(value, low, high) {
+  return Math.min(Math.max(value, low), high);
+}
+
+connect();
+requestAnimationFrame(frame);
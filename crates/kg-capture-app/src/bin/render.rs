@@ -0,0 +1,30 @@
+//! `kg-capture-render`, the console front end of the headless renderer. It is
+//! a program of its own because the GUI has no console on Windows, where its
+//! errors and progress would go nowhere.
+
+use std::process::ExitCode;
+
+use kg_capture::{render, video};
+
+const USAGE: &str = "usage: kg-capture-render <frame|video> …";
+
+fn main() -> ExitCode {
+    kg_capture::init_tracing();
+    let mut arguments = std::env::args().skip(1);
+    let command = arguments.next();
+    let result = match command.as_deref() {
+        Some("frame") => render::run(arguments),
+        Some("video") => video::run(arguments),
+        _ => {
+            eprintln!("{USAGE}");
+            return ExitCode::from(2);
+        }
+    };
+    match result {
+        Ok(()) => ExitCode::SUCCESS,
+        Err(error) => {
+            eprintln!("kg-capture-render {}: {error}", command.unwrap_or_default());
+            ExitCode::from(2)
+        }
+    }
+}
@@ -0,0 +1,211 @@
+//! Host-side playback clock. WeSing reports its position only when it updates
+//! its lyric render model, so the lyric window extrapolates between reports at
+//! display refresh rate instead of stepping once per event.
+
+use std::time::{Duration, Instant, SystemTime, UNIX_EPOCH};
+
+use kg_capture_protocol::{LyricTimeline, PlaybackPosition};
+
+/// Reports older than this are treated as paused playback: extrapolation
+/// stops instead of running the highlight past a frozen song.
+pub const MAX_EXTRAPOLATION: Duration = Duration::from_millis(300);
+/// A report this far from the extrapolated position is a seek or real drift
+/// and is shown immediately. Smaller corrections never move the highlight
+/// backwards; it holds until the new report catches up.
+pub const SNAP_THRESHOLD_MS: f32 = 250.0;
+
+#[derive(Debug, Default)]
+pub struct PlaybackClock {
+    anchor: Option<Anchor>,
+    displayed_ms: f32,
+    frozen: bool,
+}
+
+#[derive(Debug)]
+struct Anchor {
+    playback: PlaybackPosition,
+    position_ms: f32,
+    received_at: Instant,
+}
+
+impl PlaybackClock {
+    /// Re-anchors the clock on a position report. The time the report spent
+    /// in transit is added when the hook's observation time is plausible; a
+    /// replayed recording carries old timestamps, which are ignored.
+    pub fn observe(&mut self, playback: &PlaybackPosition, now: Instant) {
+        let transit =
+            Duration::from_micros(timestamp_micros().saturating_sub(playback.observed_at_micros));
+        let transit = if transit <= MAX_EXTRAPOLATION {
+            transit
+        } else {
+            Duration::ZERO
+        };
+        let position_ms = playback.position_ms + transit.as_secs_f32() * 1_000.0;
+        let same_timeline = self
+            .anchor
+            .as_ref()
+            .is_some_and(|anchor| anchor.playback.timeline_id == playback.timeline_id);
+        let predicted_ms = self.position_at(now);
+        self.anchor = Some(Anchor {
+            playback: playback.clone(),
+            position_ms,
+            received_at: now,
+        });
+        self.displayed_ms = match predicted_ms {
+            Some(predicted_ms)
+                if same_timeline
+                    && position_ms < predicted_ms
+                    && predicted_ms - position_ms < SNAP_THRESHOLD_MS =>
+            {
+                predicted_ms
+            }
+            _ => position_ms,
+        };
+    }
+
+    /// Advances the displayed position to `now`.
+    pub fn advance(&mut self, now: Instant) {
+        if let Some(position_ms) = self.position_at(now) {
+            self.displayed_ms = self.displayed_ms.max(position_ms);
+        }
+    }
+
+    /// Whether the displayed position still moves, so frames are needed.
+    pub fn is_running(&self, now: Instant) -> bool {
+        !self.frozen
+            && self.anchor.as_ref().is_some_and(|anchor| {
+                now.saturating_duration_since(anchor.received_at) < MAX_EXTRAPOLATION
+            })
+    }
+
+    /// Stops extrapolating while the hook reports that playback is not
+    /// moving. Reports are still shown as they arrive.
+    pub fn set_frozen(&mut self, frozen: bool) {
+        self.frozen = frozen;
+    }
+
+    pub fn reset(&mut self) {
+        *self = Self::default();
+    }
+
+    /// The last report moved to the displayed position. Progress is carried
+    /// forward from the reported line, and the next line becomes current once
+    /// the extrapolated position reaches its start.
+    pub fn playback(&self, timeline: &LyricTimeline) -> Option<PlaybackPosition> {
+        let anchor = self.anchor.as_ref()?;
+        let mut playback = anchor.playback.clone();
+        let advanced_ms = self.displayed_ms - playback.position_ms;
+        playback.position_ms = self.displayed_ms;
+        if advanced_ms <= 0.0 {
+            return Some(playback);
+        }
+
+        let Some(mut index) = playback
+            .current_line
+            .and_then(|index| usize::try_from(index).ok())
+            .filter(|index| *index < timeline.lines.len())
+        else {
+            return Some(playback);
+        };
+        let reported_line = &timeline.lines[index];
+        let mut progress_ms = playback.line_progress.clamp(0.0, 1.0)
+            * reported_line.duration_ms.max(0.0)
+            + advanced_ms;
+        while let Some(next) = timeline.lines.get(index + 1)
+            && next.start_ms <= self.displayed_ms
+        {
+            index += 1;
+            progress_ms = self.displayed_ms - next.start_ms;
+        }
+        let line = &timeline.lines[index];
+        playback.current_line = Some(index as u32);
+        playback.line_progress = if line.duration_ms > 0.0 {
+            (progress_ms / line.duration_ms).clamp(0.0, 1.0)
+        } else {
+            1.0
+        };
+        Some(playback)
+    }
+
+    fn position_at(&self, now: Instant) -> Option<f32> {
+        self.anchor.as_ref().map(|anchor| {
+            let elapsed = if self.frozen {
+                Duration::ZERO
+            } else {
+                now.saturating_duration_since(anchor.received_at)
+                    .min(MAX_EXTRAPOLATION)
+            };
+            anchor.position_ms + elapsed.as_secs_f32() * 1_000.0
+        })
+    }
+}
+
+fn timestamp_micros() -> u64 {
+    SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .map(|duration| duration.as_micros() as u64)
+        .unwrap_or(0)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::fixtures;
+
+    fn report(position_ms: f32, current_line: u32, line_progress: f32) -> PlaybackPosition {
+        PlaybackPosition {
+            timeline_id: 1,
+            // Far in the past, as in a replay: no transit time is added.
+            observed_at_micros: 0,
+            position_ms,
+            current_line: Some(current_line),
+            line_progress,
+        }
+    }
+
+    #[test]
+    fn extrapolates_between_reports_and_stops_when_they_stop() {
+        let timeline = fixtures::timeline(1, &[("", 0.0, 1_000.0), ("", 1_000.0, 1_000.0)]);
+        let start = Instant::now();
+        let mut clock = PlaybackClock::default();
+        clock.observe(&report(900.0, 0, 0.9), start);
+
+        clock.advance(start + Duration::from_millis(50));
+        let playback = clock.playback(&timeline).unwrap();
+        assert_eq!(playback.position_ms, 950.0);
+        assert_eq!(playback.current_line, Some(0));
+        assert!((playback.line_progress - 0.95).abs() < 1e-4);
+
+        clock.advance(start + Duration::from_millis(200));
+        let playback = clock.playback(&timeline).unwrap();
+        assert_eq!(playback.current_line, Some(1));
+        assert!((playback.line_progress - 0.1).abs() < 1e-4);
+
+        assert!(clock.is_running(start + Duration::from_millis(299)));
+        assert!(!clock.is_running(start + Duration::from_millis(300)));
+        clock.advance(start + Duration::from_secs(5));
+        assert_eq!(clock.playback(&timeline).unwrap().position_ms, 1_200.0);
+    }
+
+    #[test]
+    fn small_corrections_hold_and_seeks_snap() {
+        let timeline = fixtures::timeline(1, &[("", 0.0, 1_000.0), ("", 1_000.0, 1_000.0)]);
+        let start = Instant::now();
+        let mut clock = PlaybackClock::default();
+        clock.observe(&report(0.0, 0, 0.0), start);
+        clock.advance(start + Duration::from_millis(100));
+
+        // Slightly behind the extrapolation: the highlight does not step back.
+        clock.observe(&report(80.0, 0, 0.08), start + Duration::from_millis(100));
+        assert_eq!(clock.playback(&timeline).unwrap().position_ms, 100.0);
+        clock.advance(start + Duration::from_millis(150));
+        assert_eq!(clock.playback(&timeline).unwrap().position_ms, 130.0);
+
+        // Seeks in either direction are shown at once.
+        let seeked = start + Duration::from_millis(150);
+        clock.observe(&report(1_500.0, 1, 0.5), seeked);
+        assert_eq!(clock.playback(&timeline).unwrap().position_ms, 1_500.0);
+        clock.observe(&report(0.0, 0, 0.0), seeked);
+        assert_eq!(clock.playback(&timeline).unwrap().position_ms, 0.0);
+    }
+}
@@ -1,6 +1,7 @@
 use std::env;
 use std::fs::{self, OpenOptions};
 use std::io::Write;
+#[cfg(windows)]
 use std::mem::size_of;
 use std::path::{Path, PathBuf};
 use std::process::Command;
@@ -10,11 +11,16 @@ use std::time::{Duration, SystemTime, UNIX_EPOCH};
 
 use ipc_channel::ipc::{IpcOneShotServer, IpcReceiver, IpcSender};
 use kg_capture_protocol::{HookEvent, HookHandshake, HostCommand, PROTOCOL_VERSION, SessionNonce};
+#[cfg(windows)]
 use windows::Win32::Foundation::{CloseHandle, HANDLE};
+#[cfg(windows)]
 use windows::Win32::System::Diagnostics::ToolHelp::{
     CreateToolhelp32Snapshot, PROCESSENTRY32W, Process32FirstW, Process32NextW, TH32CS_SNAPPROCESS,
 };
 
+use crate::recording::{RECORDING_FILE_NAME, Recorder};
+use crate::source::EventSource;
+
 const WESING_PROCESS_NAME: &str = "WeSing.exe";
 
 #[derive(Clone, Debug)]
@@ -23,6 +29,7 @@ pub struct Session {
     pub command_sender: IpcSender<HostCommand>,
     pub event_receiver: Arc<Mutex<IpcReceiver<HookEvent>>>,
     log_directory: PathBuf,
+    recorder: Option<Recorder>,
 }
 
 #[derive(Clone, Copy, Debug, Eq, Ord, PartialEq, PartialOrd)]
@@ -91,9 +98,12 @@ impl Session {
                 PROTOCOL_VERSION
             ),
         );
-        let with_logs = |error: String| {
-            append_log(&host_log, LogLevel::Error, format_args!("{error}"));
-            error
+        let with_logs = {
+            let host_log = host_log.clone();
+            move |error: String| {
+                append_log(&host_log, LogLevel::Error, format_args!("{error}"));
+                error
+            }
         };
         let injector = component_path("KG_CAPTURE_INJECTOR_PATH", "kg-capture-injector.exe")
             .map_err(&with_logs)?;
@@ -181,16 +191,35 @@ impl Session {
         if handshake.hello.session_nonce != nonce {
             return Err("hook session nonce did not match".into());
         }
+        let recorder = Recorder::create(&log_directory.join(RECORDING_FILE_NAME))
+            .inspect_err(|error| append_log(&host_log, LogLevel::Warn, format_args!("{error}")))
+            .ok();
 
         Ok(Self {
             process_id: handshake.hello.process_id,
             command_sender: handshake.command_sender,
             event_receiver: Arc::new(Mutex::new(handshake.event_receiver)),
             log_directory,
+            recorder,
         })
     }
+}
+
+impl EventSource for Session {
+    fn describe(&self) -> String {
+        format!("已连接到进程 {}，正在初始化歌词同步…", self.process_id)
+    }
+
+    fn next_event(&self) -> Result<Option<HookEvent>, String> {
+        self.event_receiver
+            .lock()
+            .map_err(|_| "hook event receiver lock was poisoned".to_owned())?
+            .recv()
+            .map(Some)
+            .map_err(|error| format!("hook disconnected: {error}"))
+    }
 
-    pub fn send(&self, command: HostCommand) -> Result<(), String> {
+    fn send(&self, command: HostCommand) -> Result<(), String> {
         append_log(
             &self.log_directory.join("host.log"),
             LogLevel::Debug,
@@ -201,7 +230,7 @@ impl Session {
             .map_err(|error| format!("send hook command: {error}"))
     }
 
-    pub fn log_event(&self, event: &HookEvent) {
+    fn log_event(&self, event: &HookEvent) {
         let level = match event {
             HookEvent::Warning(_) => LogLevel::Warn,
             HookEvent::Error(_) => LogLevel::Error,
@@ -212,9 +241,13 @@ impl Session {
             level,
             format_args!("receive event {event:?}"),
         );
+        if let Some(recorder) = &self.recorder {
+            recorder.record(event);
+        }
     }
 }
 
+#[cfg(windows)]
 fn process_is_running(expected_name: &str) -> Result<bool, String> {
     let snapshot = ProcessSnapshot::new()?;
     let mut entry = PROCESSENTRY32W {
@@ -234,10 +267,19 @@ fn process_is_running(expected_name: &str) -> Result<bool, String> {
     }
 }
 
+/// The injector only exists on Windows, so there is no running WeSing to
+/// collide with elsewhere; `Session::connect` fails on the missing component.
+#[cfg(not(windows))]
+fn process_is_running(_expected_name: &str) -> Result<bool, String> {
+    Ok(false)
+}
+
+#[cfg(windows)]
 fn process_name_matches(entry: &PROCESSENTRY32W, expected_name: &str) -> bool {
     process_name(entry).eq_ignore_ascii_case(expected_name)
 }
 
+#[cfg(windows)]
 fn process_name(entry: &PROCESSENTRY32W) -> String {
     let length = entry
         .szExeFile
@@ -247,10 +289,12 @@ fn process_name(entry: &PROCESSENTRY32W) -> String {
     String::from_utf16_lossy(&entry.szExeFile[..length])
 }
 
+#[cfg(windows)]
 struct ProcessSnapshot {
     handle: HANDLE,
 }
 
+#[cfg(windows)]
 impl ProcessSnapshot {
     fn new() -> Result<Self, String> {
         let handle = unsafe { CreateToolhelp32Snapshot(TH32CS_SNAPPROCESS, 0) }
@@ -259,6 +303,7 @@ impl ProcessSnapshot {
     }
 }
 
+#[cfg(windows)]
 impl Drop for ProcessSnapshot {
     fn drop(&mut self) {
         let _ = unsafe { CloseHandle(self.handle) };
@@ -390,6 +435,7 @@ fn nonce_hex(nonce: SessionNonce) -> String {
 }
 
 fn hidden_command(program: &Path) -> Command {
+    #[cfg_attr(not(windows), allow(unused_mut))]
     let mut command = Command::new(program);
     #[cfg(windows)]
     {
@@ -422,6 +468,7 @@ mod tests {
         assert_eq!(minimum_log_level(Some("error")), LogLevel::Error);
     }
 
+    #[cfg(windows)]
     #[test]
     fn process_name_matching_is_case_insensitive() {
         let mut entry = PROCESSENTRY32W::default();
@@ -433,6 +480,7 @@ mod tests {
         assert!(!process_name_matches(&entry, "Other.exe"));
     }
 
+    #[cfg(windows)]
     #[test]
     fn running_process_lookup_finds_current_process() {
         let executable = env::current_exe().unwrap();
@@ -0,0 +1,72 @@
+use kg_capture_lyrics::ass::{self, AssOptions};
+use kg_capture_lyrics::lrc::{self, LrcOptions};
+use kg_capture_lyrics::style::LyricsStyle;
+use kg_capture_lyrics::{ttml, webvtt};
+use kg_capture_protocol::LyricTimeline;
+use serde::{Deserialize, Serialize};
+
+#[derive(Debug, Clone, Copy, Eq, PartialEq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum ExportFormat {
+    Lrc,
+    EnhancedLrc,
+    Ass,
+    Ttml,
+    WebVtt,
+}
+
+impl ExportFormat {
+    pub const ALL: [Self; 5] = [
+        Self::Lrc,
+        Self::EnhancedLrc,
+        Self::Ass,
+        Self::Ttml,
+        Self::WebVtt,
+    ];
+
+    pub fn extension(self) -> &'static str {
+        match self {
+            Self::Lrc | Self::EnhancedLrc => "lrc",
+            Self::Ass => "ass",
+            Self::Ttml => "ttml",
+            Self::WebVtt => "vtt",
+        }
+    }
+
+    pub fn filter_name(self) -> &'static str {
+        match self {
+            Self::Lrc => "LRC lyrics",
+            Self::EnhancedLrc => "Enhanced LRC lyrics",
+            Self::Ass => "ASS subtitles",
+            Self::Ttml => "TTML lyrics",
+            Self::WebVtt => "WebVTT captions",
+        }
+    }
+
+    pub fn render(self, timeline: &LyricTimeline, style: &LyricsStyle) -> String {
+        let options = LrcOptions {
+            offset_ms: None,
+            include_length: true,
+            include_credit: true,
+        };
+        match self {
+            Self::Lrc => lrc::to_lrc(timeline, &options),
+            Self::EnhancedLrc => lrc::to_enhanced_lrc(timeline, &options),
+            Self::Ass => ass::to_ass(timeline, style, &AssOptions::default()),
+            Self::Ttml => ttml::to_ttml(timeline),
+            Self::WebVtt => webvtt::to_webvtt(timeline),
+        }
+    }
+}
+
+impl std::fmt::Display for ExportFormat {
+    fn fmt(&self, formatter: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        formatter.write_str(match self {
+            Self::Lrc => "LRC（逐行）",
+            Self::EnhancedLrc => "增强 LRC（逐字）",
+            Self::Ass => "ASS 卡拉 OK 字幕",
+            Self::Ttml => "TTML（逐字）",
+            Self::WebVtt => "WebVTT（逐字）",
+        })
+    }
+}
@@ -0,0 +1,23 @@
+//! Lyric timelines shared by the unit tests.
+
+use kg_capture_protocol::{LyricLine, LyricSource, LyricTimeline};
+
+/// A fixture timeline with one line per `(text, start_ms, duration_ms)` and
+/// no word timing.
+pub fn timeline(id: u64, lines: &[(&str, f32, f32)]) -> LyricTimeline {
+    LyricTimeline {
+        id,
+        source: LyricSource::Fixture,
+        lines: lines
+            .iter()
+            .enumerate()
+            .map(|(index, &(text, start_ms, duration_ms))| LyricLine {
+                index: index as u32,
+                text: text.into(),
+                start_ms,
+                duration_ms,
+                words: Vec::new(),
+            })
+            .collect(),
+    }
+}
@@ -0,0 +1,517 @@
+//! Karaoke text for the lyric window. In wipe mode the highlight fills each
+//! glyph from left to right, or downward in vertical text, as it is sung, by
+//! drawing the text twice and clipping each copy to its side of the wipe
+//! edge. Outline, shadow and glow are drawn underneath for readability over
+//! busy footage.
+
+use iced::advanced::layout::{self, Layout};
+use iced::advanced::renderer;
+use iced::advanced::text::{self, Paragraph, paragraph};
+use iced::advanced::widget::{self, Tree, Widget, tree};
+use iced::{Color, Element, Font, Length, Pixels, Point, Rectangle, Size, Vector, mouse};
+use kg_capture_lyrics::lrc::is_cjk;
+use serde::{Deserialize, Serialize};
+
+/// How sung text changes from the text color to the highlight color.
+#[derive(Clone, Copy, Debug, Default, Eq, PartialEq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum HighlightMode {
+    /// A left-to-right fill through each word.
+    #[default]
+    Wipe,
+    /// The whole word fades between the two colors.
+    Blend,
+    /// The whole word switches color when it starts.
+    Instant,
+}
+
+impl HighlightMode {
+    pub const ALL: [Self; 3] = [Self::Wipe, Self::Blend, Self::Instant];
+}
+
+impl std::fmt::Display for HighlightMode {
+    fn fmt(&self, formatter: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        formatter.write_str(match self {
+            Self::Wipe => "逐字擦除",
+            Self::Blend => "颜色渐变",
+            Self::Instant => "整字切换",
+        })
+    }
+}
+
+/// Outline, drop shadow and glow drawn behind lyric text. Paragraphs have no
+/// stroke, so each effect is built from offset copies of the text.
+#[derive(Clone, Copy, Debug, Default, PartialEq)]
+pub struct TextEffects {
+    pub outline: Option<Outline>,
+    pub shadow: Option<Shadow>,
+    pub glow: Option<Glow>,
+}
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct Outline {
+    pub width: f32,
+    pub color: Color,
+}
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct Shadow {
+    pub offset: Vector,
+    pub blur: f32,
+    pub color: Color,
+}
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct Glow {
+    pub radius: f32,
+    pub color: Color,
+}
+
+impl TextEffects {
+    /// How far the effects reach outside the glyphs.
+    fn extent(&self) -> f32 {
+        let outline = self.outline.map_or(0.0, |outline| outline.width);
+        let shadow = self.shadow.map_or(0.0, |shadow| {
+            shadow.offset.x.abs().max(shadow.offset.y.abs()) + shadow.blur
+        });
+        let glow = self.glow.map_or(0.0, |glow| glow.radius);
+        outline.max(shadow).max(glow)
+    }
+
+    fn scale_alpha(self, factor: f32) -> Self {
+        Self {
+            outline: self.outline.map(|outline| Outline {
+                color: outline.color.scale_alpha(factor),
+                ..outline
+            }),
+            shadow: self.shadow.map(|shadow| Shadow {
+                color: shadow.color.scale_alpha(factor),
+                ..shadow
+            }),
+            glow: self.glow.map(|glow| Glow {
+                color: glow.color.scale_alpha(factor),
+                ..glow
+            }),
+        }
+    }
+
+    fn draw<Renderer>(
+        &self,
+        renderer: &mut Renderer,
+        paragraph: &Renderer::Paragraph,
+        position: Point,
+        viewport: Rectangle,
+    ) where
+        Renderer: text::Renderer,
+    {
+        let mut fill = |offset: Vector, color: Color| {
+            renderer.fill_paragraph(paragraph, position + offset, color, viewport);
+        };
+        if let Some(glow) = self.glow {
+            for (offset, weight) in soft_samples(glow.radius) {
+                fill(offset, glow.color.scale_alpha(0.35 * weight));
+            }
+        }
+        if let Some(shadow) = self.shadow {
+            if shadow.blur > 0.0 {
+                for (offset, weight) in soft_samples(shadow.blur) {
+                    fill(
+                        shadow.offset + offset,
+                        shadow.color.scale_alpha(0.5 * weight),
+                    );
+                }
+            } else {
+                fill(shadow.offset, shadow.color);
+            }
+        }
+        if let Some(outline) = self.outline {
+            // A second, inner ring fills the gaps between copies of thick strokes.
+            for radius in [outline.width, outline.width / 2.0] {
+                for offset in ring(radius, 16) {
+                    fill(offset, outline.color);
+                }
+            }
+        }
+    }
+}
+
+/// Evenly spaced offsets on a circle.
+fn ring(radius: f32, count: usize) -> impl Iterator<Item = Vector> {
+    (0..count).map(move |index| {
+        let angle = index as f32 * std::f32::consts::TAU / count as f32;
+        Vector::new(angle.cos() * radius, angle.sin() * radius)
+    })
+}
+
+/// Offsets filling a disc, weighted to fade towards the edge, so that stacked
+/// translucent copies approximate a blur.
+fn soft_samples(radius: f32) -> impl Iterator<Item = (Vector, f32)> {
+    const RINGS: usize = 3;
+    (1..=RINGS).flat_map(move |ring_index| {
+        let fraction = ring_index as f32 / RINGS as f32;
+        let weight = 1.0 - fraction * 0.75;
+        ring(radius * fraction, 8 * ring_index).map(move |offset| (offset, weight))
+    })
+}
+
+/// Lyric text with effects. Active-line words are highlighted up to
+/// `progress`; other lines stay at zero progress in their own color.
+pub struct KaraokeText<'a> {
+    content: &'a str,
+    format: widget::text::Format<Font>,
+    color: Color,
+    highlight: Color,
+    progress: f32,
+    mode: HighlightMode,
+    effects: TextEffects,
+    opacity: f32,
+    downward: bool,
+}
+
+impl<'a> KaraokeText<'a> {
+    pub fn new(content: &'a str, progress: f32, mode: HighlightMode) -> Self {
+        Self {
+            content,
+            format: widget::text::Format::default(),
+            color: Color::WHITE,
+            highlight: Color::WHITE,
+            progress: progress.clamp(0.0, 1.0),
+            mode,
+            effects: TextEffects::default(),
+            opacity: 1.0,
+            downward: false,
+        }
+    }
+
+    /// Text that is not highlighted, such as a candidate line.
+    pub fn plain(content: &'a str, color: Color) -> Self {
+        Self::new(content, 0.0, HighlightMode::Instant).colors(color, color)
+    }
+
+    pub fn font(mut self, font: Font) -> Self {
+        self.format.font = Some(font);
+        self
+    }
+
+    pub fn size(mut self, size: f32) -> Self {
+        self.format.size = Some(Pixels(size));
+        self
+    }
+
+    pub fn width(mut self, width: impl Into<Length>) -> Self {
+        self.format.width = width.into();
+        self
+    }
+
+    pub fn align_x(mut self, alignment: impl Into<text::Alignment>) -> Self {
+        self.format.align_x = alignment.into();
+        self
+    }
+
+    /// Unsung and sung colors.
+    pub fn colors(mut self, color: Color, highlight: Color) -> Self {
+        self.color = color;
+        self.highlight = highlight;
+        self
+    }
+
+    pub fn effects(mut self, effects: TextEffects) -> Self {
+        self.effects = effects;
+        self
+    }
+
+    /// Wipes from top to bottom instead of left to right, for a glyph in
+    /// vertical text.
+    pub fn downward(mut self) -> Self {
+        self.downward = true;
+        self
+    }
+
+    /// Fades the text and its effects, for line transitions.
+    pub fn opacity(mut self, opacity: f32) -> Self {
+        self.opacity = opacity.clamp(0.0, 1.0);
+        self
+    }
+}
+
+impl<Message, Theme, Renderer> Widget<Message, Theme, Renderer> for KaraokeText<'_>
+where
+    Renderer: text::Renderer<Font = Font>,
+{
+    fn tag(&self) -> tree::Tag {
+        tree::Tag::of::<paragraph::Plain<Renderer::Paragraph>>()
+    }
+
+    fn state(&self) -> tree::State {
+        tree::State::new(paragraph::Plain::<Renderer::Paragraph>::default())
+    }
+
+    fn size(&self) -> Size<Length> {
+        Size::new(self.format.width, self.format.height)
+    }
+
+    fn layout(
+        &mut self,
+        tree: &mut Tree,
+        renderer: &Renderer,
+        limits: &layout::Limits,
+    ) -> layout::Node {
+        widget::text::layout(
+            tree.state
+                .downcast_mut::<paragraph::Plain<Renderer::Paragraph>>(),
+            renderer,
+            limits,
+            self.content,
+            self.format,
+        )
+    }
+
+    fn draw(
+        &self,
+        tree: &Tree,
+        renderer: &mut Renderer,
+        _theme: &Theme,
+        _style: &renderer::Style,
+        layout: Layout<'_>,
+        _cursor: mouse::Cursor,
+        viewport: &Rectangle,
+    ) {
+        let paragraph = tree
+            .state
+            .downcast_ref::<paragraph::Plain<Renderer::Paragraph>>()
+            .raw();
+        let bounds = layout.bounds();
+        let position = bounds.anchor(
+            paragraph.min_bounds(),
+            paragraph.align_x(),
+            paragraph.align_y(),
+        );
+        if self.opacity <= 0.0 {
+            return;
+        }
+        let (color, highlight) = (
+            self.color.scale_alpha(self.opacity),
+            self.highlight.scale_alpha(self.opacity),
+        );
+        let effects = self.effects.scale_alpha(self.opacity);
+        effects.draw(renderer, paragraph, position, *viewport);
+
+        match self.mode {
+            HighlightMode::Wipe if self.progress > 0.0 && self.progress < 1.0 => {
+                // Clips reach past the text so the fill is never cut short.
+                let reach = bounds.expand(effects.extent() + 1.0);
+                let (sung, unsung) = if self.downward {
+                    let edge = position.y + paragraph.min_bounds().height * self.progress;
+                    (
+                        Rectangle {
+                            height: edge - reach.y,
+                            ..reach
+                        },
+                        Rectangle {
+                            y: edge,
+                            height: reach.y + reach.height - edge,
+                            ..reach
+                        },
+                    )
+                } else {
+                    let edge = position.x + paragraph.min_bounds().width * self.progress;
+                    (
+                        Rectangle {
+                            width: edge - reach.x,
+                            ..reach
+                        },
+                        Rectangle {
+                            x: edge,
+                            width: reach.x + reach.width - edge,
+                            ..reach
+                        },
+                    )
+                };
+                for (clip, color) in [(sung, highlight), (unsung, color)] {
+                    if let Some(clip) = clip.intersection(viewport) {
+                        renderer.with_layer(clip, |renderer| {
+                            renderer.fill_paragraph(paragraph, position, color, clip);
+                        });
+                    }
+                }
+            }
+            mode => {
+                let color = match mode {
+                    HighlightMode::Blend => blend(color, highlight, self.progress),
+                    _ if self.progress > 0.0 => highlight,
+                    _ => color,
+                };
+                renderer.fill_paragraph(paragraph, position, color, *viewport);
+            }
+        }
+    }
+}
+
+impl<'a, Message, Theme, Renderer> From<KaraokeText<'a>> for Element<'a, Message, Theme, Renderer>
+where
+    Renderer: text::Renderer<Font = Font> + 'a,
+{
+    fn from(text: KaraokeText<'a>) -> Self {
+        Element::new(text)
+    }
+}
+
+fn blend(from: Color, to: Color, progress: f32) -> Color {
+    Color {
+        r: from.r + (to.r - from.r) * progress,
+        g: from.g + (to.g - from.g) * progress,
+        b: from.b + (to.b - from.b) * progress,
+        a: from.a + (to.a - from.a) * progress,
+    }
+}
+
+/// Splits a line without word timing into pieces that can wrap: Latin words
+/// keep their trailing space, and CJK characters stand alone. Each piece gets
+/// its share of `progress` by character count, so the wipe moves through the
+/// line at an even pace.
+pub fn plain_segments(line: &str, progress: f32) -> Vec<(&str, f32)> {
+    let mut pieces = Vec::new();
+    let mut start = 0;
+    for (index, character) in line.char_indices() {
+        let end = index + character.len_utf8();
+        if is_cjk(character) {
+            if start < index {
+                pieces.push(&line[start..index]);
+            }
+            pieces.push(&line[index..end]);
+            start = end;
+        } else if character.is_whitespace() {
+            pieces.push(&line[start..end]);
+            start = end;
+        }
+    }
+    if start < line.len() {
+        pieces.push(&line[start..]);
+    }
+
+    let total = line.chars().count().max(1) as f32;
+    let sung = progress.clamp(0.0, 1.0) * total;
+    let mut before = 0.0;
+    pieces
+        .into_iter()
+        .map(|piece| {
+            let length = piece.chars().count() as f32;
+            let piece_progress = ((sung - before) / length).clamp(0.0, 1.0);
+            before += length;
+            (piece, piece_progress)
+        })
+        .collect()
+}
+
+/// Splits highlighted segments into single characters for vertical text,
+/// spreading each segment's progress over its characters. Whitespace is
+/// dropped and CJK punctuation uses its vertical presentation form.
+pub fn vertical_glyphs<'a>(segments: &[(&'a str, f32)]) -> Vec<(&'a str, f32)> {
+    let mut glyphs = Vec::new();
+    for (segment, progress) in segments {
+        let length = segment.chars().count() as f32;
+        let sung = progress.clamp(0.0, 1.0) * length;
+        for (position, (index, character)) in segment.char_indices().enumerate() {
+            if character.is_whitespace() {
+                continue;
+            }
+            let glyph =
+                vertical_form(character).unwrap_or(&segment[index..index + character.len_utf8()]);
+            glyphs.push((glyph, (sung - position as f32).clamp(0.0, 1.0)));
+        }
+    }
+    glyphs
+}
+
+fn vertical_form(character: char) -> Option<&'static str> {
+    Some(match character {
+        '，' => "︐",
+        '、' => "︑",
+        '。' => "︒",
+        '：' => "︓",
+        '；' => "︔",
+        '！' => "︕",
+        '？' => "︖",
+        '…' => "︙",
+        '—' => "︱",
+        '（' => "︵",
+        '）' => "︶",
+        '《' => "︽",
+        '》' => "︾",
+        '「' => "﹁",
+        '」' => "﹂",
+        _ => return None,
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn plain_lines_split_into_wrappable_pieces() {
+        assert_eq!(
+            plain_segments("把爱 Rock on", 0.5),
+            vec![
+                ("把", 1.0),
+                ("爱", 1.0),
+                (" ", 1.0),
+                ("Rock ", 0.4),
+                ("on", 0.0),
+            ]
+        );
+        assert_eq!(plain_segments("", 1.0), Vec::<(&str, f32)>::new());
+    }
+
+    #[test]
+    fn vertical_glyphs_spread_progress_downward() {
+        assert_eq!(
+            vertical_glyphs(&[("把爱", 0.75), ("留在，", 0.0), ("on", 1.0)]),
+            vec![
+                ("把", 1.0),
+                ("爱", 0.5),
+                ("留", 0.0),
+                ("在", 0.0),
+                ("︐", 0.0),
+                ("o", 1.0),
+                ("n", 1.0),
+            ]
+        );
+        assert_eq!(vertical_glyphs(&[(" ", 1.0)]), Vec::<(&str, f32)>::new());
+    }
+
+    #[test]
+    fn effects_extend_to_their_widest_part() {
+        let effects = TextEffects {
+            outline: Some(Outline {
+                width: 3.0,
+                color: Color::BLACK,
+            }),
+            shadow: Some(Shadow {
+                offset: Vector::new(2.0, 4.0),
+                blur: 1.5,
+                color: Color::BLACK,
+            }),
+            glow: None,
+        };
+        assert_eq!(effects.extent(), 5.5);
+        assert_eq!(TextEffects::default().extent(), 0.0);
+
+        let samples: Vec<_> = soft_samples(6.0).collect();
+        assert_eq!(samples.len(), 8 + 16 + 24);
+        assert!(
+            samples.iter().all(|(offset, weight)| {
+                (offset.x.hypot(offset.y) - 6.0) < 1e-4 && *weight > 0.0
+            })
+        );
+    }
+
+    #[test]
+    fn blend_interpolates_each_channel() {
+        let color = blend(Color::BLACK, Color::WHITE, 0.25);
+        assert_eq!(
+            (color.r, color.g, color.b, color.a),
+            (0.25, 0.25, 0.25, 1.0)
+        );
+    }
+}
@@ -0,0 +1,3311 @@
+//! The KG Capture controller and lyric window. [`run`] starts the GUI, and
+//! [`render`] and [`video`] draw the lyric window headlessly, for the
+//! `kg-capture-render` command and any other caller.
+
+mod clock;
+mod connection;
+mod export;
+#[cfg(test)]
+mod fixtures;
+mod karaoke;
+mod now_singing;
+mod obs;
+mod offset;
+mod overlay;
+mod practice;
+mod preset;
+mod recording;
+pub mod render;
+mod settings;
+mod source;
+#[cfg(windows)]
+mod system_fonts;
+mod transition;
+pub mod video;
+mod websocket;
+
+pub use karaoke::HighlightMode;
+pub use settings::AppearanceSettings;
+pub use transition::{Easing, LineTransition};
+
+use std::cell::RefCell;
+use std::ops::RangeInclusive;
+use std::path::PathBuf;
+use std::sync::Arc;
+use std::time::{Duration, Instant};
+
+use clock::PlaybackClock;
+use connection::Session;
+use export::ExportFormat;
+use iced::futures::SinkExt;
+use iced::task;
+use iced::theme::Palette;
+use iced::widget::{
+    Column, Row, button, checkbox, column, container, image, pick_list, responsive, row,
+    scrollable, slider, space, stack, text, text_input,
+};
+use iced::{
+    Color, ContentFit, Element, Fill, Font, Point, Size, Subscription, Task, Theme, keyboard,
+    window,
+};
+use karaoke::{Glow, KaraokeText, Outline, Shadow, TextEffects};
+use kg_capture_lyrics::style::{Alignment, LyricsStyle, Rgb};
+use kg_capture_protocol::{
+    HookEvent, HostCommand, LyricLine, LyricTimeline, PlaybackPosition, PlaybackState,
+};
+use now_singing::{TextFile, TextFileSettings, TextFileWriter};
+use obs::{LyricTexts, ObsClient, ObsSettings, ObsStatus};
+use offset::{OFFSET_STEP_MS, TimingOffsets};
+use practice::{
+    MAX_PRACTICE_SPEED, MIN_PRACTICE_SPEED, PRACTICE_EXTENSIONS, PracticeClock, PracticeSource,
+};
+use preset::{AppearancePreset, PRESET_EXTENSION};
+use recording::{MAX_REPLAY_SPEED, MIN_REPLAY_SPEED, Recording, ReplaySource};
+use serde::{Deserialize, Serialize};
+use settings::{LoadError, Settings};
+use source::{EventSource, FixtureSource};
+use transition::{LineTracker, ScrollIn, Transformed, TransitionFrame};
+use websocket::{LyricsServer, WebSocketSettings};
+
+/// Logs to stderr, so a video streamed to stdout stays intact.
+pub fn init_tracing() {
+    tracing_subscriber::fmt()
+        .with_writer(std::io::stderr)
+        .with_env_filter(
+            tracing_subscriber::EnvFilter::try_from_default_env()
+                .unwrap_or_else(|_| "kg_capture=info".into()),
+        )
+        .init();
+}
+
+/// Opens the control and lyric windows and runs until both are closed.
+pub fn run() -> iced::Result {
+    iced::daemon(App::new, App::update, App::view)
+        .title(App::title)
+        .theme(App::theme)
+        .subscription(App::subscription)
+        .run()
+}
+
+#[derive(Debug, Clone)]
+enum Message {
+    BrowseExecutable,
+    ExecutableSelected(Option<std::path::PathBuf>),
+    ExecutablePathChanged(String),
+    Launch,
+    StartFixture,
+    Connected(Result<Arc<dyn EventSource>, String>),
+    HookEvent(Result<HookEvent, String>),
+    SourceFinished,
+    Disconnect,
+    OpenRecording,
+    RecordingSelected(Option<std::path::PathBuf>),
+    ReplaySpeedChanged(f32),
+    OpenPractice,
+    PracticeFileSelected(Option<std::path::PathBuf>),
+    PracticeToggled,
+    PracticeSeeked(f32),
+    PracticeSpeedChanged(f32),
+    PresetSelected(String),
+    PresetNameChanged(String),
+    SavePreset,
+    DeletePreset,
+    ExportPreset,
+    PresetExportSelected(Option<std::path::PathBuf>),
+    ImportPreset,
+    PresetImportSelected(Option<std::path::PathBuf>),
+    GlobalOffsetAdjusted(i32),
+    SongOffsetAdjusted(i32),
+    SongOffsetReset,
+    ExportFormatChanged(ExportFormat),
+    ExportLyrics,
+    LyricsExportSelected(Option<std::path::PathBuf>),
+    ShowLyricsWindow,
+    WebSocketToggled(bool),
+    WebSocketPortChanged(String),
+    WebSocketPortSubmitted,
+    WebSocketRemoteToggled(bool),
+    ObsToggled(bool),
+    ObsUrlChanged(String),
+    ObsPasswordChanged(String),
+    ObsReconnect,
+    ObsStatusPolled,
+    ObsPreviousSourceChanged(String),
+    ObsCurrentSourceChanged(String),
+    ObsNextSourceChanged(String),
+    TextFilePathChanged(TextFile, String),
+    TextFilePathSubmitted(TextFile),
+    BrowseTextFile(TextFile),
+    TextFileSelected(TextFile, Option<std::path::PathBuf>),
+    BackgroundModeChanged(BackgroundMode),
+    BackgroundColorChanged(String),
+    ChromaKeyColorChanged(String),
+    TextColorChanged(String),
+    HighlightColorChanged(String),
+    LyricsFontChanged(LyricsFont),
+    LyricsLayoutChanged(LyricsLayout),
+    LyricsAlignmentChanged(LyricsAlignment),
+    HighlightModeChanged(HighlightMode),
+    OutlineWidthChanged(f32),
+    OutlineColorChanged(String),
+    ShadowOffsetChanged(f32),
+    ShadowBlurChanged(f32),
+    ShadowColorChanged(String),
+    GlowRadiusChanged(f32),
+    GlowColorChanged(String),
+    LineTransitionChanged(LineTransition),
+    TransitionDurationChanged(f32),
+    EasingChanged(Easing),
+    ActiveFontSizeChanged(f32),
+    CandidateFontSizeChanged(f32),
+    ShowPreviousLineChanged(bool),
+    CandidateLineCountChanged(f32),
+    WindowCloseRequested(window::Id),
+    Frame(Instant),
+    SaveSettings,
+}
+
+impl Message {
+    /// Whether handling this message may change the lyric window's
+    /// appearance or the timing offset, which browser overlays follow.
+    fn changes_lyrics_style(&self) -> bool {
+        matches!(
+            self,
+            Self::PresetSelected(_)
+                | Self::PresetImportSelected(_)
+                | Self::GlobalOffsetAdjusted(_)
+                | Self::SongOffsetAdjusted(_)
+                | Self::SongOffsetReset
+                | Self::BackgroundModeChanged(_)
+                | Self::BackgroundColorChanged(_)
+                | Self::ChromaKeyColorChanged(_)
+                | Self::TextColorChanged(_)
+                | Self::HighlightColorChanged(_)
+                | Self::LyricsFontChanged(_)
+                | Self::LyricsLayoutChanged(_)
+                | Self::LyricsAlignmentChanged(_)
+                | Self::HighlightModeChanged(_)
+                | Self::OutlineWidthChanged(_)
+                | Self::OutlineColorChanged(_)
+                | Self::ShadowOffsetChanged(_)
+                | Self::ShadowBlurChanged(_)
+                | Self::ShadowColorChanged(_)
+                | Self::GlowRadiusChanged(_)
+                | Self::GlowColorChanged(_)
+                | Self::LineTransitionChanged(_)
+                | Self::TransitionDurationChanged(_)
+                | Self::EasingChanged(_)
+                | Self::ActiveFontSizeChanged(_)
+                | Self::CandidateFontSizeChanged(_)
+                | Self::ShowPreviousLineChanged(_)
+                | Self::CandidateLineCountChanged(_)
+        )
+    }
+}
+
+#[derive(Debug, Clone, Copy, Eq, PartialEq)]
+enum ConnectionState {
+    Disconnected,
+    Connecting,
+    Connected,
+    Streaming,
+    Failed,
+}
+
+#[derive(Debug, Clone, Eq, PartialEq)]
+enum LyricsFont {
+    System,
+    Named {
+        family_name: Arc<str>,
+        display_name: Arc<str>,
+        font: Font,
+    },
+}
+
+impl LyricsFont {
+    /// A font family by name. iced names fonts by `&'static str`, so the
+    /// family name is leaked here, once per installed family or loaded
+    /// appearance rather than once per frame.
+    fn named(family_name: String, display_name: String) -> Self {
+        let font = Font::with_name(Box::leak(family_name.clone().into_boxed_str()));
+        Self::Named {
+            family_name: family_name.into(),
+            display_name: display_name.into(),
+            font,
+        }
+    }
+
+    fn font(&self) -> Font {
+        match self {
+            Self::System => Font::DEFAULT,
+            Self::Named { font, .. } => *font,
+        }
+    }
+}
+
+impl std::fmt::Display for LyricsFont {
+    fn fmt(&self, formatter: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        formatter.write_str(match self {
+            Self::System => "系统默认",
+            Self::Named { display_name, .. } => display_name,
+        })
+    }
+}
+
+#[derive(Debug, Clone, Copy, Eq, PartialEq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum LyricsAlignment {
+    Left,
+    Center,
+    Right,
+}
+
+impl LyricsAlignment {
+    const ALL: [Self; 3] = [Self::Left, Self::Center, Self::Right];
+
+    fn horizontal(self) -> iced::alignment::Horizontal {
+        match self {
+            Self::Left => iced::alignment::Horizontal::Left,
+            Self::Center => iced::alignment::Horizontal::Center,
+            Self::Right => iced::alignment::Horizontal::Right,
+        }
+    }
+
+    /// Where columns of vertical text start: left alignment reads as top.
+    fn vertical(self) -> iced::alignment::Vertical {
+        match self {
+            Self::Left => iced::alignment::Vertical::Top,
+            Self::Center => iced::alignment::Vertical::Center,
+            Self::Right => iced::alignment::Vertical::Bottom,
+        }
+    }
+}
+
+impl From<LyricsAlignment> for Alignment {
+    fn from(alignment: LyricsAlignment) -> Self {
+        match alignment {
+            LyricsAlignment::Left => Self::Left,
+            LyricsAlignment::Center => Self::Center,
+            LyricsAlignment::Right => Self::Right,
+        }
+    }
+}
+
+impl std::fmt::Display for LyricsAlignment {
+    fn fmt(&self, formatter: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        formatter.write_str(match self {
+            Self::Left => "左对齐",
+            Self::Center => "居中",
+            Self::Right => "右对齐",
+        })
+    }
+}
+
+/// How lines are arranged in the lyric window.
+#[derive(Debug, Clone, Copy, Default, Eq, PartialEq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum LyricsLayout {
+    /// Previous line, active line and candidates in one column.
+    #[default]
+    Stacked,
+    /// KTV style: even lines on a left-aligned top row, odd lines on a
+    /// right-aligned bottom row, each replaced by the next line once sung.
+    TwoLine,
+    /// Vertical writing: each line is a column read downward and columns run
+    /// from right to left.
+    Vertical,
+}
+
+impl LyricsLayout {
+    const ALL: [Self; 3] = [Self::Stacked, Self::TwoLine, Self::Vertical];
+}
+
+impl std::fmt::Display for LyricsLayout {
+    fn fmt(&self, formatter: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        formatter.write_str(match self {
+            Self::Stacked => "堆叠",
+            Self::TwoLine => "双行交替",
+            Self::Vertical => "竖排",
+        })
+    }
+}
+
+/// What the lyric window paints behind the text. OBS captures a transparent
+/// window with its alpha channel; a chroma key suits captures that cannot.
+#[derive(Debug, Clone, Copy, Default, Eq, PartialEq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum BackgroundMode {
+    #[default]
+    Solid,
+    Transparent,
+    ChromaKey,
+}
+
+impl BackgroundMode {
+    const ALL: [Self; 3] = [Self::Solid, Self::Transparent, Self::ChromaKey];
+}
+
+impl std::fmt::Display for BackgroundMode {
+    fn fmt(&self, formatter: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        formatter.write_str(match self {
+            Self::Solid => "纯色",
+            Self::Transparent => "透明",
+            Self::ChromaKey => "色键",
+        })
+    }
+}
+
+#[cfg(windows)]
+fn available_lyrics_fonts() -> Vec<LyricsFont> {
+    let mut fonts = vec![LyricsFont::System];
+    match system_fonts::families() {
+        Ok(names) => fonts.extend(
+            names
+                .into_iter()
+                .map(|font| LyricsFont::named(font.family_name, font.display_name)),
+        ),
+        Err(error) => tracing::warn!(%error, "failed to enumerate DirectWrite font families"),
+    }
+    fonts
+}
+
+#[cfg(not(windows))]
+fn available_lyrics_fonts() -> Vec<LyricsFont> {
+    vec![LyricsFont::System]
+}
+
+/// The installed font with this family name, or the system font when it is
+/// not installed.
+fn lyrics_font(fonts: &[LyricsFont], family: Option<&str>) -> LyricsFont {
+    fonts
+        .iter()
+        .find(|font| {
+            matches!(
+                font,
+                LyricsFont::Named { family_name, .. } if Some(&**family_name) == family
+            )
+        })
+        .cloned()
+        .unwrap_or(LyricsFont::System)
+}
+
+const ACTIVE_FONT_SIZES: RangeInclusive<f32> = 20.0..=96.0;
+const CANDIDATE_FONT_SIZES: RangeInclusive<f32> = 12.0..=72.0;
+const MAX_CANDIDATE_LINES: usize = 10;
+const OUTLINE_WIDTHS: RangeInclusive<f32> = 0.0..=8.0;
+const SHADOW_OFFSETS: RangeInclusive<f32> = 0.0..=12.0;
+const SHADOW_BLURS: RangeInclusive<f32> = 0.0..=12.0;
+const GLOW_RADII: RangeInclusive<f32> = 0.0..=16.0;
+const TRANSITION_DURATIONS: RangeInclusive<f32> = 0.0..=1_000.0;
+const LINE_SPACING: f32 = 14.0;
+/// Outline forced in chroma-key mode, so the glyphs keep smooth edges inside
+/// the hard-edged matte around them.
+const KEYED_OUTLINE_WIDTH: f32 = 2.0;
+/// Settings are written once changes have settled for this long, so dragging
+/// a slider or typing a path does not rewrite the file at every step.
+const SETTINGS_SAVE_DELAY: Duration = Duration::from_millis(500);
+const SETTINGS_SAVE_POLL_INTERVAL: Duration = Duration::from_millis(100);
+const OBS_STATUS_POLL_INTERVAL: Duration = Duration::from_millis(250);
+
+pub struct LyricsAppearance {
+    background_mode: BackgroundMode,
+    background_input: String,
+    background: Color,
+    chroma_key_input: String,
+    chroma_key: Color,
+    text_input: String,
+    text: Color,
+    highlight_input: String,
+    highlight: Color,
+    font: LyricsFont,
+    layout: LyricsLayout,
+    alignment: LyricsAlignment,
+    highlight_mode: HighlightMode,
+    active_font_size: f32,
+    candidate_font_size: f32,
+    show_previous_line: bool,
+    candidate_line_count: usize,
+    outline_width: f32,
+    outline_input: String,
+    outline: Color,
+    shadow_offset: f32,
+    shadow_blur: f32,
+    shadow_input: String,
+    shadow: Color,
+    glow_radius: f32,
+    glow_input: String,
+    glow: Color,
+    transition: LineTransition,
+    transition_ms: f32,
+    easing: Easing,
+}
+
+impl LyricsAppearance {
+    /// Applies saved appearance, falling back to the default for any color
+    /// that does not parse and clamping sizes to the control ranges.
+    fn from_settings(settings: &AppearanceSettings, fonts: &[LyricsFont]) -> Self {
+        let defaults = AppearanceSettings::default();
+        let (background_input, background) =
+            setting_color(&settings.background, &defaults.background);
+        let (chroma_key_input, chroma_key) =
+            setting_color(&settings.chroma_key_color, &defaults.chroma_key_color);
+        let (text_input, text) = setting_color(&settings.text, &defaults.text);
+        let (highlight_input, highlight) = setting_color(&settings.highlight, &defaults.highlight);
+        let (outline_input, outline) =
+            setting_color(&settings.outline_color, &defaults.outline_color);
+        let (shadow_input, shadow) = setting_color(&settings.shadow_color, &defaults.shadow_color);
+        let (glow_input, glow) = setting_color(&settings.glow_color, &defaults.glow_color);
+        Self {
+            background_mode: settings.background_mode,
+            background_input,
+            background,
+            chroma_key_input,
+            chroma_key,
+            text_input,
+            text,
+            highlight_input,
+            highlight,
+            font: lyrics_font(fonts, settings.font_family.as_deref()),
+            layout: settings.layout,
+            alignment: settings.alignment,
+            highlight_mode: settings.highlight_mode,
+            active_font_size: settings
+                .active_font_size
+                .clamp(*ACTIVE_FONT_SIZES.start(), *ACTIVE_FONT_SIZES.end()),
+            candidate_font_size: settings
+                .candidate_font_size
+                .clamp(*CANDIDATE_FONT_SIZES.start(), *CANDIDATE_FONT_SIZES.end()),
+            show_previous_line: settings.show_previous_line,
+            candidate_line_count: settings.candidate_line_count.min(MAX_CANDIDATE_LINES),
+            outline_width: clamp_to(settings.outline_width, &OUTLINE_WIDTHS),
+            outline_input,
+            outline,
+            shadow_offset: clamp_to(settings.shadow_offset, &SHADOW_OFFSETS),
+            shadow_blur: clamp_to(settings.shadow_blur, &SHADOW_BLURS),
+            shadow_input,
+            shadow,
+            glow_radius: clamp_to(settings.glow_radius, &GLOW_RADII),
+            glow_input,
+            glow,
+            transition: settings.transition,
+            transition_ms: clamp_to(settings.transition_ms, &TRANSITION_DURATIONS),
+            easing: settings.easing,
+        }
+    }
+
+    fn settings(&self) -> AppearanceSettings {
+        AppearanceSettings {
+            background_mode: self.background_mode,
+            background: hex_color(self.background),
+            chroma_key_color: hex_color(self.chroma_key),
+            text: hex_color(self.text),
+            highlight: hex_color(self.highlight),
+            font_family: match &self.font {
+                LyricsFont::System => None,
+                LyricsFont::Named { family_name, .. } => Some(family_name.to_string()),
+            },
+            layout: self.layout,
+            alignment: self.alignment,
+            highlight_mode: self.highlight_mode,
+            active_font_size: self.active_font_size,
+            candidate_font_size: self.candidate_font_size,
+            show_previous_line: self.show_previous_line,
+            candidate_line_count: self.candidate_line_count,
+            outline_width: self.outline_width,
+            outline_color: hex_color(self.outline),
+            shadow_offset: self.shadow_offset,
+            shadow_blur: self.shadow_blur,
+            shadow_color: hex_color(self.shadow),
+            glow_radius: self.glow_radius,
+            glow_color: hex_color(self.glow),
+            transition: self.transition,
+            transition_ms: self.transition_ms,
+            easing: self.easing,
+        }
+    }
+
+    /// Against a chroma key every translucent pixel is tinted by the key and
+    /// survives keying as a colored fringe, so the soft effects are left out.
+    /// An opaque outline is always drawn as a matte: the glyphs' anti-aliased
+    /// edges then blend into the outline instead of the key, and only the
+    /// outline's own outer edge touches the key.
+    fn effects(&self) -> TextEffects {
+        let keyed = self.background_mode == BackgroundMode::ChromaKey;
+        let shadow_blur = if keyed { 0.0 } else { self.shadow_blur };
+        let outline_width = if keyed {
+            self.outline_width.max(KEYED_OUTLINE_WIDTH)
+        } else {
+            self.outline_width
+        };
+        TextEffects {
+            outline: (outline_width > 0.0).then_some(Outline {
+                width: outline_width,
+                color: Color {
+                    a: 1.0,
+                    ..self.outline
+                },
+            }),
+            shadow: (self.shadow_offset > 0.0 || shadow_blur > 0.0).then_some(Shadow {
+                offset: iced::Vector::new(self.shadow_offset, self.shadow_offset),
+                blur: shadow_blur,
+                color: self.shadow,
+            }),
+            glow: (self.glow_radius > 0.0 && !keyed).then_some(Glow {
+                radius: self.glow_radius,
+                color: self.glow,
+            }),
+        }
+    }
+
+    /// The transition the lyric window plays. A chroma key gets no fading
+    /// transitions, as the two-line layout's always are: the matte would
+    /// make the fading text pop in and out at half opacity.
+    fn line_transition(&self) -> LineTransition {
+        let fades = matches!(
+            self.transition,
+            LineTransition::Crossfade | LineTransition::ScaleIn | LineTransition::Fade
+        ) || self.layout == LyricsLayout::TwoLine;
+        if fades && self.background_mode == BackgroundMode::ChromaKey {
+            LineTransition::Off
+        } else {
+            self.transition
+        }
+    }
+
+    fn transition_duration(&self) -> Duration {
+        match self.line_transition() {
+            LineTransition::Off => Duration::ZERO,
+            _ => Duration::from_secs_f32(self.transition_ms / 1_000.0),
+        }
+    }
+
+    fn window_background(&self) -> Color {
+        match self.background_mode {
+            BackgroundMode::Solid => self.background,
+            BackgroundMode::Transparent => Color::TRANSPARENT,
+            BackgroundMode::ChromaKey => self.chroma_key,
+        }
+    }
+
+    fn style(&self) -> LyricsStyle {
+        LyricsStyle {
+            font_family: match &self.font {
+                LyricsFont::System => None,
+                LyricsFont::Named { family_name, .. } => Some(family_name.to_string()),
+            },
+            active_font_size: self.active_font_size,
+            candidate_font_size: self.candidate_font_size,
+            background: rgb(self.background),
+            text: rgb(self.text),
+            highlight: rgb(self.highlight),
+            alignment: self.alignment.into(),
+        }
+    }
+}
+
+struct App {
+    control_window: window::Id,
+    lyrics_window: Option<window::Id>,
+    connection: ConnectionState,
+    detail: String,
+    source: Option<Arc<dyn EventSource>>,
+    events: Option<task::Handle>,
+    replay_speed: f32,
+    practice: Option<Arc<PracticeClock>>,
+    practice_speed: f32,
+    export_format: ExportFormat,
+    timeline: Option<LyricTimeline>,
+    song_key: Option<u64>,
+    timing_offsets: TimingOffsets,
+    playback: Option<PlaybackPosition>,
+    playback_state: Option<PlaybackState>,
+    clock: PlaybackClock,
+    line_tracker: LineTracker,
+    executable_path: String,
+    available_fonts: Vec<LyricsFont>,
+    lyrics_appearance: LyricsAppearance,
+    lyrics_theme: Theme,
+    presets: Vec<AppearancePreset>,
+    selected_preset: Option<String>,
+    websocket: WebSocketSettings,
+    websocket_port_input: String,
+    lyrics_server: Option<LyricsServer>,
+    obs: ObsSettings,
+    obs_client: Option<ObsClient>,
+    obs_sources: Vec<(String, String)>,
+    text_files: TextFileSettings,
+    /// Paths as typed; each one is applied when Enter is pressed.
+    text_file_inputs: TextFileSettings,
+    text_file_writer: Option<TextFileWriter>,
+    /// Song and line the text files were last written for, `Some(None)` once
+    /// they were emptied; `None` forces the next write.
+    text_files_written: Option<Option<(u64, Option<usize>)>>,
+    preset_name: String,
+    /// Draws the lyric window in chroma-key mode.
+    keyed_renderer: RefCell<Option<render::FrameRenderer>>,
+    settings_path: Option<PathBuf>,
+    saved_settings: Settings,
+    /// Persisted state that differs from the saved file, and when it last
+    /// changed.
+    pending_settings: Option<(Settings, Instant)>,
+}
+
+impl App {
+    fn new() -> (Self, Task<Message>) {
+        let settings_path = settings::settings_path();
+        let loaded = settings_path.as_deref().map(settings::load).transpose();
+        let settings = match &loaded {
+            Ok(settings) => settings.clone().flatten().unwrap_or_default(),
+            Err(_) => Settings::default(),
+        };
+        let (mut app, task) = Self::with_settings(settings_path, settings);
+        let Err(error) = loaded else {
+            return (app, task);
+        };
+        tracing::warn!(%error, "failed to load settings");
+        app.detail = match (&error, &app.settings_path) {
+            (LoadError::Invalid(_), Some(path)) => match settings::set_aside(path) {
+                Ok(backup) => format!(
+                    "设置无法读取，已恢复默认值；原文件已保存为 {}。",
+                    backup.display()
+                ),
+                Err(_) => {
+                    app.settings_path = None;
+                    format!("设置无法读取，已恢复默认值，本次修改不会保存：{error}")
+                }
+            },
+            // The file may be readable next time or belong to a newer
+            // version, so it is left untouched and not saved over.
+            _ => {
+                app.settings_path = None;
+                format!("设置无法读取，已恢复默认值，本次修改不会保存：{error}")
+            }
+        };
+        (app, task)
+    }
+
+    fn with_settings(settings_path: Option<PathBuf>, settings: Settings) -> (Self, Task<Message>) {
+        let available_fonts = available_lyrics_fonts();
+        let lyrics_appearance =
+            LyricsAppearance::from_settings(&settings.appearance, &available_fonts);
+        let (control_window, open_control_window) = window::open(window::Settings {
+            size: Size::new(720.0, 680.0),
+            min_size: Some(Size::new(640.0, 620.0)),
+            position: window::Position::Specific(Point::new(40.0, 40.0)),
+            exit_on_close_request: false,
+            ..window::Settings::default()
+        });
+        let (lyrics_window, open_lyrics_task) = open_lyrics_window();
+
+        let mut app = Self {
+            control_window,
+            lyrics_window: Some(lyrics_window),
+            connection: ConnectionState::Disconnected,
+            detail: "选择 WeSing.exe；程序将以子进程方式启动并读取歌词。".into(),
+            source: None,
+            events: None,
+            replay_speed: settings
+                .replay_speed
+                .clamp(MIN_REPLAY_SPEED, MAX_REPLAY_SPEED),
+            practice: None,
+            practice_speed: settings
+                .practice_speed
+                .clamp(MIN_PRACTICE_SPEED, MAX_PRACTICE_SPEED),
+            export_format: settings.export_format,
+            timeline: None,
+            song_key: None,
+            timing_offsets: settings.timing_offsets.clamped(),
+            playback: None,
+            playback_state: None,
+            clock: PlaybackClock::default(),
+            line_tracker: LineTracker::default(),
+            executable_path: settings.executable_path.clone(),
+            available_fonts,
+            lyrics_appearance,
+            lyrics_theme: lyrics_theme(),
+            presets: settings.presets.clone(),
+            selected_preset: None,
+            websocket: settings.websocket.clone(),
+            websocket_port_input: settings.websocket.port.to_string(),
+            lyrics_server: None,
+            obs: settings.obs.clone(),
+            obs_client: None,
+            obs_sources: Vec::new(),
+            text_files: settings.text_files.clone(),
+            text_file_inputs: settings.text_files.clone(),
+            text_file_writer: None,
+            text_files_written: None,
+            preset_name: String::new(),
+            keyed_renderer: RefCell::new(None),
+            settings_path,
+            saved_settings: settings,
+            pending_settings: None,
+        };
+        // The loaded file counts as saved once validated, so a value adjusted
+        // here, such as a font that is not installed, is not written back
+        // until something else changes.
+        app.saved_settings = app.settings();
+        app.apply_websocket_settings();
+        app.apply_obs_settings();
+        match TextFileWriter::start() {
+            Ok(writer) => app.text_file_writer = Some(writer),
+            Err(error) => app.detail = format!("文本文件写入启动失败：{error}"),
+        }
+        (
+            app,
+            Task::batch([open_control_window.discard(), open_lyrics_task.discard()]),
+        )
+    }
+
+    fn update(&mut self, message: Message) -> Task<Message> {
+        let changes_lyrics_style = message.changes_lyrics_style();
+        let task = self.handle_message(message);
+        if changes_lyrics_style {
+            self.publish_overlay_style();
+        }
+        self.update_obs_sources();
+        self.update_text_files();
+        self.track_settings();
+        task
+    }
+
+    /// Keeps browser overlays in step with the lyric window's look and the
+    /// timing offset after a style change. Unchanged values are not sent
+    /// again.
+    fn publish_overlay_style(&self) {
+        if let Some(server) = &self.lyrics_server {
+            server.publish_appearance(&self.lyrics_appearance.settings());
+            server.publish_timing_offset(self.timing_offsets.total_ms(self.song_key));
+        }
+    }
+
+    fn settings(&self) -> Settings {
+        Settings {
+            executable_path: self.executable_path.clone(),
+            replay_speed: self.replay_speed,
+            practice_speed: self.practice_speed,
+            export_format: self.export_format,
+            timing_offsets: self.timing_offsets.clone(),
+            appearance: self.lyrics_appearance.settings(),
+            presets: self.presets.clone(),
+            websocket: self.websocket.clone(),
+            obs: ObsSettings {
+                password: String::new(),
+                ..self.obs.clone()
+            },
+            text_files: self.text_files.clone(),
+            ..Settings::default()
+        }
+    }
+
+    /// Notes a change to persisted state, which restarts the save delay.
+    fn track_settings(&mut self) {
+        let settings = self.settings();
+        let last = match &self.pending_settings {
+            Some((pending, _)) => pending,
+            None => &self.saved_settings,
+        };
+        if settings != *last {
+            self.pending_settings = Some((settings, Instant::now()));
+        }
+    }
+
+    /// Writes the settings file if persisted state changed since the last
+    /// save.
+    fn save_settings(&mut self) {
+        let Some((settings, _)) = self.pending_settings.take() else {
+            return;
+        };
+        if settings == self.saved_settings {
+            return;
+        }
+        if let Some(path) = &self.settings_path
+            && let Err(error) = settings::save(path, &settings)
+        {
+            tracing::warn!(%error, "failed to save settings");
+        }
+        self.saved_settings = settings;
+    }
+
+    /// Starts, restarts or stops the WebSocket server to match its settings.
+    /// A new server is sent the current song, so open overlays catch up.
+    fn apply_websocket_settings(&mut self) {
+        self.lyrics_server = None;
+        if !self.websocket.enabled {
+            return;
+        }
+        match LyricsServer::start(self.websocket.address()) {
+            Ok(server) => {
+                server.publish_appearance(&self.lyrics_appearance.settings());
+                server.publish_timing_offset(self.timing_offsets.total_ms(self.song_key));
+                if let Some(timeline) = &self.timeline {
+                    server.publish_timeline(timeline);
+                }
+                if let Some(playback) = &self.playback {
+                    server.publish_playback(playback);
+                }
+                if let (Some(timeline), Some(state)) = (&self.timeline, self.playback_state) {
+                    server.publish_playback_state(timeline.id, state);
+                }
+                self.lyrics_server = Some(server);
+            }
+            Err(error) => {
+                tracing::warn!(%error, "failed to start the WebSocket server");
+                self.detail = format!("WebSocket 服务启动失败：{error}");
+            }
+        }
+    }
+
+    /// Connects to OBS, or disconnects, to match the OBS settings.
+    fn apply_obs_settings(&mut self) {
+        self.obs_client = None;
+        self.obs_sources.clear();
+        if !self.obs.enabled {
+            return;
+        }
+        match ObsClient::start(self.obs.url.trim().to_owned(), self.obs.password.clone()) {
+            Ok(client) => self.obs_client = Some(client),
+            Err(error) => self.detail = format!("OBS 连接启动失败：{error}"),
+        }
+    }
+
+    /// Sends the lines around the shown one to the OBS text sources when
+    /// they change.
+    fn update_obs_sources(&mut self) {
+        let Some(client) = &self.obs_client else {
+            return;
+        };
+        let texts = self
+            .timeline
+            .as_ref()
+            .map(|timeline| LyricTexts::at(timeline, self.shown_line()))
+            .unwrap_or_default();
+        let sources = self.obs.source_texts(&texts);
+        if sources != self.obs_sources {
+            client.update(sources.clone());
+            self.obs_sources = sources;
+        }
+    }
+
+    /// Rewrites the "now singing" text files when the shown line changes, and
+    /// empties them when the song is gone.
+    fn update_text_files(&mut self) {
+        let Some(writer) = &self.text_file_writer else {
+            return;
+        };
+        let line = self.shown_line();
+        let written = Some(self.timeline.as_ref().map(|timeline| (timeline.id, line)));
+        if written == self.text_files_written {
+            return;
+        }
+        self.text_files_written = written;
+        let files = now_singing::file_contents(&self.text_files, self.timeline.as_ref(), line);
+        if !files.is_empty() {
+            writer.write(files);
+        }
+    }
+
+    fn handle_message(&mut self, message: Message) -> Task<Message> {
+        match message {
+            Message::BrowseExecutable => Task::perform(
+                async {
+                    rfd::FileDialog::new()
+                        .set_title("Locate the WeSing executable")
+                        .add_filter("Windows executable", &["exe"])
+                        .pick_file()
+                },
+                Message::ExecutableSelected,
+            ),
+            Message::ExecutableSelected(path) => {
+                if let Some(path) = path {
+                    self.executable_path = path.to_string_lossy().into_owned();
+                    self.detail = "已选择目标程序，可以启动。".into();
+                }
+                Task::none()
+            }
+            Message::ExecutablePathChanged(path) => {
+                self.executable_path = path;
+                Task::none()
+            }
+            Message::Launch => {
+                self.connection = ConnectionState::Connecting;
+                self.detail = "正在启动 WeSing 并初始化歌词同步…".into();
+                let executable = std::path::PathBuf::from(self.executable_path.clone());
+                Task::perform(
+                    async move {
+                        Session::connect(executable)
+                            .map(|session| Arc::new(session) as Arc<dyn EventSource>)
+                    },
+                    Message::Connected,
+                )
+            }
+            Message::StartFixture => {
+                self.connection = ConnectionState::Connecting;
+                Task::done(Message::Connected(Ok(Arc::new(FixtureSource::new()))))
+            }
+            Message::Connected(Ok(source)) => match source.send(HostCommand::StartCapture) {
+                Ok(()) => {
+                    self.connection = ConnectionState::Connected;
+                    self.detail = source.describe();
+                    self.clear_song();
+                    self.source = Some(source.clone());
+                    let (events, handle) = Task::run(event_stream(source), Message::HookEvent)
+                        .chain(Task::done(Message::SourceFinished))
+                        .abortable();
+                    self.events = Some(handle.abort_on_drop());
+                    events
+                }
+                Err(error) => {
+                    self.connection = ConnectionState::Failed;
+                    self.detail = error;
+                    Task::none()
+                }
+            },
+            Message::Connected(Err(error)) => {
+                self.connection = ConnectionState::Failed;
+                self.detail = error;
+                Task::none()
+            }
+            Message::SourceFinished => {
+                if let Some(source) = self.source.take() {
+                    self.events = None;
+                    self.practice = None;
+                    self.connection = ConnectionState::Disconnected;
+                    self.detail = source.finished_detail();
+                }
+                Task::none()
+            }
+            Message::Disconnect => {
+                self.send(HostCommand::Shutdown);
+                self.source = None;
+                self.events = None;
+                self.practice = None;
+                self.connection = ConnectionState::Disconnected;
+                self.detail = "已断开连接。".into();
+                self.clear_song();
+                Task::none()
+            }
+            Message::OpenRecording => Task::perform(
+                async {
+                    rfd::FileDialog::new()
+                        .set_title("Open a KG Capture recording")
+                        .add_filter("KG Capture recording", &["jsonl"])
+                        .pick_file()
+                },
+                Message::RecordingSelected,
+            ),
+            Message::RecordingSelected(None) => Task::none(),
+            Message::RecordingSelected(Some(path)) => {
+                match Recording::load(&path) {
+                    Ok(recording) => Task::done(Message::Connected(Ok(Arc::new(
+                        ReplaySource::new(path, recording, self.replay_speed),
+                    )))),
+                    Err(error) => {
+                        self.connection = ConnectionState::Failed;
+                        self.detail = error;
+                        Task::none()
+                    }
+                }
+            }
+            Message::ReplaySpeedChanged(speed) => {
+                self.replay_speed = speed.clamp(MIN_REPLAY_SPEED, MAX_REPLAY_SPEED);
+                Task::none()
+            }
+            Message::OpenPractice => Task::perform(
+                async {
+                    rfd::FileDialog::new()
+                        .set_title("Open lyrics to practice")
+                        .add_filter("Lyrics", &PRACTICE_EXTENSIONS)
+                        .pick_file()
+                },
+                Message::PracticeFileSelected,
+            ),
+            Message::PracticeFileSelected(None) => Task::none(),
+            Message::PracticeFileSelected(Some(path)) => match practice::load_timeline(&path) {
+                Ok(timeline) => {
+                    let clock = Arc::new(PracticeClock::new(
+                        practice::practice_duration_ms(&timeline),
+                        self.practice_speed,
+                    ));
+                    self.practice = Some(clock.clone());
+                    Task::done(Message::Connected(Ok(Arc::new(PracticeSource::new(
+                        path, timeline, clock,
+                    )))))
+                }
+                Err(error) => {
+                    self.connection = ConnectionState::Failed;
+                    self.detail = error;
+                    Task::none()
+                }
+            },
+            Message::PracticeToggled => {
+                if let Some(clock) = &self.practice {
+                    clock.toggle();
+                }
+                Task::none()
+            }
+            Message::PracticeSeeked(position_ms) => {
+                if let Some(clock) = &self.practice {
+                    clock.seek(position_ms);
+                }
+                Task::none()
+            }
+            Message::PracticeSpeedChanged(speed) => {
+                self.practice_speed = speed.clamp(MIN_PRACTICE_SPEED, MAX_PRACTICE_SPEED);
+                if let Some(clock) = &self.practice {
+                    clock.set_speed(self.practice_speed);
+                }
+                Task::none()
+            }
+            Message::GlobalOffsetAdjusted(delta_ms) => {
+                self.timing_offsets.adjust_global(delta_ms);
+                Task::none()
+            }
+            Message::SongOffsetAdjusted(delta_ms) => {
+                if let Some(song) = self.song_key {
+                    self.timing_offsets.adjust_song(song, delta_ms);
+                }
+                Task::none()
+            }
+            Message::SongOffsetReset => {
+                if let Some(song) = self.song_key {
+                    self.timing_offsets.reset_song(song);
+                }
+                Task::none()
+            }
+            Message::WebSocketToggled(enabled) => {
+                self.websocket.enabled = enabled;
+                if let Some(port) = parse_port(&self.websocket_port_input) {
+                    self.websocket.port = port;
+                }
+                self.websocket_port_input = self.websocket.port.to_string();
+                self.apply_websocket_settings();
+                Task::none()
+            }
+            Message::WebSocketPortChanged(input) => {
+                self.websocket_port_input = input;
+                Task::none()
+            }
+            Message::WebSocketPortSubmitted => {
+                match parse_port(&self.websocket_port_input) {
+                    Some(port) if port != self.websocket.port => {
+                        self.websocket.port = port;
+                        self.apply_websocket_settings();
+                    }
+                    Some(_) => {}
+                    None => self.detail = "端口应为 1 到 65535 之间的整数。".into(),
+                }
+                Task::none()
+            }
+            Message::WebSocketRemoteToggled(allow_remote) => {
+                self.websocket.allow_remote = allow_remote;
+                self.apply_websocket_settings();
+                Task::none()
+            }
+            Message::ObsToggled(enabled) => {
+                self.obs.enabled = enabled;
+                self.apply_obs_settings();
+                Task::none()
+            }
+            Message::ObsUrlChanged(url) => {
+                self.obs.url = url;
+                Task::none()
+            }
+            Message::ObsPasswordChanged(password) => {
+                self.obs.password = password;
+                Task::none()
+            }
+            Message::ObsReconnect => {
+                self.apply_obs_settings();
+                Task::none()
+            }
+            // Only redraws the control window with the new status.
+            Message::ObsStatusPolled => Task::none(),
+            Message::ObsPreviousSourceChanged(source) => {
+                self.obs.previous_source = source;
+                Task::none()
+            }
+            Message::ObsCurrentSourceChanged(source) => {
+                self.obs.current_source = source;
+                Task::none()
+            }
+            Message::ObsNextSourceChanged(source) => {
+                self.obs.next_source = source;
+                Task::none()
+            }
+            Message::TextFilePathChanged(file, path) => {
+                *self.text_file_inputs.path_mut(file) = path;
+                Task::none()
+            }
+            Message::TextFilePathSubmitted(file) => {
+                let path = self.text_file_inputs.path(file).trim().to_owned();
+                *self.text_file_inputs.path_mut(file) = path.clone();
+                *self.text_files.path_mut(file) = path;
+                self.text_files_written = None;
+                Task::none()
+            }
+            Message::BrowseTextFile(file) => Task::perform(
+                async move {
+                    rfd::FileDialog::new()
+                        .set_title("Choose a text file")
+                        .add_filter("Text file", &["txt"])
+                        .set_file_name(match file {
+                            TextFile::Current => "current-line.txt",
+                            TextFile::Next => "next-line.txt",
+                            TextFile::Summary => "song.txt",
+                        })
+                        .save_file()
+                },
+                move |path| Message::TextFileSelected(file, path),
+            ),
+            Message::TextFileSelected(_, None) => Task::none(),
+            Message::TextFileSelected(file, Some(path)) => {
+                let path = path.to_string_lossy().into_owned();
+                *self.text_file_inputs.path_mut(file) = path.clone();
+                *self.text_files.path_mut(file) = path;
+                self.text_files_written = None;
+                Task::none()
+            }
+            Message::ExportFormatChanged(format) => {
+                self.export_format = format;
+                Task::none()
+            }
+            Message::ExportLyrics => {
+                let format = self.export_format;
+                Task::perform(
+                    async move {
+                        rfd::FileDialog::new()
+                            .set_title("Export lyrics")
+                            .add_filter(format.filter_name(), &[format.extension()])
+                            .set_file_name(format!("lyrics.{}", format.extension()))
+                            .save_file()
+                    },
+                    Message::LyricsExportSelected,
+                )
+            }
+            Message::LyricsExportSelected(None) => Task::none(),
+            Message::LyricsExportSelected(Some(path)) => {
+                let Some(timeline) = &self.timeline else {
+                    self.detail = "没有可导出的歌词。".into();
+                    return Task::none();
+                };
+                self.detail = match std::fs::write(
+                    &path,
+                    self.export_format
+                        .render(timeline, &self.lyrics_appearance.style()),
+                ) {
+                    Ok(()) => format!("歌词已导出到 {}。", path.display()),
+                    Err(error) => format!("导出歌词失败：{error}"),
+                };
+                Task::none()
+            }
+            Message::PresetSelected(name) => {
+                if let Some(preset) = preset::built_in_presets()
+                    .into_iter()
+                    .chain(self.presets.iter().cloned())
+                    .find(|preset| preset.name == name)
+                {
+                    self.apply_preset(&preset);
+                }
+                Task::none()
+            }
+            Message::PresetNameChanged(name) => {
+                self.preset_name = name;
+                Task::none()
+            }
+            Message::SavePreset => {
+                let name = self.preset_name.trim().to_owned();
+                self.detail = if name.is_empty() {
+                    "请输入预设名称。".into()
+                } else if preset::is_built_in(&name) {
+                    format!("不能覆盖内置预设“{name}”。")
+                } else {
+                    self.store_preset(AppearancePreset {
+                        name: name.clone(),
+                        appearance: self.lyrics_appearance.settings(),
+                    });
+                    self.selected_preset = Some(name.clone());
+                    format!("已保存外观预设“{name}”。")
+                };
+                Task::none()
+            }
+            Message::DeletePreset => {
+                if let Some(name) = self.selected_preset.take() {
+                    self.presets.retain(|preset| preset.name != name);
+                    self.detail = format!("已删除外观预设“{name}”。");
+                }
+                Task::none()
+            }
+            Message::ExportPreset => {
+                let file_name = preset::file_name(&self.preset_name);
+                Task::perform(
+                    async move {
+                        rfd::FileDialog::new()
+                            .set_title("Export appearance preset")
+                            .add_filter("KG Capture appearance", &[PRESET_EXTENSION])
+                            .set_file_name(file_name)
+                            .save_file()
+                    },
+                    Message::PresetExportSelected,
+                )
+            }
+            Message::PresetExportSelected(None) => Task::none(),
+            Message::PresetExportSelected(Some(path)) => {
+                let name = match self.preset_name.trim() {
+                    "" => path
+                        .file_stem()
+                        .map_or_else(|| "外观".into(), |stem| stem.to_string_lossy().into_owned()),
+                    name => name.to_owned(),
+                };
+                let preset = AppearancePreset {
+                    name,
+                    appearance: self.lyrics_appearance.settings(),
+                };
+                self.detail = match std::fs::write(&path, preset::to_json(&preset)) {
+                    Ok(()) => format!("外观预设已导出到 {}。", path.display()),
+                    Err(error) => format!("导出外观预设失败：{error}"),
+                };
+                Task::none()
+            }
+            Message::ImportPreset => Task::perform(
+                async {
+                    rfd::FileDialog::new()
+                        .set_title("Import appearance preset")
+                        .add_filter("KG Capture appearance", &[PRESET_EXTENSION])
+                        .pick_file()
+                },
+                Message::PresetImportSelected,
+            ),
+            Message::PresetImportSelected(None) => Task::none(),
+            Message::PresetImportSelected(Some(path)) => {
+                match std::fs::read_to_string(&path)
+                    .map_err(|error| error.to_string())
+                    .and_then(|contents| preset::parse_preset(&contents))
+                {
+                    Ok(mut preset) => {
+                        if preset::is_built_in(&preset.name) {
+                            preset.name = format!("{}（导入）", preset.name);
+                        }
+                        self.store_preset(preset.clone());
+                        self.apply_preset(&preset);
+                        self.detail = format!("已导入外观预设“{}”。", preset.name);
+                    }
+                    Err(error) => self.detail = format!("导入外观预设失败：{error}"),
+                }
+                Task::none()
+            }
+            Message::ShowLyricsWindow => {
+                if let Some(window) = self.lyrics_window {
+                    window::gain_focus(window)
+                } else {
+                    let (window, open_window) = open_lyrics_window();
+                    self.lyrics_window = Some(window);
+                    open_window.discard()
+                }
+            }
+            Message::BackgroundColorChanged(input) => {
+                if let Some(color) = parse_hex_color(&input) {
+                    self.lyrics_appearance.background = color;
+                }
+                self.lyrics_appearance.background_input = input;
+                Task::none()
+            }
+            Message::BackgroundModeChanged(mode) => {
+                self.lyrics_appearance.background_mode = mode;
+                Task::none()
+            }
+            Message::ChromaKeyColorChanged(input) => {
+                if let Some(color) = parse_hex_color(&input) {
+                    self.lyrics_appearance.chroma_key = color;
+                }
+                self.lyrics_appearance.chroma_key_input = input;
+                Task::none()
+            }
+            Message::TextColorChanged(input) => {
+                if let Some(color) = parse_hex_color(&input) {
+                    self.lyrics_appearance.text = color;
+                }
+                self.lyrics_appearance.text_input = input;
+                Task::none()
+            }
+            Message::HighlightColorChanged(input) => {
+                if let Some(color) = parse_hex_color(&input) {
+                    self.lyrics_appearance.highlight = color;
+                }
+                self.lyrics_appearance.highlight_input = input;
+                Task::none()
+            }
+            Message::LyricsFontChanged(font) => {
+                self.lyrics_appearance.font = font;
+                Task::none()
+            }
+            Message::LyricsLayoutChanged(layout) => {
+                self.lyrics_appearance.layout = layout;
+                Task::none()
+            }
+            Message::LyricsAlignmentChanged(alignment) => {
+                self.lyrics_appearance.alignment = alignment;
+                Task::none()
+            }
+            Message::HighlightModeChanged(mode) => {
+                self.lyrics_appearance.highlight_mode = mode;
+                Task::none()
+            }
+            Message::OutlineWidthChanged(width) => {
+                self.lyrics_appearance.outline_width = clamp_to(width, &OUTLINE_WIDTHS);
+                Task::none()
+            }
+            Message::OutlineColorChanged(input) => {
+                if let Some(color) = parse_hex_color(&input) {
+                    self.lyrics_appearance.outline = color;
+                }
+                self.lyrics_appearance.outline_input = input;
+                Task::none()
+            }
+            Message::ShadowOffsetChanged(offset) => {
+                self.lyrics_appearance.shadow_offset = clamp_to(offset, &SHADOW_OFFSETS);
+                Task::none()
+            }
+            Message::ShadowBlurChanged(blur) => {
+                self.lyrics_appearance.shadow_blur = clamp_to(blur, &SHADOW_BLURS);
+                Task::none()
+            }
+            Message::ShadowColorChanged(input) => {
+                if let Some(color) = parse_hex_color(&input) {
+                    self.lyrics_appearance.shadow = color;
+                }
+                self.lyrics_appearance.shadow_input = input;
+                Task::none()
+            }
+            Message::GlowRadiusChanged(radius) => {
+                self.lyrics_appearance.glow_radius = clamp_to(radius, &GLOW_RADII);
+                Task::none()
+            }
+            Message::GlowColorChanged(input) => {
+                if let Some(color) = parse_hex_color(&input) {
+                    self.lyrics_appearance.glow = color;
+                }
+                self.lyrics_appearance.glow_input = input;
+                Task::none()
+            }
+            Message::LineTransitionChanged(transition) => {
+                self.lyrics_appearance.transition = transition;
+                Task::none()
+            }
+            Message::TransitionDurationChanged(duration_ms) => {
+                self.lyrics_appearance.transition_ms = clamp_to(duration_ms, &TRANSITION_DURATIONS);
+                Task::none()
+            }
+            Message::EasingChanged(easing) => {
+                self.lyrics_appearance.easing = easing;
+                Task::none()
+            }
+            Message::ActiveFontSizeChanged(size) => {
+                self.lyrics_appearance.active_font_size =
+                    size.clamp(*ACTIVE_FONT_SIZES.start(), *ACTIVE_FONT_SIZES.end());
+                Task::none()
+            }
+            Message::CandidateFontSizeChanged(size) => {
+                self.lyrics_appearance.candidate_font_size =
+                    size.clamp(*CANDIDATE_FONT_SIZES.start(), *CANDIDATE_FONT_SIZES.end());
+                Task::none()
+            }
+            Message::ShowPreviousLineChanged(show) => {
+                self.lyrics_appearance.show_previous_line = show;
+                Task::none()
+            }
+            Message::CandidateLineCountChanged(count) => {
+                self.lyrics_appearance.candidate_line_count =
+                    (count.max(0.0) as usize).min(MAX_CANDIDATE_LINES);
+                Task::none()
+            }
+            Message::WindowCloseRequested(window) if window == self.control_window => {
+                self.save_settings();
+                if let Some(source) = &self.source {
+                    let _ = source.send(HostCommand::Shutdown);
+                }
+                iced::exit()
+            }
+            Message::WindowCloseRequested(window) => {
+                if self.lyrics_window == Some(window) {
+                    self.lyrics_window = None;
+                }
+                window::close(window)
+            }
+            Message::Frame(now) => {
+                self.clock.advance(now);
+                self.follow_line(now);
+                Task::none()
+            }
+            Message::SaveSettings => {
+                if self
+                    .pending_settings
+                    .as_ref()
+                    .is_some_and(|(_, changed_at)| changed_at.elapsed() >= SETTINGS_SAVE_DELAY)
+                {
+                    self.save_settings();
+                }
+                Task::none()
+            }
+            Message::HookEvent(Ok(event)) => {
+                if let Some(source) = &self.source {
+                    source.log_event(&event);
+                }
+                self.handle_hook_event(event);
+                self.follow_line(Instant::now());
+                Task::none()
+            }
+            Message::HookEvent(Err(error)) => {
+                self.source = None;
+                self.events = None;
+                self.practice = None;
+                self.connection = ConnectionState::Failed;
+                self.detail = error;
+                Task::none()
+            }
+        }
+    }
+
+    fn title(&self, window: window::Id) -> String {
+        if window == self.control_window {
+            "KG Capture".into()
+        } else {
+            "KG Lyrics".into()
+        }
+    }
+
+    fn theme(&self, window: window::Id) -> Theme {
+        if self.lyrics_window == Some(window) {
+            self.lyrics_theme.clone()
+        } else {
+            Theme::Dark
+        }
+    }
+
+    fn subscription(&self) -> Subscription<Message> {
+        let now = Instant::now();
+        let frames = (self.lyrics_window.is_some()
+            && (self.clock.is_running(now) || self.line_transition(now).is_some()))
+        .then(|| window::frames().map(Message::Frame));
+        // The OBS thread cannot send messages, so its status is polled until
+        // the connection is up.
+        let obs_status = self
+            .obs_client
+            .as_ref()
+            .is_some_and(|client| {
+                matches!(
+                    client.status(),
+                    ObsStatus::Connecting | ObsStatus::Failed(_)
+                )
+            })
+            .then(|| {
+                Subscription::run_with(OBS_STATUS_POLL_INTERVAL, ticks)
+                    .map(|()| Message::ObsStatusPolled)
+            });
+        // Settings are saved from a tick once their changes have settled.
+        let settings_save = self.pending_settings.is_some().then(|| {
+            Subscription::run_with(SETTINGS_SAVE_POLL_INTERVAL, ticks)
+                .map(|()| Message::SaveSettings)
+        });
+        Subscription::batch(
+            [
+                window::close_requests().map(Message::WindowCloseRequested),
+                keyboard::listen().filter_map(offset_hotkey),
+            ]
+            .into_iter()
+            .chain(frames)
+            .chain(obs_status)
+            .chain(settings_save),
+        )
+    }
+
+    /// The playback shown in the lyric window: the clock's position moved by
+    /// the timing offset.
+    fn shown_playback(&self) -> Option<PlaybackPosition> {
+        let timeline = self.timeline.as_ref()?;
+        let offset_ms = self.timing_offsets.total_ms(self.song_key);
+        self.clock
+            .playback(timeline)
+            .map(|playback| offset::shift_playback(timeline, &playback, offset_ms))
+    }
+
+    fn shown_line(&self) -> Option<usize> {
+        self.shown_playback()
+            .and_then(|playback| playback.current_line)
+            .and_then(|index| usize::try_from(index).ok())
+    }
+
+    fn follow_line(&mut self, now: Instant) {
+        let line = self.shown_line();
+        self.line_tracker.follow(line, now);
+    }
+
+    fn line_transition(&self, now: Instant) -> Option<TransitionFrame> {
+        self.line_tracker.frame(
+            now,
+            self.lyrics_appearance.transition_duration(),
+            self.lyrics_appearance.easing,
+        )
+    }
+
+    fn apply_preset(&mut self, preset: &AppearancePreset) {
+        self.lyrics_appearance =
+            LyricsAppearance::from_settings(&preset.appearance, &self.available_fonts);
+        self.selected_preset = Some(preset.name.clone());
+        self.preset_name = preset.name.clone();
+    }
+
+    /// Adds a user preset, replacing one with the same name.
+    fn store_preset(&mut self, preset: AppearancePreset) {
+        match self
+            .presets
+            .iter_mut()
+            .find(|existing| existing.name == preset.name)
+        {
+            Some(existing) => *existing = preset,
+            None => self.presets.push(preset),
+        }
+    }
+
+    /// Forgets the shown song, in the app and in browser overlays.
+    fn clear_song(&mut self) {
+        self.timeline = None;
+        self.song_key = None;
+        self.playback = None;
+        self.playback_state = None;
+        self.clock.reset();
+        if let Some(server) = &self.lyrics_server {
+            server.publish_cleared();
+        }
+    }
+
+    fn send(&mut self, command: HostCommand) {
+        if let Some(source) = &self.source
+            && let Err(error) = source.send(command)
+        {
+            self.connection = ConnectionState::Failed;
+            self.detail = error;
+        }
+    }
+
+    fn handle_hook_event(&mut self, event: HookEvent) {
+        match event {
+            HookEvent::CaptureStarted => {
+                self.connection = ConnectionState::Streaming;
+                self.detail = "歌词同步已就绪，正在等待 WeSing 加载歌词…".into();
+            }
+            HookEvent::CaptureStopped => {
+                self.connection = ConnectionState::Connected;
+                self.detail = "歌词读取已停止。".into();
+            }
+            HookEvent::Timeline(timeline) => {
+                self.detail = "歌词同步中。".into();
+                self.playback = None;
+                self.playback_state = None;
+                self.clock.reset();
+                self.song_key = Some(offset::song_key(&timeline));
+                if let Some(server) = &self.lyrics_server {
+                    // The song's own offset applies from its first position.
+                    server.publish_timing_offset(self.timing_offsets.total_ms(self.song_key));
+                    server.publish_timeline(&timeline);
+                }
+                self.timeline = Some(timeline);
+            }
+            HookEvent::Playback(playback) => {
+                if self
+                    .timeline
+                    .as_ref()
+                    .is_some_and(|timeline| timeline.id == playback.timeline_id)
+                {
+                    self.clock.observe(&playback, Instant::now());
+                    if let Some(server) = &self.lyrics_server {
+                        server.publish_playback(&playback);
+                    }
+                    self.playback = Some(playback);
+                }
+            }
+            HookEvent::PlaybackState { timeline_id, state } => {
+                if self
+                    .timeline
+                    .as_ref()
+                    .is_some_and(|timeline| timeline.id == timeline_id)
+                {
+                    self.clock.set_frozen(matches!(
+                        state,
+                        PlaybackState::Paused | PlaybackState::Stopped | PlaybackState::SongEnded
+                    ));
+                    if let Some(server) = &self.lyrics_server {
+                        server.publish_playback_state(timeline_id, state);
+                    }
+                    self.playback_state = Some(state);
+                }
+            }
+            HookEvent::Warning(message) => self.detail = format!("警告：{message}"),
+            HookEvent::Error(message) => {
+                self.connection = ConnectionState::Failed;
+                self.detail = message;
+            }
+            HookEvent::Pong { .. } => {}
+        }
+    }
+
+    fn view(&self, window: window::Id) -> Element<'_, Message> {
+        if window == self.control_window {
+            self.control_view()
+        } else if self.lyrics_window == Some(window) {
+            self.lyrics_window_view()
+        } else {
+            container(text("")).into()
+        }
+    }
+
+    fn control_view(&self) -> Element<'_, Message> {
+        let status = match self.connection {
+            ConnectionState::Disconnected => "未连接",
+            ConnectionState::Connecting => "连接中",
+            ConnectionState::Connected => "已连接",
+            ConnectionState::Streaming => "歌词同步中",
+            ConnectionState::Failed => "错误",
+        };
+        let status = match self.playback_state {
+            Some(state) => format!("{status} · {}", playback_state_label(state)),
+            None => status.to_owned(),
+        };
+
+        let executable = text_input("WeSing.exe 路径", &self.executable_path)
+            .on_input(Message::ExecutablePathChanged)
+            .width(Fill);
+        let browse = button("浏览…").on_press(Message::BrowseExecutable);
+        let can_launch = matches!(
+            self.connection,
+            ConnectionState::Disconnected | ConnectionState::Failed
+        ) && !self.executable_path.trim().is_empty();
+        let launch = button("启动 WeSing").on_press_maybe(can_launch.then_some(Message::Launch));
+        let can_connect = matches!(
+            self.connection,
+            ConnectionState::Disconnected | ConnectionState::Failed
+        );
+        let open_recording =
+            button("回放录制…").on_press_maybe(can_connect.then_some(Message::OpenRecording));
+        let start_fixture =
+            button("演示").on_press_maybe(can_connect.then_some(Message::StartFixture));
+        let replay_speed = slider(
+            MIN_REPLAY_SPEED..=MAX_REPLAY_SPEED,
+            self.replay_speed,
+            Message::ReplaySpeedChanged,
+        )
+        .step(0.25_f32)
+        .width(160);
+        let disconnect = button("断开").on_press_maybe(
+            matches!(
+                self.connection,
+                ConnectionState::Connected | ConnectionState::Streaming
+            )
+            .then_some(Message::Disconnect),
+        );
+        let open_practice =
+            button("练习…").on_press_maybe(can_connect.then_some(Message::OpenPractice));
+        let practice_controls = self.practice.as_ref().map(|clock| {
+            let position_ms = clock.position_ms();
+            row![
+                button(if clock.is_playing() {
+                    "暂停"
+                } else {
+                    "播放"
+                })
+                .on_press(Message::PracticeToggled),
+                slider(
+                    0.0..=clock.duration_ms(),
+                    position_ms,
+                    Message::PracticeSeeked
+                )
+                .width(Fill),
+                text(format!(
+                    "{} / {}",
+                    practice::format_position(position_ms),
+                    practice::format_position(clock.duration_ms())
+                )),
+                slider(
+                    MIN_PRACTICE_SPEED..=MAX_PRACTICE_SPEED,
+                    self.practice_speed,
+                    Message::PracticeSpeedChanged,
+                )
+                .step(0.05_f32)
+                .width(120),
+                text(format!("{:.2}×", self.practice_speed)),
+            ]
+            .spacing(12)
+            .align_y(iced::Center)
+        });
+        let song_offset = self.song_key.map(|song| self.timing_offsets.song_ms(song));
+        let timing_offset = row![
+            text("全局偏移").width(72),
+            button("−").on_press(Message::GlobalOffsetAdjusted(-OFFSET_STEP_MS)),
+            text(offset::format_offset(self.timing_offsets.global_ms)).width(72),
+            button("+").on_press(Message::GlobalOffsetAdjusted(OFFSET_STEP_MS)),
+            text("本曲偏移").width(72),
+            button("−")
+                .on_press_maybe(song_offset.map(|_| Message::SongOffsetAdjusted(-OFFSET_STEP_MS))),
+            text(song_offset.map_or_else(|| "—".into(), offset::format_offset)).width(72),
+            button("+")
+                .on_press_maybe(song_offset.map(|_| Message::SongOffsetAdjusted(OFFSET_STEP_MS))),
+            button("重置").on_press_maybe(
+                song_offset
+                    .filter(|offset_ms| *offset_ms != 0)
+                    .map(|_| Message::SongOffsetReset)
+            ),
+        ]
+        .spacing(10)
+        .align_y(iced::Center);
+        let export_format = pick_list(
+            ExportFormat::ALL,
+            Some(self.export_format),
+            Message::ExportFormatChanged,
+        )
+        .width(180);
+        let export_lyrics = button("导出歌词")
+            .on_press_maybe(self.timeline.is_some().then_some(Message::ExportLyrics));
+        let lyrics_window = button(if self.lyrics_window.is_some() {
+            "显示歌词窗口"
+        } else {
+            "重新打开歌词窗口"
+        })
+        .on_press(Message::ShowLyricsWindow);
+        let websocket = row![
+            checkbox(self.websocket.enabled)
+                .label("WebSocket 服务")
+                .on_toggle(Message::WebSocketToggled),
+            text("端口"),
+            text_input("端口", &self.websocket_port_input)
+                .on_input(Message::WebSocketPortChanged)
+                .on_submit(Message::WebSocketPortSubmitted)
+                .width(80),
+            checkbox(self.websocket.allow_remote)
+                .label("允许局域网连接")
+                .on_toggle(Message::WebSocketRemoteToggled),
+            text(match &self.lyrics_server {
+                Some(server) => format!("http://{}/", server.address()),
+                None => "未运行".into(),
+            })
+            .size(13),
+        ]
+        .spacing(10)
+        .align_y(iced::Center);
+        let obs_connection = row![
+            checkbox(self.obs.enabled)
+                .label("OBS 文本源")
+                .on_toggle(Message::ObsToggled),
+            text_input(obs::DEFAULT_URL, &self.obs.url)
+                .on_input(Message::ObsUrlChanged)
+                .on_submit(Message::ObsReconnect)
+                .width(190),
+            text_input("密码", &self.obs.password)
+                .secure(true)
+                .on_input(Message::ObsPasswordChanged)
+                .on_submit(Message::ObsReconnect)
+                .width(120),
+            text(match &self.obs_client {
+                Some(client) => client.status().to_string(),
+                None => "未启用".into(),
+            })
+            .size(13),
+        ]
+        .spacing(10)
+        .align_y(iced::Center);
+        let obs_sources = row![
+            text("上一句").width(52),
+            text_input("源名称", &self.obs.previous_source)
+                .on_input(Message::ObsPreviousSourceChanged)
+                .width(130),
+            text("当前").width(36),
+            text_input("源名称", &self.obs.current_source)
+                .on_input(Message::ObsCurrentSourceChanged)
+                .width(130),
+            text("下一句").width(52),
+            text_input("源名称", &self.obs.next_source)
+                .on_input(Message::ObsNextSourceChanged)
+                .width(130),
+        ]
+        .spacing(10)
+        .align_y(iced::Center);
+        let text_files = TextFile::ALL
+            .map(|file| {
+                row![
+                    text(file.to_string()).width(92),
+                    text_input("未设置（输入后按回车）", self.text_file_inputs.path(file))
+                        .on_input(move |path| Message::TextFilePathChanged(file, path))
+                        .on_submit(Message::TextFilePathSubmitted(file))
+                        .width(Fill),
+                    button("选择…").on_press(Message::BrowseTextFile(file)),
+                ]
+                .spacing(10)
+                .align_y(iced::Center)
+                .into()
+            })
+            .into_iter()
+            .chain(
+                self.text_file_writer
+                    .as_ref()
+                    .and_then(TextFileWriter::error)
+                    .map(|error| text(format!("写入文本文件失败：{error}")).size(13).into()),
+            );
+        let preset_names: Vec<String> = preset::built_in_presets()
+            .into_iter()
+            .chain(self.presets.iter().cloned())
+            .map(|preset| preset.name)
+            .collect();
+        let presets = pick_list(
+            preset_names,
+            self.selected_preset.clone(),
+            Message::PresetSelected,
+        )
+        .placeholder("选择预设")
+        .width(160);
+        let preset_name = text_input("预设名称", &self.preset_name)
+            .on_input(Message::PresetNameChanged)
+            .on_submit(Message::SavePreset)
+            .width(140);
+        let save_preset = button("保存").on_press(Message::SavePreset);
+        let delete_preset = button("删除").on_press_maybe(
+            self.selected_preset
+                .as_deref()
+                .filter(|name| !preset::is_built_in(name))
+                .map(|_| Message::DeletePreset),
+        );
+        let import_preset = button("导入…").on_press(Message::ImportPreset);
+        let export_preset = button("导出…").on_press(Message::ExportPreset);
+        let background_color = text_input("#RRGGBB", &self.lyrics_appearance.background_input)
+            .on_input(Message::BackgroundColorChanged)
+            .width(150);
+        let background_mode = pick_list(
+            BackgroundMode::ALL,
+            Some(self.lyrics_appearance.background_mode),
+            Message::BackgroundModeChanged,
+        )
+        .width(120);
+        let chroma_key_color = text_input("#RRGGBB", &self.lyrics_appearance.chroma_key_input)
+            .on_input(Message::ChromaKeyColorChanged)
+            .width(150);
+        let text_color = text_input("#RRGGBB", &self.lyrics_appearance.text_input)
+            .on_input(Message::TextColorChanged)
+            .width(150);
+        let highlight_color = text_input("#RRGGBB", &self.lyrics_appearance.highlight_input)
+            .on_input(Message::HighlightColorChanged)
+            .width(150);
+        let background_preview = color_swatch(self.lyrics_appearance.background);
+        let text_preview = color_swatch(self.lyrics_appearance.text);
+        let highlight_preview = color_swatch(self.lyrics_appearance.highlight);
+        let font = pick_list(
+            self.available_fonts.as_slice(),
+            Some(self.lyrics_appearance.font.clone()),
+            Message::LyricsFontChanged,
+        )
+        .width(200);
+        let layout = pick_list(
+            LyricsLayout::ALL,
+            Some(self.lyrics_appearance.layout),
+            Message::LyricsLayoutChanged,
+        )
+        .width(120);
+        let alignment = pick_list(
+            LyricsAlignment::ALL,
+            Some(self.lyrics_appearance.alignment),
+            Message::LyricsAlignmentChanged,
+        )
+        .width(120);
+        let highlight_mode = pick_list(
+            HighlightMode::ALL,
+            Some(self.lyrics_appearance.highlight_mode),
+            Message::HighlightModeChanged,
+        )
+        .width(120);
+        let active_font_size = slider(
+            ACTIVE_FONT_SIZES,
+            self.lyrics_appearance.active_font_size,
+            Message::ActiveFontSizeChanged,
+        )
+        .step(1.0_f32)
+        .width(Fill);
+        let candidate_font_size = slider(
+            CANDIDATE_FONT_SIZES,
+            self.lyrics_appearance.candidate_font_size,
+            Message::CandidateFontSizeChanged,
+        )
+        .step(1.0_f32)
+        .width(Fill);
+        let show_previous_line = checkbox(self.lyrics_appearance.show_previous_line)
+            .label("显示上一句歌词")
+            .on_toggle(Message::ShowPreviousLineChanged);
+        let candidate_line_count = slider(
+            0.0..=MAX_CANDIDATE_LINES as f32,
+            self.lyrics_appearance.candidate_line_count as f32,
+            Message::CandidateLineCountChanged,
+        )
+        .step(1.0_f32)
+        .width(Fill);
+        let outline_width = slider(
+            OUTLINE_WIDTHS,
+            self.lyrics_appearance.outline_width,
+            Message::OutlineWidthChanged,
+        )
+        .step(0.5_f32)
+        .width(Fill);
+        let outline_color = text_input("#RRGGBB", &self.lyrics_appearance.outline_input)
+            .on_input(Message::OutlineColorChanged)
+            .width(110);
+        let shadow_offset = slider(
+            SHADOW_OFFSETS,
+            self.lyrics_appearance.shadow_offset,
+            Message::ShadowOffsetChanged,
+        )
+        .step(0.5_f32)
+        .width(Fill);
+        let shadow_blur = slider(
+            SHADOW_BLURS,
+            self.lyrics_appearance.shadow_blur,
+            Message::ShadowBlurChanged,
+        )
+        .step(0.5_f32)
+        .width(Fill);
+        let shadow_color = text_input("#RRGGBB", &self.lyrics_appearance.shadow_input)
+            .on_input(Message::ShadowColorChanged)
+            .width(110);
+        let glow_radius = slider(
+            GLOW_RADII,
+            self.lyrics_appearance.glow_radius,
+            Message::GlowRadiusChanged,
+        )
+        .step(0.5_f32)
+        .width(Fill);
+        let glow_color = text_input("#RRGGBB", &self.lyrics_appearance.glow_input)
+            .on_input(Message::GlowColorChanged)
+            .width(110);
+        let line_transition = pick_list(
+            LineTransition::ALL,
+            Some(self.lyrics_appearance.transition),
+            Message::LineTransitionChanged,
+        )
+        .width(120);
+        let transition_duration = slider(
+            TRANSITION_DURATIONS,
+            self.lyrics_appearance.transition_ms,
+            Message::TransitionDurationChanged,
+        )
+        .step(10.0_f32)
+        .width(Fill);
+        let easing = pick_list(
+            Easing::ALL,
+            Some(self.lyrics_appearance.easing),
+            Message::EasingChanged,
+        )
+        .width(110);
+        let colors_valid = [
+            &self.lyrics_appearance.background_input,
+            &self.lyrics_appearance.chroma_key_input,
+            &self.lyrics_appearance.text_input,
+            &self.lyrics_appearance.highlight_input,
+            &self.lyrics_appearance.outline_input,
+            &self.lyrics_appearance.shadow_input,
+            &self.lyrics_appearance.glow_input,
+        ]
+        .into_iter()
+        .all(|input| parse_hex_color(input).is_some());
+
+        let content = column![
+            text("KG Capture").size(32),
+            text(format!("状态：{status}")),
+            text(&self.detail),
+            row![executable, browse].spacing(8),
+            row![
+                launch,
+                disconnect,
+                open_recording,
+                start_fixture,
+                replay_speed,
+                text(format!("{:.2}×", self.replay_speed)),
+            ]
+            .spacing(12)
+            .align_y(iced::Center),
+            row![open_practice, practice_controls]
+                .spacing(12)
+                .align_y(iced::Center),
+            row![lyrics_window, export_format, export_lyrics]
+                .spacing(12)
+                .align_y(iced::Center),
+            timing_offset,
+            websocket,
+            obs_connection,
+            obs_sources,
+            Column::with_children(text_files).spacing(8),
+            text("歌词窗口样式").size(20),
+            row![
+                text("预设").width(72),
+                presets,
+                preset_name,
+                save_preset,
+                delete_preset,
+                import_preset,
+                export_preset,
+            ]
+            .spacing(10)
+            .align_y(iced::Center),
+            row![
+                text("背景色").width(72),
+                background_color,
+                background_preview,
+                text("文本色").width(72),
+                text_color,
+                text_preview,
+            ]
+            .spacing(10)
+            .align_y(iced::Center),
+            row![
+                text("高亮色").width(72),
+                highlight_color,
+                highlight_preview,
+                text("背景").width(52),
+                background_mode,
+                text("布局").width(52),
+                layout,
+            ]
+            .spacing(10)
+            .align_y(iced::Center),
+            row![
+                text("色键").width(72),
+                chroma_key_color,
+                color_swatch(self.lyrics_appearance.chroma_key),
+                text(match self.lyrics_appearance.background_mode {
+                    BackgroundMode::ChromaKey => {
+                        "色键模式以硬边缘绘制文字，强制至少 2 px 不透明描边，并关闭发光、阴影模糊和淡入淡出过渡。"
+                    }
+                    _ => "仅在色键模式下使用。",
+                })
+                .size(13),
+            ]
+            .spacing(10)
+            .align_y(iced::Center),
+            row![
+                text("字体").width(72),
+                font,
+                text("对齐").width(52),
+                alignment,
+                text("高亮").width(52),
+                highlight_mode,
+            ]
+            .spacing(10)
+            .align_y(iced::Center),
+            row![
+                text("播放中字号").width(92),
+                active_font_size,
+                text(format!("{:.0} px", self.lyrics_appearance.active_font_size)).width(58),
+            ]
+            .spacing(10)
+            .align_y(iced::Center),
+            row![
+                text("候选字号").width(92),
+                candidate_font_size,
+                text(format!(
+                    "{:.0} px",
+                    self.lyrics_appearance.candidate_font_size
+                ))
+                .width(58),
+            ]
+            .spacing(10)
+            .align_y(iced::Center),
+            row![
+                show_previous_line,
+                text("候选条目数").width(92),
+                candidate_line_count,
+                text(format!(
+                    "{} 条",
+                    self.lyrics_appearance.candidate_line_count
+                ))
+                .width(58),
+            ]
+            .spacing(10)
+            .align_y(iced::Center),
+            row![
+                text("描边").width(92),
+                outline_width,
+                text(format!("{:.1} px", self.lyrics_appearance.outline_width)).width(58),
+                outline_color,
+                color_swatch(self.lyrics_appearance.outline),
+            ]
+            .spacing(10)
+            .align_y(iced::Center),
+            row![
+                text("阴影偏移/模糊").width(92),
+                shadow_offset,
+                shadow_blur,
+                text(format!(
+                    "{:.1}/{:.1}",
+                    self.lyrics_appearance.shadow_offset, self.lyrics_appearance.shadow_blur
+                ))
+                .width(58),
+                shadow_color,
+                color_swatch(self.lyrics_appearance.shadow),
+            ]
+            .spacing(10)
+            .align_y(iced::Center),
+            row![
+                text("发光").width(92),
+                glow_radius,
+                text(format!("{:.1} px", self.lyrics_appearance.glow_radius)).width(58),
+                glow_color,
+                color_swatch(self.lyrics_appearance.glow),
+            ]
+            .spacing(10)
+            .align_y(iced::Center),
+            row![
+                text("换行动画").width(92),
+                line_transition,
+                transition_duration,
+                text(format!("{:.0} ms", self.lyrics_appearance.transition_ms)).width(58),
+                easing,
+            ]
+            .spacing(10)
+            .align_y(iced::Center),
+            text(if colors_valid {
+                "颜色使用 #RRGGBB 格式，修改会实时应用到歌词窗口。"
+            } else {
+                "颜色格式无效；请输入类似 #1A1A1A 的六位十六进制颜色。"
+            })
+            .size(13),
+        ]
+        .spacing(12)
+        .padding(24);
+
+        scrollable(content).width(Fill).height(Fill).into()
+    }
+
+    fn lyrics_window_view(&self) -> Element<'_, Message> {
+        let transition = self.line_transition(Instant::now());
+        if self.lyrics_appearance.background_mode == BackgroundMode::ChromaKey {
+            return responsive(move |size| self.keyed_lyrics_view(size, transition)).into();
+        }
+        lyrics_window_content(
+            self.timeline.as_ref(),
+            self.shown_playback().as_ref(),
+            self.playback_state,
+            &self.lyrics_appearance,
+            self.lyrics_appearance.window_background(),
+            transition,
+        )
+    }
+
+    /// The chroma-keyed lyric window, matted on the CPU and shown pixel for
+    /// pixel: the GPU renderer has no way to draw text without anti-aliasing.
+    fn keyed_lyrics_view(
+        &self,
+        size: Size,
+        transition: Option<TransitionFrame>,
+    ) -> Element<'_, Message> {
+        let playback = self.shown_playback();
+        let size = Size::new(size.width.round() as u32, size.height.round() as u32);
+        let mut renderer = self.keyed_renderer.borrow_mut();
+        if renderer
+            .as_ref()
+            .is_none_or(|renderer| renderer.size() != size)
+        {
+            *renderer = render::FrameRenderer::new(size).ok();
+        }
+        let Some(renderer) = renderer.as_mut() else {
+            return lyrics_window_content(
+                self.timeline.as_ref(),
+                playback.as_ref(),
+                self.playback_state,
+                &self.lyrics_appearance,
+                self.lyrics_appearance.window_background(),
+                transition,
+            );
+        };
+        let pixels = renderer.render_window(
+            self.timeline.as_ref(),
+            playback.as_ref(),
+            self.playback_state,
+            &self.lyrics_appearance,
+            transition,
+        );
+        image(image::Handle::from_rgba(size.width, size.height, pixels))
+            .width(Fill)
+            .height(Fill)
+            .content_fit(ContentFit::Fill)
+            .filter_method(image::FilterMethod::Nearest)
+            .into()
+    }
+}
+
+/// Everything the lyric window shows, over `background`. The headless
+/// renderer draws the same content.
+fn lyrics_window_content<'a>(
+    timeline: Option<&'a LyricTimeline>,
+    playback: Option<&PlaybackPosition>,
+    playback_state: Option<PlaybackState>,
+    appearance: &LyricsAppearance,
+    background: Color,
+    transition: Option<TransitionFrame>,
+) -> Element<'a, Message> {
+    let alignment = appearance.alignment.horizontal();
+    let finished = match playback_state {
+        Some(PlaybackState::SongEnded) => Some("歌曲已结束。"),
+        Some(PlaybackState::Stopped) => Some("播放已停止。"),
+        _ => None,
+    };
+    let lyrics = match (timeline, playback, finished) {
+        (Some(timeline), Some(playback), None) => {
+            lyric_view(timeline, playback, appearance, transition)
+        }
+        (Some(_), _, finished) => container(
+            text(finished.unwrap_or("等待播放位置…"))
+                .font(appearance.font.font())
+                .size(appearance.candidate_font_size)
+                .color(appearance.text),
+        )
+        .width(Fill)
+        .height(Fill)
+        .align_x(alignment)
+        .center_y(Fill)
+        .into(),
+        _ => container(
+            text("等待歌词…")
+                .font(appearance.font.font())
+                .size(appearance.candidate_font_size)
+                .color(appearance.text),
+        )
+        .width(Fill)
+        .height(Fill)
+        .align_x(alignment)
+        .center_y(Fill)
+        .into(),
+    };
+
+    container(lyrics)
+        .width(Fill)
+        .height(Fill)
+        .padding(36)
+        .style(move |_| container::Style::default().background(background))
+        .into()
+}
+
+/// Clears the lyric window to transparent, so the view alone decides what
+/// shows behind the text.
+fn lyrics_theme() -> Theme {
+    Theme::custom(
+        "KG Lyrics",
+        Palette {
+            background: Color::TRANSPARENT,
+            ..Palette::DARK
+        },
+    )
+}
+
+fn open_lyrics_window() -> (window::Id, Task<window::Id>) {
+    window::open(window::Settings {
+        size: Size::new(900.0, 420.0),
+        min_size: Some(Size::new(480.0, 240.0)),
+        position: window::Position::Centered,
+        transparent: true,
+        exit_on_close_request: false,
+        ..window::Settings::default()
+    })
+}
+
+/// Ctrl+←/→ moves the current song's offset, Ctrl+Shift+←/→ the global one,
+/// and Ctrl+0 clears the song offset. Keys captured by a focused text input
+/// are not seen here.
+fn offset_hotkey(event: keyboard::Event) -> Option<Message> {
+    let keyboard::Event::KeyPressed { key, modifiers, .. } = event else {
+        return None;
+    };
+    if !modifiers.command() {
+        return None;
+    }
+    let delta_ms = match key.as_ref() {
+        keyboard::Key::Named(keyboard::key::Named::ArrowLeft) => -OFFSET_STEP_MS,
+        keyboard::Key::Named(keyboard::key::Named::ArrowRight) => OFFSET_STEP_MS,
+        keyboard::Key::Character("0") if !modifiers.shift() => {
+            return Some(Message::SongOffsetReset);
+        }
+        _ => return None,
+    };
+    Some(if modifiers.shift() {
+        Message::GlobalOffsetAdjusted(delta_ms)
+    } else {
+        Message::SongOffsetAdjusted(delta_ms)
+    })
+}
+
+fn playback_state_label(state: PlaybackState) -> &'static str {
+    match state {
+        PlaybackState::Playing => "播放中",
+        PlaybackState::Paused => "已暂停",
+        PlaybackState::Seeking => "跳转中",
+        PlaybackState::Stopped => "已停止",
+        PlaybackState::SongEnded => "已播完",
+    }
+}
+
+/// How one copy of the lyric layout is drawn while a transition runs.
+#[derive(Clone, Copy)]
+struct LinesLook {
+    opacity: f32,
+    active_opacity: f32,
+    active_scale: f32,
+}
+
+impl LinesLook {
+    const SHOWN: Self = Self {
+        opacity: 1.0,
+        active_opacity: 1.0,
+        active_scale: 1.0,
+    };
+
+    fn faded(opacity: f32) -> Self {
+        Self {
+            opacity,
+            ..Self::SHOWN
+        }
+    }
+}
+
+fn lyric_view<'a>(
+    timeline: &'a LyricTimeline,
+    playback: &PlaybackPosition,
+    appearance: &LyricsAppearance,
+    transition: Option<TransitionFrame>,
+) -> Element<'a, Message> {
+    let current_index = playback
+        .current_line
+        .and_then(|index| usize::try_from(index).ok())
+        .filter(|index| *index < timeline.lines.len());
+    let progress = playback.line_progress.clamp(0.0, 1.0);
+    let transition = transition.filter(|frame| current_index == Some(frame.from + 1));
+    if appearance.layout == LyricsLayout::TwoLine {
+        // Every transition fades the upcoming line into the finished one's row.
+        return two_line_view(
+            timeline,
+            current_index,
+            playback.position_ms,
+            progress,
+            appearance,
+            transition.map_or(1.0, |frame| frame.progress),
+        );
+    }
+    let shown = |look| {
+        lyric_lines(
+            timeline,
+            current_index,
+            playback.position_ms,
+            progress,
+            appearance,
+            look,
+        )
+    };
+    let Some(TransitionFrame { from, progress: t }) = transition else {
+        return shown(LinesLook::SHOWN);
+    };
+    // The finished line as it looked when its last word was sung.
+    let finished = |look| {
+        let line = &timeline.lines[from];
+        lyric_lines(
+            timeline,
+            Some(from),
+            line.start_ms + line.duration_ms,
+            1.0,
+            appearance,
+            look,
+        )
+    };
+
+    match appearance.line_transition() {
+        LineTransition::Off => shown(LinesLook::SHOWN),
+        LineTransition::ScrollUp => ScrollIn::new(
+            shown(LinesLook::SHOWN),
+            departed_line(timeline, from, appearance),
+        )
+        .spacing(LINE_SPACING)
+        .columns(appearance.layout == LyricsLayout::Vertical)
+        .remaining(1.0 - t)
+        .into(),
+        LineTransition::Crossfade => stack![
+            finished(LinesLook::faded(1.0 - t)),
+            shown(LinesLook::faded(t))
+        ]
+        .into(),
+        LineTransition::ScaleIn => shown(LinesLook {
+            active_opacity: t,
+            active_scale: 0.8 + 0.2 * t,
+            ..LinesLook::SHOWN
+        }),
+        LineTransition::Fade => stack![
+            shown(LinesLook::SHOWN),
+            finished(LinesLook {
+                opacity: 0.0,
+                active_opacity: 1.0 - t,
+                active_scale: 1.0,
+            })
+        ]
+        .into(),
+    }
+}
+
+/// The first line of the finished line's layout, which scrolls away when the
+/// next line starts: the previous line when it is shown, and otherwise the
+/// finished line itself. Only its size is used.
+fn departed_line<'a>(
+    timeline: &'a LyricTimeline,
+    from: usize,
+    appearance: &LyricsAppearance,
+) -> Element<'a, Message> {
+    let vertical = appearance.layout == LyricsLayout::Vertical;
+    if appearance.show_previous_line {
+        let text = &timeline.lines[from.saturating_sub(1)].text;
+        return if vertical {
+            vertical_line(
+                &[(text, 0.0)],
+                appearance.candidate_font_size,
+                appearance.text,
+                appearance,
+                1.0,
+            )
+        } else {
+            lyric_line_text(text, appearance.text, appearance).into()
+        };
+    }
+    let line = &timeline.lines[from];
+    let segments = line_segments(line, line.start_ms + line.duration_ms, 1.0);
+    if vertical {
+        vertical_line(
+            &segments,
+            appearance.active_font_size,
+            appearance.text,
+            appearance,
+            1.0,
+        )
+    } else {
+        current_line_view(
+            line,
+            line.start_ms + line.duration_ms,
+            1.0,
+            appearance,
+            appearance.alignment.horizontal(),
+            1.0,
+        )
+    }
+}
+
+/// The previous, active and candidate lines around `current_index`.
+fn lyric_lines<'a>(
+    timeline: &'a LyricTimeline,
+    current_index: Option<usize>,
+    position_ms: f32,
+    progress: f32,
+    appearance: &LyricsAppearance,
+    look: LinesLook,
+) -> Element<'a, Message> {
+    if appearance.layout == LyricsLayout::Vertical {
+        return lyric_columns(
+            timeline,
+            current_index,
+            position_ms,
+            progress,
+            appearance,
+            look,
+        );
+    }
+    let mut body = column![].spacing(LINE_SPACING).width(Fill);
+
+    if let Some(index) = current_index {
+        if appearance.show_previous_line && index > 0 {
+            body = body.push(
+                lyric_line_text(
+                    &timeline.lines[index - 1].text,
+                    dim_color(appearance.text, 0.55),
+                    appearance,
+                )
+                .opacity(look.opacity),
+            );
+        }
+        body = body.push(
+            Transformed::new(current_line_view(
+                &timeline.lines[index],
+                position_ms,
+                progress,
+                appearance,
+                appearance.alignment.horizontal(),
+                look.opacity * look.active_opacity,
+            ))
+            .scale(look.active_scale),
+        );
+        for line in timeline
+            .lines
+            .iter()
+            .skip(index + 1)
+            .take(appearance.candidate_line_count)
+        {
+            body = body.push(
+                lyric_line_text(&line.text, dim_color(appearance.text, 0.78), appearance)
+                    .opacity(look.opacity),
+            );
+        }
+    } else {
+        body = body.push(
+            lyric_line_text("等待第一句歌词…", appearance.text, appearance).opacity(look.opacity),
+        );
+        for line in timeline.lines.iter().take(appearance.candidate_line_count) {
+            body = body.push(
+                lyric_line_text(&line.text, dim_color(appearance.text, 0.78), appearance)
+                    .opacity(look.opacity),
+            );
+        }
+    }
+
+    container(body)
+        .width(Fill)
+        .height(Fill)
+        .center_y(Fill)
+        .into()
+}
+
+fn current_line_view<'a>(
+    line: &'a LyricLine,
+    position_ms: f32,
+    line_progress: f32,
+    appearance: &LyricsAppearance,
+    align_x: iced::alignment::Horizontal,
+    opacity: f32,
+) -> Element<'a, Message> {
+    let mut words = row![].spacing(0);
+    for (segment, progress) in line_segments(line, position_ms, line_progress) {
+        words = words.push(
+            KaraokeText::new(segment, progress, appearance.highlight_mode)
+                .font(appearance.font.font())
+                .size(appearance.active_font_size)
+                .colors(dim_color(appearance.text, 0.68), appearance.highlight)
+                .effects(appearance.effects())
+                .opacity(opacity),
+        );
+    }
+    container(words.wrap()).width(Fill).align_x(align_x).into()
+}
+
+/// The highlight progress of each word, or of each piece of a line without
+/// word timings.
+fn line_segments(line: &LyricLine, position_ms: f32, line_progress: f32) -> Vec<(&str, f32)> {
+    if line.words.is_empty() {
+        return karaoke::plain_segments(&line.text, line_progress);
+    }
+    line.words
+        .iter()
+        .map(|word| {
+            let progress = if word.duration_ms > 0.0 {
+                ((position_ms - word.start_ms) / word.duration_ms).clamp(0.0, 1.0)
+            } else if position_ms >= word.start_ms {
+                1.0
+            } else {
+                0.0
+            };
+            (word.text.as_str(), progress)
+        })
+        .collect()
+}
+
+/// The stacked lines turned vertical: columns are laid out in reading order,
+/// previous line first, and placed right to left.
+fn lyric_columns<'a>(
+    timeline: &'a LyricTimeline,
+    current_index: Option<usize>,
+    position_ms: f32,
+    progress: f32,
+    appearance: &LyricsAppearance,
+    look: LinesLook,
+) -> Element<'a, Message> {
+    let plain = |content: &'a str, color: Color| {
+        vertical_line(
+            &[(content, 0.0)],
+            appearance.candidate_font_size,
+            color,
+            appearance,
+            look.opacity,
+        )
+    };
+    let mut columns = Vec::new();
+    let candidates_from = if let Some(index) = current_index {
+        if appearance.show_previous_line && index > 0 {
+            columns.push(plain(
+                &timeline.lines[index - 1].text,
+                dim_color(appearance.text, 0.55),
+            ));
+        }
+        columns.push(
+            Transformed::new(vertical_line(
+                &line_segments(&timeline.lines[index], position_ms, progress),
+                appearance.active_font_size,
+                dim_color(appearance.text, 0.68),
+                appearance,
+                look.opacity * look.active_opacity,
+            ))
+            .scale(look.active_scale)
+            .into(),
+        );
+        index + 1
+    } else {
+        columns.push(plain("等待第一句歌词…", appearance.text));
+        0
+    };
+    columns.extend(
+        timeline
+            .lines
+            .iter()
+            .skip(candidates_from)
+            .take(appearance.candidate_line_count)
+            .map(|line| plain(&line.text, dim_color(appearance.text, 0.78))),
+    );
+
+    let alignment = appearance.alignment.vertical();
+    container(
+        Row::with_children(columns.into_iter().rev())
+            .spacing(LINE_SPACING)
+            .align_y(alignment),
+    )
+    .width(Fill)
+    .height(Fill)
+    .center_x(Fill)
+    .align_y(alignment)
+    .into()
+}
+
+/// One line of vertical text, a glyph per cell with the wipe running down.
+fn vertical_line<'a>(
+    segments: &[(&'a str, f32)],
+    size: f32,
+    color: Color,
+    appearance: &LyricsAppearance,
+    opacity: f32,
+) -> Element<'a, Message> {
+    Column::with_children(karaoke::vertical_glyphs(segments).into_iter().map(
+        |(glyph, progress)| {
+            KaraokeText::new(glyph, progress, appearance.highlight_mode)
+                .downward()
+                .font(appearance.font.font())
+                .size(size)
+                .colors(color, appearance.highlight)
+                .effects(appearance.effects())
+                .opacity(opacity)
+                .into()
+        },
+    ))
+    .align_x(iced::Center)
+    .into()
+}
+
+/// Lines shown on the top and bottom rows of the two-line layout: even
+/// indices on top, odd ones below, so the line after the active one always
+/// takes the other row.
+fn two_line_rows(current_index: Option<usize>, line_count: usize) -> [Option<usize>; 2] {
+    let first = current_index.unwrap_or(0);
+    let mut rows = [None, None];
+    for index in (first..first + 2).filter(|index| *index < line_count) {
+        rows[index % 2] = Some(index);
+    }
+    rows
+}
+
+/// The active line and the one after it on alternating rows. Before the
+/// first line both rows show what is coming.
+fn two_line_view<'a>(
+    timeline: &'a LyricTimeline,
+    current_index: Option<usize>,
+    position_ms: f32,
+    progress: f32,
+    appearance: &LyricsAppearance,
+    upcoming_opacity: f32,
+) -> Element<'a, Message> {
+    let row_view = |index: Option<usize>, align_x| -> Element<'a, Message> {
+        let Some(index) = index else {
+            return space().into();
+        };
+        let (progress, opacity) = if Some(index) == current_index {
+            (progress, 1.0)
+        } else {
+            (0.0, upcoming_opacity)
+        };
+        current_line_view(
+            &timeline.lines[index],
+            position_ms,
+            progress,
+            appearance,
+            align_x,
+            opacity,
+        )
+    };
+    let [top, bottom] = two_line_rows(current_index, timeline.lines.len());
+    let top = row_view(top, iced::alignment::Horizontal::Left);
+    let bottom = row_view(bottom, iced::alignment::Horizontal::Right);
+
+    container(column![top, bottom].spacing(LINE_SPACING * 2.0).width(Fill))
+        .width(Fill)
+        .height(Fill)
+        .center_y(Fill)
+        .into()
+}
+
+fn parse_port(input: &str) -> Option<u16> {
+    input.trim().parse().ok().filter(|port| *port != 0)
+}
+
+fn parse_hex_color(input: &str) -> Option<Color> {
+    let hex = input.trim().strip_prefix('#').unwrap_or(input.trim());
+    if hex.len() != 6 || !hex.is_ascii() {
+        return None;
+    }
+
+    let red = u8::from_str_radix(&hex[0..2], 16).ok()?;
+    let green = u8::from_str_radix(&hex[2..4], 16).ok()?;
+    let blue = u8::from_str_radix(&hex[4..6], 16).ok()?;
+    Some(Color::from_rgb8(red, green, blue))
+}
+
+fn clamp_to(value: f32, range: &RangeInclusive<f32>) -> f32 {
+    value.clamp(*range.start(), *range.end())
+}
+
+/// A previous, candidate or placeholder line in the lyric window.
+fn lyric_line_text<'a>(
+    content: &'a str,
+    color: Color,
+    appearance: &LyricsAppearance,
+) -> KaraokeText<'a> {
+    KaraokeText::plain(content, color)
+        .font(appearance.font.font())
+        .size(appearance.candidate_font_size)
+        .width(Fill)
+        .align_x(appearance.alignment.horizontal())
+        .effects(appearance.effects())
+}
+
+fn setting_color(input: &str, fallback: &str) -> (String, Color) {
+    match parse_hex_color(input) {
+        Some(color) => (input.trim().to_owned(), color),
+        None => (
+            fallback.to_owned(),
+            parse_hex_color(fallback).unwrap_or(Color::BLACK),
+        ),
+    }
+}
+
+fn hex_color(color: Color) -> String {
+    let [red, green, blue, _] = color.into_rgba8();
+    format!("#{red:02X}{green:02X}{blue:02X}")
+}
+
+fn rgb(color: Color) -> Rgb {
+    let [red, green, blue, _] = color.into_rgba8();
+    Rgb::new(red, green, blue)
+}
+
+fn color_swatch(color: Color) -> Element<'static, Message> {
+    container(text(""))
+        .width(30)
+        .height(30)
+        .style(move |_| {
+            container::Style::default().background(color).border(
+                iced::border::rounded(4)
+                    .color(Color::from_rgb8(120, 124, 132))
+                    .width(1),
+            )
+        })
+        .into()
+}
+
+fn dim_color(color: Color, factor: f32) -> Color {
+    Color {
+        r: color.r * factor,
+        g: color.g * factor,
+        b: color.b * factor,
+        a: color.a,
+    }
+}
+
+/// Ticks at `interval` from a thread of its own; the executor has no timers.
+fn ticks(interval: &Duration) -> impl iced::futures::Stream<Item = ()> + use<> {
+    let interval = *interval;
+    iced::stream::channel(1, async move |mut output| {
+        let (finished_sender, finished) = iced::futures::channel::oneshot::channel();
+        let _ = std::thread::Builder::new()
+            .name("kg-capture-ticks".into())
+            .spawn(move || {
+                loop {
+                    std::thread::sleep(interval);
+                    if iced::futures::executor::block_on(output.send(())).is_err() {
+                        break;
+                    }
+                }
+                let _ = finished_sender.send(());
+            });
+        let _ = finished.await;
+    })
+}
+
+fn event_stream(
+    source: Arc<dyn EventSource>,
+) -> impl iced::futures::Stream<Item = Result<HookEvent, String>> {
+    iced::stream::channel(16, async move |mut output| {
+        let (finished_sender, finished) = iced::futures::channel::oneshot::channel();
+        let _ = std::thread::Builder::new()
+            .name("kg-capture-host-events".into())
+            .spawn(move || {
+                loop {
+                    let event = match source.next_event() {
+                        Ok(Some(event)) => Ok(event),
+                        Ok(None) => break,
+                        Err(error) => Err(error),
+                    };
+                    let disconnected = event.is_err();
+                    if iced::futures::executor::block_on(output.send(event)).is_err()
+                        || disconnected
+                    {
+                        break;
+                    }
+                }
+                let _ = finished_sender.send(());
+            });
+        let _ = finished.await;
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::collections::VecDeque;
+    use std::sync::Mutex;
+
+    /// Synthetic source that yields a fixed script and records host commands.
+    #[derive(Debug, Default)]
+    struct ScriptedSource {
+        events: Mutex<VecDeque<HookEvent>>,
+        commands: Mutex<Vec<HostCommand>>,
+    }
+
+    impl ScriptedSource {
+        fn new(events: impl IntoIterator<Item = HookEvent>) -> Arc<Self> {
+            Arc::new(Self {
+                events: Mutex::new(events.into_iter().collect()),
+                commands: Mutex::default(),
+            })
+        }
+    }
+
+    impl EventSource for ScriptedSource {
+        fn describe(&self) -> String {
+            "scripted".into()
+        }
+
+        fn next_event(&self) -> Result<Option<HookEvent>, String> {
+            Ok(self.events.lock().unwrap().pop_front())
+        }
+
+        fn send(&self, command: HostCommand) -> Result<(), String> {
+            self.commands.lock().unwrap().push(command);
+            Ok(())
+        }
+    }
+
+    fn playback(timeline_id: u64) -> PlaybackPosition {
+        PlaybackPosition {
+            timeline_id,
+            observed_at_micros: 0,
+            position_ms: 500.0,
+            current_line: Some(0),
+            line_progress: 0.25,
+        }
+    }
+
+    /// Settings as saved once pending changes have settled.
+    fn saved(app: &mut App) -> &Settings {
+        app.save_settings();
+        &app.saved_settings
+    }
+
+    fn connect(app: &mut App, source: Arc<ScriptedSource>) {
+        let _ = app.update(Message::Connected(Ok(source.clone())));
+        while let Ok(Some(event)) = source.next_event() {
+            let _ = app.update(Message::HookEvent(Ok(event)));
+        }
+    }
+
+    #[test]
+    fn synthetic_source_drives_the_host_state_machine() {
+        let (mut app, _) = App::with_settings(None, Settings::default());
+        let source = ScriptedSource::new([
+            HookEvent::CaptureStarted,
+            HookEvent::Timeline(fixtures::timeline(3, &[("把爱留在身边", 0.0, 2_000.0)])),
+            HookEvent::Playback(playback(3)),
+        ]);
+        connect(&mut app, source.clone());
+
+        assert!(matches!(
+            source.commands.lock().unwrap().as_slice(),
+            [HostCommand::StartCapture]
+        ));
+        assert_eq!(app.connection, ConnectionState::Streaming);
+        assert_eq!(app.timeline.as_ref().map(|timeline| timeline.id), Some(3));
+        assert_eq!(app.playback, Some(playback(3)));
+
+        let _ = app.update(Message::SourceFinished);
+        assert_eq!(app.connection, ConnectionState::Disconnected);
+        assert!(app.source.is_none());
+    }
+
+    #[test]
+    fn playback_for_another_timeline_is_ignored() {
+        let (mut app, _) = App::with_settings(None, Settings::default());
+        connect(
+            &mut app,
+            ScriptedSource::new([
+                HookEvent::Timeline(fixtures::timeline(3, &[("把爱留在身边", 0.0, 2_000.0)])),
+                HookEvent::Playback(playback(4)),
+            ]),
+        );
+
+        assert!(app.playback.is_none());
+    }
+
+    #[test]
+    fn advancing_a_line_starts_a_transition() {
+        let mut timeline = fixtures::timeline(3, &[("把爱留在身边", 0.0, 2_000.0)]);
+        let mut second = timeline.lines[0].clone();
+        second.index = 1;
+        second.start_ms = 2_000.0;
+        second.words.clear();
+        timeline.lines.push(second);
+        let (mut app, _) = App::with_settings(None, Settings::default());
+        connect(
+            &mut app,
+            ScriptedSource::new([
+                HookEvent::Timeline(timeline),
+                HookEvent::Playback(playback(3)),
+            ]),
+        );
+        assert_eq!(app.line_transition(Instant::now()), None);
+
+        let _ = app.update(Message::HookEvent(Ok(HookEvent::Playback(
+            PlaybackPosition {
+                position_ms: 2_100.0,
+                current_line: Some(1),
+                line_progress: 0.05,
+                ..playback(3)
+            },
+        ))));
+        assert_eq!(
+            app.line_transition(Instant::now()).map(|frame| frame.from),
+            Some(0)
+        );
+
+        let _ = app.update(Message::LineTransitionChanged(LineTransition::Off));
+        assert_eq!(app.line_transition(Instant::now()), None);
+        assert_eq!(saved(&mut app).appearance.transition, LineTransition::Off);
+    }
+
+    #[test]
+    fn playback_states_follow_the_current_timeline() {
+        let (mut app, _) = App::with_settings(None, Settings::default());
+        connect(
+            &mut app,
+            ScriptedSource::new([
+                HookEvent::Timeline(fixtures::timeline(3, &[("把爱留在身边", 0.0, 2_000.0)])),
+                HookEvent::Playback(playback(3)),
+                HookEvent::PlaybackState {
+                    timeline_id: 4,
+                    state: PlaybackState::Stopped,
+                },
+                HookEvent::PlaybackState {
+                    timeline_id: 3,
+                    state: PlaybackState::Paused,
+                },
+            ]),
+        );
+        assert_eq!(app.playback_state, Some(PlaybackState::Paused));
+        assert!(!app.clock.is_running(Instant::now()));
+
+        let _ = app.update(Message::HookEvent(Ok(HookEvent::PlaybackState {
+            timeline_id: 3,
+            state: PlaybackState::Playing,
+        })));
+        let _ = app.update(Message::HookEvent(Ok(HookEvent::Playback(playback(3)))));
+        assert!(app.clock.is_running(Instant::now()));
+
+        let _ = app.update(Message::HookEvent(Ok(HookEvent::Timeline(
+            fixtures::timeline(5, &[("把爱留在身边", 0.0, 2_000.0)]),
+        ))));
+        assert_eq!(app.playback_state, None);
+    }
+
+    #[test]
+    fn song_offsets_are_remembered_by_lyric_content() {
+        let (mut app, _) = App::with_settings(None, Settings::default());
+        connect(
+            &mut app,
+            ScriptedSource::new([HookEvent::Timeline(fixtures::timeline(
+                3,
+                &[("把爱留在身边", 0.0, 2_000.0)],
+            ))]),
+        );
+        let _ = app.update(Message::SongOffsetAdjusted(OFFSET_STEP_MS));
+        let _ = app.update(Message::GlobalOffsetAdjusted(-2 * OFFSET_STEP_MS));
+        assert_eq!(app.timing_offsets.total_ms(app.song_key), -OFFSET_STEP_MS);
+
+        // WeSing reloads the same song under a new timeline id.
+        let _ = app.update(Message::HookEvent(Ok(HookEvent::Timeline(
+            fixtures::timeline(4, &[("把爱留在身边", 0.0, 2_000.0)]),
+        ))));
+        assert_eq!(
+            app.song_key.map(|song| app.timing_offsets.song_ms(song)),
+            Some(OFFSET_STEP_MS)
+        );
+    }
+
+    #[test]
+    fn saved_settings_are_validated_and_tracked() {
+        let mut settings = Settings {
+            executable_path: r"D:\WeSing\WeSing.exe".into(),
+            ..Settings::default()
+        };
+        settings.appearance.background = "blue".into();
+        settings.appearance.highlight = "#00ff00".into();
+        settings.appearance.active_font_size = 500.0;
+        settings.appearance.candidate_line_count = 40;
+        settings.appearance.font_family = Some("Not Installed".into());
+        let (mut app, _) = App::with_settings(None, settings);
+
+        assert_eq!(app.executable_path, r"D:\WeSing\WeSing.exe");
+        assert_eq!(app.lyrics_appearance.background_input, "#292B2F");
+        assert_eq!(
+            app.lyrics_appearance.highlight,
+            Color::from_rgb8(0, 0xff, 0)
+        );
+        assert_eq!(app.lyrics_appearance.active_font_size, 96.0);
+        assert_eq!(app.lyrics_appearance.candidate_line_count, 10);
+        assert_eq!(app.lyrics_appearance.font, LyricsFont::System);
+
+        let _ = app.update(Message::LyricsAlignmentChanged(LyricsAlignment::Right));
+        assert_eq!(saved(&mut app).appearance.alignment, LyricsAlignment::Right);
+        assert_eq!(saved(&mut app).appearance.highlight, "#00FF00");
+        assert_eq!(saved(&mut app).appearance.font_family, None);
+    }
+
+    #[test]
+    fn settings_are_saved_once_changes_settle() {
+        let (mut app, _) = App::with_settings(None, Settings::default());
+        let _ = app.update(Message::Frame(Instant::now()));
+        let _ = app.update(Message::ObsPasswordChanged("secret".into()));
+        assert_eq!(app.pending_settings, None);
+
+        let _ = app.update(Message::ExecutablePathChanged(r"D:\WeSing".into()));
+        let _ = app.update(Message::SaveSettings);
+        assert!(app.pending_settings.is_some());
+        assert_eq!(app.saved_settings.executable_path, "");
+
+        if let Some((_, changed_at)) = &mut app.pending_settings {
+            *changed_at -= SETTINGS_SAVE_DELAY;
+        }
+        let _ = app.update(Message::SaveSettings);
+        assert_eq!(app.pending_settings, None);
+        assert_eq!(app.saved_settings.executable_path, r"D:\WeSing");
+    }
+
+    #[test]
+    fn overlays_are_restyled_only_by_style_messages() {
+        assert!(Message::GlobalOffsetAdjusted(OFFSET_STEP_MS).changes_lyrics_style());
+        assert!(Message::TextColorChanged("#FFFFFF".into()).changes_lyrics_style());
+        assert!(!Message::Frame(Instant::now()).changes_lyrics_style());
+        assert!(!Message::HookEvent(Ok(HookEvent::Playback(playback(3)))).changes_lyrics_style());
+        assert!(!Message::SavePreset.changes_lyrics_style());
+    }
+
+    #[test]
+    fn presets_are_saved_applied_and_deleted() {
+        let (mut app, _) = App::with_settings(None, Settings::default());
+        let _ = app.update(Message::PresetSelected("投影".into()));
+        assert_eq!(app.lyrics_appearance.background, Color::BLACK);
+        assert_eq!(app.preset_name, "投影");
+
+        // Built-in presets cannot be overwritten.
+        let _ = app.update(Message::SavePreset);
+        assert!(app.presets.is_empty());
+
+        let _ = app.update(Message::HighlightColorChanged("#00FF00".into()));
+        let _ = app.update(Message::PresetNameChanged("绿色投影".into()));
+        let _ = app.update(Message::SavePreset);
+        assert_eq!(saved(&mut app).presets.len(), 1);
+        assert_eq!(saved(&mut app).presets[0].appearance.highlight, "#00FF00");
+
+        let _ = app.update(Message::PresetSelected("OBS 深色".into()));
+        assert_eq!(app.lyrics_appearance.highlight_input, "#FFD54F");
+        let _ = app.update(Message::PresetSelected("绿色投影".into()));
+        assert_eq!(app.lyrics_appearance.highlight_input, "#00FF00");
+
+        let _ = app.update(Message::DeletePreset);
+        assert!(saved(&mut app).presets.is_empty());
+    }
+
+    #[test]
+    fn background_modes_choose_the_window_background() {
+        let mut settings = Settings::default();
+        settings.appearance.shadow_offset = 2.0;
+        settings.appearance.shadow_blur = 4.0;
+        settings.appearance.glow_radius = 6.0;
+        let (mut app, _) = App::with_settings(None, settings);
+        assert!(app.lyrics_appearance.effects().glow.is_some());
+
+        let _ = app.update(Message::BackgroundModeChanged(BackgroundMode::Transparent));
+        assert_eq!(
+            app.lyrics_appearance.window_background(),
+            Color::TRANSPARENT
+        );
+
+        let _ = app.update(Message::ChromaKeyColorChanged("#ff00ff".into()));
+        let _ = app.update(Message::BackgroundModeChanged(BackgroundMode::ChromaKey));
+        assert_eq!(
+            app.lyrics_appearance.window_background(),
+            Color::from_rgb8(0xff, 0, 0xff)
+        );
+        let effects = app.lyrics_appearance.effects();
+        assert!(effects.glow.is_none());
+        assert_eq!(
+            effects.outline,
+            Some(Outline {
+                width: KEYED_OUTLINE_WIDTH,
+                color: Color::BLACK,
+            })
+        );
+        assert_eq!(effects.shadow.map(|shadow| shadow.blur), Some(0.0));
+        let _ = app.update(Message::LineTransitionChanged(LineTransition::Crossfade));
+        assert_eq!(app.lyrics_appearance.line_transition(), LineTransition::Off);
+        let _ = app.update(Message::LineTransitionChanged(LineTransition::ScrollUp));
+        assert_eq!(
+            app.lyrics_appearance.line_transition(),
+            LineTransition::ScrollUp
+        );
+        assert_eq!(saved(&mut app).appearance.chroma_key_color, "#FF00FF");
+        assert_eq!(
+            saved(&mut app).appearance.background_mode,
+            BackgroundMode::ChromaKey
+        );
+        assert_eq!(saved(&mut app).appearance.glow_radius, 6.0);
+    }
+
+    #[test]
+    fn disconnect_shuts_down_the_source_and_clears_lyrics() {
+        let (mut app, _) = App::with_settings(None, Settings::default());
+        let source = ScriptedSource::new([HookEvent::Timeline(fixtures::timeline(
+            3,
+            &[("把爱留在身边", 0.0, 2_000.0)],
+        ))]);
+        connect(&mut app, source.clone());
+
+        let _ = app.update(Message::Disconnect);
+        assert!(matches!(
+            source.commands.lock().unwrap().as_slice(),
+            [HostCommand::StartCapture, HostCommand::Shutdown]
+        ));
+        assert_eq!(app.connection, ConnectionState::Disconnected);
+        assert!(app.timeline.is_none());
+
+        // A stream that ends after an explicit disconnect must not override the
+        // status of whatever source is connected next.
+        let _ = app.update(Message::SourceFinished);
+        assert_eq!(app.detail, "已断开连接。");
+    }
+
+    #[test]
+    fn text_files_follow_the_song_and_empty_on_disconnect() {
+        let directory =
+            std::env::temp_dir().join(format!("kg-capture-app-text-files-{}", std::process::id()));
+        std::fs::create_dir_all(&directory).unwrap();
+        let current = directory.join("current.txt");
+        let settings = Settings {
+            text_files: TextFileSettings {
+                current_path: current.to_string_lossy().into_owned(),
+                ..TextFileSettings::default()
+            },
+            ..Settings::default()
+        };
+        let (mut app, _) = App::with_settings(None, settings);
+        let wait_for = |expected: &str| {
+            let deadline = Instant::now() + Duration::from_secs(5);
+            while std::fs::read_to_string(&current).ok().as_deref() != Some(expected) {
+                assert!(
+                    Instant::now() < deadline,
+                    "text file never became {expected:?}"
+                );
+                std::thread::sleep(Duration::from_millis(10));
+            }
+        };
+
+        connect(
+            &mut app,
+            ScriptedSource::new([
+                HookEvent::Timeline(fixtures::timeline(3, &[("把爱留在身边", 0.0, 2_000.0)])),
+                HookEvent::Playback(playback(3)),
+            ]),
+        );
+        wait_for("把爱留在身边");
+
+        // A half-typed path is not used until it is submitted.
+        let typed = directory.join("typed.txt");
+        let _ = app.update(Message::TextFilePathChanged(
+            TextFile::Current,
+            typed.to_string_lossy().into_owned(),
+        ));
+        assert_eq!(app.text_files.current_path, current.to_string_lossy());
+
+        let _ = app.update(Message::Disconnect);
+        wait_for("");
+        assert!(!typed.exists());
+
+        let _ = app.update(Message::TextFilePathSubmitted(TextFile::Current));
+        assert_eq!(app.text_files.current_path, typed.to_string_lossy());
+        std::fs::remove_dir_all(directory).unwrap();
+    }
+
+    #[test]
+    fn lost_source_reports_failure() {
+        let (mut app, _) = App::with_settings(None, Settings::default());
+        connect(&mut app, ScriptedSource::new([HookEvent::CaptureStarted]));
+
+        let _ = app.update(Message::HookEvent(Err("hook disconnected".into())));
+        assert_eq!(app.connection, ConnectionState::Failed);
+        assert_eq!(app.detail, "hook disconnected");
+        assert!(app.source.is_none());
+    }
+
+    #[test]
+    fn two_line_rows_alternate_and_show_the_next_line() {
+        assert_eq!(two_line_rows(None, 4), [Some(0), Some(1)]);
+        assert_eq!(two_line_rows(Some(0), 4), [Some(0), Some(1)]);
+        assert_eq!(two_line_rows(Some(1), 4), [Some(2), Some(1)]);
+        assert_eq!(two_line_rows(Some(2), 4), [Some(2), Some(3)]);
+        assert_eq!(two_line_rows(Some(3), 4), [None, Some(3)]);
+        assert_eq!(two_line_rows(None, 1), [Some(0), None]);
+    }
+
+    #[test]
+    fn parses_six_digit_hex_colors() {
+        assert_eq!(
+            parse_hex_color("#1a2B3c"),
+            Some(Color::from_rgb8(0x1a, 0x2b, 0x3c))
+        );
+        assert_eq!(
+            parse_hex_color(" FFFFFF "),
+            Some(Color::from_rgb8(0xff, 0xff, 0xff))
+        );
+    }
+
+    #[test]
+    fn rejects_invalid_hex_colors() {
+        assert_eq!(parse_hex_color("#123"), None);
+        assert_eq!(parse_hex_color("#GG0000"), None);
+        assert_eq!(parse_hex_color("#12345678"), None);
+    }
+}
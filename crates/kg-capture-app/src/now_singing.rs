@@ -0,0 +1,328 @@
+//! "Now singing" text files for tools that watch files, such as OBS text
+//! sources reading from a file, Stream Deck plugins and chat bots. The files
+//! are rewritten whenever the shown line changes, on a thread of their own so
+//! a slow disk or a locked file never stalls the UI.
+
+use std::fs;
+use std::path::{Path, PathBuf};
+use std::sync::mpsc::{self, Receiver, RecvTimeoutError};
+use std::sync::{Arc, Mutex};
+use std::thread;
+use std::time::Duration;
+
+use kg_capture_lyrics::timing::timeline_end_ms;
+use kg_capture_protocol::LyricTimeline;
+use serde::{Deserialize, Serialize};
+
+use crate::obs::LyricTexts;
+use crate::practice::format_position;
+
+/// A reader that holds a file open without delete sharing blocks the rename
+/// on Windows for a moment, so it is retried a few times.
+const RENAME_ATTEMPTS: u32 = 5;
+const RENAME_RETRY_DELAY: Duration = Duration::from_millis(20);
+/// A file held for longer is written again this often until it is released
+/// or newer contents arrive.
+const FAILED_WRITE_RETRY_DELAY: Duration = Duration::from_millis(250);
+
+/// Output paths; an empty path is not written.
+#[derive(Clone, Debug, Default, PartialEq, Serialize, Deserialize)]
+#[serde(default)]
+pub struct TextFileSettings {
+    pub current_path: String,
+    pub next_path: String,
+    pub summary_path: String,
+}
+
+#[derive(Clone, Copy, Debug, Eq, PartialEq)]
+pub enum TextFile {
+    Current,
+    Next,
+    Summary,
+}
+
+impl TextFile {
+    pub const ALL: [Self; 3] = [Self::Current, Self::Next, Self::Summary];
+}
+
+impl std::fmt::Display for TextFile {
+    fn fmt(&self, formatter: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        formatter.write_str(match self {
+            Self::Current => "当前句文件",
+            Self::Next => "下一句文件",
+            Self::Summary => "歌曲概要文件",
+        })
+    }
+}
+
+impl TextFileSettings {
+    pub fn path(&self, file: TextFile) -> &str {
+        match file {
+            TextFile::Current => &self.current_path,
+            TextFile::Next => &self.next_path,
+            TextFile::Summary => &self.summary_path,
+        }
+    }
+
+    pub fn path_mut(&mut self, file: TextFile) -> &mut String {
+        match file {
+            TextFile::Current => &mut self.current_path,
+            TextFile::Next => &mut self.next_path,
+            TextFile::Summary => &mut self.summary_path,
+        }
+    }
+}
+
+/// The line count and song length, with the shown line's number and start
+/// time once singing has begun.
+pub fn summary(timeline: &LyricTimeline, current_line: Option<usize>) -> String {
+    let total = timeline.lines.len();
+    let duration = format_position(timeline_end_ms(timeline).unwrap_or(0.0));
+    match current_line.and_then(|index| Some((index, timeline.lines.get(index)?))) {
+        Some((index, line)) => format!(
+            "第 {}/{total} 句 · {} / {duration}",
+            index + 1,
+            format_position(line.start_ms)
+        ),
+        None => format!("共 {total} 句 · {duration}"),
+    }
+}
+
+/// Every configured file with its contents for `current_line`. Without a
+/// timeline the files are emptied.
+pub fn file_contents(
+    settings: &TextFileSettings,
+    timeline: Option<&LyricTimeline>,
+    current_line: Option<usize>,
+) -> Vec<(PathBuf, String)> {
+    let texts = timeline
+        .map(|timeline| LyricTexts::at(timeline, current_line))
+        .unwrap_or_default();
+    let summary = timeline
+        .map(|timeline| summary(timeline, current_line))
+        .unwrap_or_default();
+    TextFile::ALL
+        .into_iter()
+        .zip([texts.current, texts.next, summary])
+        .filter_map(|(file, contents)| {
+            let path = settings.path(file).trim();
+            (!path.is_empty()).then(|| (PathBuf::from(path), contents))
+        })
+        .collect()
+}
+
+/// Stops when dropped.
+pub struct TextFileWriter {
+    updates: mpsc::Sender<Vec<(PathBuf, String)>>,
+    error: Arc<Mutex<Option<String>>>,
+}
+
+impl TextFileWriter {
+    pub fn start() -> Result<Self, String> {
+        let (updates, receiver) = mpsc::channel();
+        let error = Arc::new(Mutex::new(None));
+        {
+            let error = Arc::clone(&error);
+            thread::Builder::new()
+                .name("now-singing-files".into())
+                .spawn(move || run(&receiver, &error))
+                .map_err(|error| format!("start text file writer: {error}"))?;
+        }
+        Ok(Self { updates, error })
+    }
+
+    /// Queues file contents. Only the latest queued contents are written,
+    /// and contents that cannot be written are retried until they are or
+    /// newer contents arrive.
+    pub fn write(&self, files: Vec<(PathBuf, String)>) {
+        let _ = self.updates.send(files);
+    }
+
+    /// Why the last write failed, until a write succeeds.
+    pub fn error(&self) -> Option<String> {
+        self.error.lock().ok().and_then(|error| error.clone())
+    }
+}
+
+fn run(updates: &Receiver<Vec<(PathBuf, String)>>, error: &Mutex<Option<String>>) {
+    let mut failed: Option<Vec<(PathBuf, String)>> = None;
+    loop {
+        let received = match failed.take() {
+            None => updates.recv().map_err(|_| RecvTimeoutError::Disconnected),
+            Some(files) => match updates.recv_timeout(FAILED_WRITE_RETRY_DELAY) {
+                Err(RecvTimeoutError::Timeout) => Ok(files),
+                received => received,
+            },
+        };
+        let Ok(mut files) = received else { return };
+        while let Ok(newer) = updates.try_recv() {
+            files = newer;
+        }
+        let failure = files
+            .iter()
+            .try_for_each(|(path, contents)| replace_file(path, contents))
+            .err();
+        if let Ok(mut error) = error.lock() {
+            // A file that stays held is retried without logging each attempt.
+            if let Some(failure) = failure
+                .as_ref()
+                .filter(|failure| error.as_ref() != Some(failure))
+            {
+                tracing::warn!(error = %failure, "failed to write text files");
+            }
+            error.clone_from(&failure);
+        }
+        if failure.is_some() {
+            failed = Some(files);
+        }
+    }
+}
+
+/// Written beside the target and renamed over it, so a reader sees either
+/// the old text or the new one, never a partial write.
+fn replace_file(path: &Path, contents: &str) -> Result<(), String> {
+    let mut temporary = path.as_os_str().to_owned();
+    temporary.push(".tmp");
+    let temporary = PathBuf::from(temporary);
+    fs::write(&temporary, contents)
+        .map_err(|error| format!("write {}: {error}", temporary.display()))?;
+    let mut attempt = 1;
+    loop {
+        match fs::rename(&temporary, path) {
+            Ok(()) => return Ok(()),
+            Err(_) if attempt < RENAME_ATTEMPTS => {
+                attempt += 1;
+                thread::sleep(RENAME_RETRY_DELAY);
+            }
+            Err(error) => {
+                let _ = fs::remove_file(&temporary);
+                return Err(format!("replace {}: {error}", path.display()));
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::fixtures;
+
+    fn temporary_directory(name: &str) -> PathBuf {
+        let directory = std::env::temp_dir().join(format!(
+            "kg-capture-now-singing-{name}-{}",
+            std::process::id()
+        ));
+        fs::create_dir_all(&directory).unwrap();
+        directory
+    }
+
+    /// Waits for the writer thread to make `condition` true.
+    fn eventually(condition: impl Fn() -> bool) -> bool {
+        let deadline = std::time::Instant::now() + Duration::from_secs(5);
+        while !condition() {
+            if std::time::Instant::now() > deadline {
+                return false;
+            }
+            thread::sleep(Duration::from_millis(10));
+        }
+        true
+    }
+
+    fn contents(path: &Path) -> String {
+        fs::read_to_string(path).unwrap_or_default()
+    }
+
+    #[test]
+    fn summarizes_the_song_and_the_shown_line() {
+        let timeline = fixtures::timeline(
+            1,
+            &[
+                ("把爱留在身边", 62_000.0, 4_000.0),
+                ("窗外有个蓝蓝的天", 66_000.0, 4_000.0),
+            ],
+        );
+        assert_eq!(summary(&timeline, None), "共 2 句 · 1:10");
+        assert_eq!(summary(&timeline, Some(1)), "第 2/2 句 · 1:06 / 1:10");
+    }
+
+    #[test]
+    fn writes_configured_files_and_skips_empty_paths() {
+        let timeline = fixtures::timeline(
+            1,
+            &[
+                ("把爱留在身边", 62_000.0, 4_000.0),
+                ("窗外有个蓝蓝的天", 66_000.0, 4_000.0),
+            ],
+        );
+        let directory = temporary_directory("paths");
+        let current = directory.join("current.txt");
+        let summary = directory.join("summary.txt");
+        let settings = TextFileSettings {
+            current_path: current.to_string_lossy().into_owned(),
+            next_path: String::new(),
+            summary_path: summary.to_string_lossy().into_owned(),
+        };
+        let writer = TextFileWriter::start().unwrap();
+
+        writer.write(file_contents(&settings, Some(&timeline), Some(0)));
+        assert!(eventually(|| contents(&current) == "把爱留在身边"));
+        writer.write(file_contents(&settings, Some(&timeline), Some(1)));
+        assert!(eventually(
+            || contents(&summary) == "第 2/2 句 · 1:06 / 1:10"
+        ));
+        assert_eq!(contents(&current), "窗外有个蓝蓝的天");
+        writer.write(file_contents(&settings, None, None));
+        assert!(eventually(|| contents(&current).is_empty()));
+        assert_eq!(writer.error(), None);
+        drop(writer);
+
+        let mut names: Vec<_> = fs::read_dir(&directory)
+            .unwrap()
+            .map(|entry| entry.unwrap().file_name())
+            .collect();
+        names.sort();
+        assert_eq!(names, ["current.txt", "summary.txt"]);
+        fs::remove_dir_all(directory).unwrap();
+    }
+
+    #[test]
+    fn held_files_are_retried_and_reported_until_written() {
+        let directory = temporary_directory("held");
+        // A directory in the way fails the rename the way a reader holding
+        // the file does on Windows.
+        let current = directory.join("current.txt");
+        fs::create_dir_all(current.join("held")).unwrap();
+        let writer = TextFileWriter::start().unwrap();
+
+        writer.write(vec![(current.clone(), "把爱留在身边".into())]);
+        assert!(eventually(|| writer.error().is_some()));
+        assert!(writer.error().unwrap().starts_with("replace "));
+
+        fs::remove_dir_all(&current).unwrap();
+        assert!(eventually(|| contents(&current) == "把爱留在身边"));
+        assert!(eventually(|| writer.error().is_none()));
+        drop(writer);
+        fs::remove_dir_all(directory).unwrap();
+    }
+
+    #[test]
+    fn only_the_latest_queued_contents_are_written() {
+        let directory = temporary_directory("latest");
+        let held = directory.join("held.txt");
+        fs::create_dir_all(held.join("held")).unwrap();
+        let skipped = directory.join("skipped.txt");
+        let latest = directory.join("latest.txt");
+        let writer = TextFileWriter::start().unwrap();
+
+        // The held file keeps the writer busy retrying the rename while the
+        // later contents queue up behind it, and is given up for them.
+        writer.write(vec![(held.clone(), "一".into())]);
+        writer.write(vec![(skipped.clone(), "二".into())]);
+        writer.write(vec![(latest.clone(), "三".into())]);
+        assert!(eventually(|| contents(&latest) == "三"));
+        assert!(!skipped.exists());
+        assert!(eventually(|| writer.error().is_none()));
+        drop(writer);
+        fs::remove_dir_all(directory).unwrap();
+    }
+}
@@ -0,0 +1,591 @@
+//! OBS WebSocket v5 client that shows the previous, current and next lyric
+//! lines in native OBS text sources, for setups that cannot capture the lyric
+//! window. It runs on its own thread, answers OBS's password challenge and
+//! reconnects when OBS restarts.
+
+use std::collections::HashMap;
+use std::net::{TcpStream, ToSocketAddrs};
+use std::sync::mpsc::{self, Receiver, RecvTimeoutError};
+use std::sync::{Arc, Mutex};
+use std::thread;
+use std::time::{Duration, Instant};
+
+use base64::prelude::{BASE64_STANDARD, Engine};
+use kg_capture_protocol::LyricTimeline;
+use serde::{Deserialize, Serialize};
+use serde_json::{Value, json};
+use sha2::{Digest, Sha256};
+use tungstenite::client::IntoClientRequest;
+use tungstenite::handshake::HandshakeError;
+use tungstenite::{Message, WebSocket};
+
+pub const DEFAULT_URL: &str = "ws://127.0.0.1:4455";
+const RPC_VERSION: u32 = 1;
+const RESPONSE_TIMEOUT: Duration = Duration::from_secs(5);
+/// Idle connections are checked this often, so a closed OBS is noticed
+/// before the next line.
+const KEEPALIVE_INTERVAL: Duration = Duration::from_secs(10);
+const MIN_RECONNECT_DELAY: Duration = Duration::from_secs(1);
+const MAX_RECONNECT_DELAY: Duration = Duration::from_secs(10);
+
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
+#[serde(default)]
+pub struct ObsSettings {
+    pub enabled: bool,
+    pub url: String,
+    /// Kept for the session only and never written to the settings file.
+    #[serde(skip)]
+    pub password: String,
+    /// Names of OBS text sources; an empty name leaves that line out.
+    pub previous_source: String,
+    pub current_source: String,
+    pub next_source: String,
+}
+
+impl Default for ObsSettings {
+    fn default() -> Self {
+        Self {
+            enabled: false,
+            url: DEFAULT_URL.into(),
+            password: String::new(),
+            previous_source: String::new(),
+            current_source: String::new(),
+            next_source: String::new(),
+        }
+    }
+}
+
+impl ObsSettings {
+    /// Every configured source with the text it should show.
+    pub fn source_texts(&self, texts: &LyricTexts) -> Vec<(String, String)> {
+        [
+            (&self.previous_source, &texts.previous),
+            (&self.current_source, &texts.current),
+            (&self.next_source, &texts.next),
+        ]
+        .into_iter()
+        .filter(|(source, _)| !source.trim().is_empty())
+        .map(|(source, text)| (source.trim().to_owned(), text.clone()))
+        .collect()
+    }
+}
+
+/// The lines around the active one. Before the first line only the next one
+/// is known.
+#[derive(Clone, Debug, Default, PartialEq)]
+pub struct LyricTexts {
+    pub previous: String,
+    pub current: String,
+    pub next: String,
+}
+
+impl LyricTexts {
+    pub fn at(timeline: &LyricTimeline, current_line: Option<usize>) -> Self {
+        let text = |index: Option<usize>| {
+            index
+                .and_then(|index| timeline.lines.get(index))
+                .map(|line| line.text.clone())
+                .unwrap_or_default()
+        };
+        Self {
+            previous: text(current_line.and_then(|index| index.checked_sub(1))),
+            current: text(current_line),
+            next: text(Some(current_line.map_or(0, |index| index + 1))),
+        }
+    }
+}
+
+#[derive(Clone, Debug, PartialEq)]
+pub enum ObsStatus {
+    Connecting,
+    Connected,
+    /// Connected, but OBS rejected the last update, usually because a source
+    /// name does not exist.
+    Rejected(String),
+    Failed(String),
+}
+
+impl std::fmt::Display for ObsStatus {
+    fn fmt(&self, formatter: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Self::Connecting => formatter.write_str("连接中"),
+            Self::Connected => formatter.write_str("已连接"),
+            Self::Rejected(message) => write!(formatter, "已连接 · {message}"),
+            Self::Failed(message) => write!(formatter, "连接失败：{message}"),
+        }
+    }
+}
+
+/// Stops when dropped.
+pub struct ObsClient {
+    updates: mpsc::Sender<Vec<(String, String)>>,
+    status: Arc<Mutex<ObsStatus>>,
+}
+
+impl ObsClient {
+    pub fn start(url: String, password: String) -> Result<Self, String> {
+        let (updates, receiver) = mpsc::channel();
+        let status = Arc::new(Mutex::new(ObsStatus::Connecting));
+        {
+            let status = Arc::clone(&status);
+            thread::Builder::new()
+                .name("obs-websocket".into())
+                .spawn(move || run(&url, &password, &receiver, &status))
+                .map_err(|error| format!("start OBS client: {error}"))?;
+        }
+        Ok(Self { updates, status })
+    }
+
+    pub fn status(&self) -> ObsStatus {
+        self.status
+            .lock()
+            .map_or(ObsStatus::Connecting, |status| status.clone())
+    }
+
+    /// Queues source texts. Only the latest queued texts are sent, and only
+    /// sources whose text changed.
+    pub fn update(&self, sources: Vec<(String, String)>) {
+        let _ = self.updates.send(sources);
+    }
+}
+
+fn run(
+    url: &str,
+    password: &str,
+    updates: &Receiver<Vec<(String, String)>>,
+    status: &Mutex<ObsStatus>,
+) {
+    let set_status = |new_status| {
+        if let Ok(mut status) = status.lock() {
+            *status = new_status;
+        }
+    };
+    let mut wanted = Vec::new();
+    let mut delay = Duration::ZERO;
+    loop {
+        if !wait(updates, &mut wanted, delay) {
+            return;
+        }
+        set_status(ObsStatus::Connecting);
+        let mut session = match Session::connect(url, password) {
+            Ok(session) => session,
+            Err(error) => {
+                tracing::debug!(%error, "failed to connect to OBS");
+                set_status(ObsStatus::Failed(error));
+                delay = (delay * 2).clamp(MIN_RECONNECT_DELAY, MAX_RECONNECT_DELAY);
+                continue;
+            }
+        };
+        tracing::info!(url, "connected to OBS");
+        set_status(ObsStatus::Connected);
+        // OBS was reachable a moment ago, so a restart is retried at once.
+        delay = Duration::ZERO;
+        match session.serve(updates, &mut wanted, &set_status) {
+            Ok(()) => {
+                let _ = session.socket.close(None);
+                let _ = session.socket.flush();
+                return;
+            }
+            Err(error) => {
+                tracing::warn!(%error, "lost the OBS connection");
+                set_status(ObsStatus::Failed(error));
+            }
+        }
+    }
+}
+
+/// Waits for `delay` while keeping the latest update. `false` once the client
+/// has been dropped.
+fn wait(
+    updates: &Receiver<Vec<(String, String)>>,
+    wanted: &mut Vec<(String, String)>,
+    delay: Duration,
+) -> bool {
+    let deadline = Instant::now() + delay;
+    loop {
+        match updates.recv_timeout(deadline.saturating_duration_since(Instant::now())) {
+            Ok(sources) => *wanted = sources,
+            Err(RecvTimeoutError::Timeout) => return true,
+            Err(RecvTimeoutError::Disconnected) => return false,
+        }
+    }
+}
+
+/// `base64(sha256(base64(sha256(password + salt)) + challenge))`, as the
+/// OBS WebSocket protocol specifies.
+fn authentication(password: &str, salt: &str, challenge: &str) -> String {
+    let secret = BASE64_STANDARD.encode(Sha256::digest(format!("{password}{salt}")));
+    BASE64_STANDARD.encode(Sha256::digest(format!("{secret}{challenge}")))
+}
+
+#[derive(Deserialize)]
+struct Envelope {
+    op: u8,
+    d: Value,
+}
+
+#[derive(Deserialize)]
+struct Hello {
+    authentication: Option<Challenge>,
+}
+
+#[derive(Deserialize)]
+struct Challenge {
+    challenge: String,
+    salt: String,
+}
+
+#[derive(Deserialize)]
+struct RequestResponse {
+    #[serde(rename = "requestId")]
+    request_id: String,
+    #[serde(rename = "requestStatus")]
+    status: RequestStatus,
+}
+
+#[derive(Deserialize)]
+struct RequestStatus {
+    result: bool,
+    code: u16,
+    comment: Option<String>,
+}
+
+mod op {
+    pub const HELLO: u8 = 0;
+    pub const IDENTIFY: u8 = 1;
+    pub const IDENTIFIED: u8 = 2;
+    pub const REQUEST: u8 = 6;
+    pub const REQUEST_RESPONSE: u8 = 7;
+}
+
+struct Session {
+    socket: WebSocket<TcpStream>,
+    next_request: u64,
+    /// Texts OBS has accepted on this connection.
+    shown: HashMap<String, String>,
+    /// Texts OBS rejected, with its reason, by source. They are retried on
+    /// the next keepalive or when the text changes.
+    refused: HashMap<String, (String, String)>,
+}
+
+impl Session {
+    /// Every step has a deadline, so an address that accepts connections but
+    /// never answers, such as a stuck OBS or another program on the port,
+    /// fails like a closed one.
+    fn connect(url: &str, password: &str) -> Result<Self, String> {
+        let request = url
+            .into_client_request()
+            .map_err(|error| error.to_string())?;
+        let uri = request.uri();
+        if uri.scheme_str() != Some("ws") {
+            return Err("only ws:// addresses are supported".into());
+        }
+        let host = uri.host().unwrap_or_default();
+        let host = host.trim_start_matches('[').trim_end_matches(']');
+        let port = uri.port_u16().unwrap_or(80);
+        let addresses = (host, port)
+            .to_socket_addrs()
+            .map_err(|error| format!("resolve {host}: {error}"))?;
+        let mut last_error = format!("{host} has no address");
+        let mut stream = None;
+        for address in addresses {
+            match TcpStream::connect_timeout(&address, RESPONSE_TIMEOUT) {
+                Ok(connected) => {
+                    stream = Some(connected);
+                    break;
+                }
+                Err(error) => last_error = format!("connect to {address}: {error}"),
+            }
+        }
+        let stream = stream.ok_or(last_error)?;
+        stream
+            .set_read_timeout(Some(RESPONSE_TIMEOUT))
+            .and_then(|()| stream.set_write_timeout(Some(RESPONSE_TIMEOUT)))
+            .map_err(|error| error.to_string())?;
+        let (socket, _) = tungstenite::client(request, stream).map_err(|error| match error {
+            // A blocking socket is only interrupted by its timeout.
+            HandshakeError::Interrupted(_) => "OBS did not answer the handshake".to_owned(),
+            HandshakeError::Failure(error) => error.to_string(),
+        })?;
+        let mut session = Self {
+            socket,
+            next_request: 0,
+            shown: HashMap::new(),
+            refused: HashMap::new(),
+        };
+
+        let hello: Hello = serde_json::from_value(session.read(op::HELLO)?)
+            .map_err(|error| format!("invalid Hello from OBS: {error}"))?;
+        let mut identify = json!({ "rpcVersion": RPC_VERSION, "eventSubscriptions": 0 });
+        if let Some(challenge) = hello.authentication {
+            if password.is_empty() {
+                return Err("OBS requires a password".into());
+            }
+            identify["authentication"] =
+                authentication(password, &challenge.salt, &challenge.challenge).into();
+        }
+        session.send(op::IDENTIFY, identify)?;
+        session.read(op::IDENTIFIED)?;
+        Ok(session)
+    }
+
+    /// Keeps OBS up to date until the client is dropped (`Ok`) or the
+    /// connection fails.
+    fn serve(
+        &mut self,
+        updates: &Receiver<Vec<(String, String)>>,
+        wanted: &mut Vec<(String, String)>,
+        set_status: &dyn Fn(ObsStatus),
+    ) -> Result<(), String> {
+        loop {
+            self.refused
+                .retain(|source, _| wanted.iter().any(|(wanted, _)| wanted == source));
+            for (source, text) in wanted.iter() {
+                if self.shown.get(source) == Some(text)
+                    || self
+                        .refused
+                        .get(source)
+                        .is_some_and(|(refused, _)| refused == text)
+                {
+                    continue;
+                }
+                let request = json!({
+                    "inputName": source,
+                    "inputSettings": { "text": text },
+                    "overlay": true,
+                });
+                match self.request("SetInputSettings", request)? {
+                    Ok(()) => {
+                        self.refused.remove(source);
+                        self.shown.insert(source.clone(), text.clone());
+                    }
+                    Err(comment) => {
+                        tracing::warn!(source, comment, "OBS rejected a text update");
+                        self.refused.insert(source.clone(), (text.clone(), comment));
+                    }
+                }
+            }
+            set_status(
+                self.refused
+                    .iter()
+                    .next()
+                    .map_or(ObsStatus::Connected, |(source, (_, comment))| {
+                        ObsStatus::Rejected(format!("{source}：{comment}"))
+                    }),
+            );
+
+            match updates.recv_timeout(KEEPALIVE_INTERVAL) {
+                Ok(sources) => *wanted = sources,
+                Err(RecvTimeoutError::Timeout) => {
+                    // A refused keepalive is treated like a broken connection.
+                    self.request("GetVersion", json!({}))??;
+                    self.refused.clear();
+                }
+                Err(RecvTimeoutError::Disconnected) => return Ok(()),
+            }
+            if let Some(latest) = updates.try_iter().last() {
+                *wanted = latest;
+            }
+        }
+    }
+
+    /// Sends a request and waits for its response. The outer error is a
+    /// broken connection; the inner one is OBS refusing the request.
+    fn request(&mut self, request_type: &str, data: Value) -> Result<Result<(), String>, String> {
+        self.next_request += 1;
+        let request_id = self.next_request.to_string();
+        self.send(
+            op::REQUEST,
+            json!({
+                "requestType": request_type,
+                "requestId": request_id,
+                "requestData": data,
+            }),
+        )?;
+        loop {
+            let response: RequestResponse =
+                serde_json::from_value(self.read(op::REQUEST_RESPONSE)?)
+                    .map_err(|error| format!("invalid response from OBS: {error}"))?;
+            if response.request_id != request_id {
+                continue;
+            }
+            let status = response.status;
+            return Ok(if status.result {
+                Ok(())
+            } else {
+                Err(status
+                    .comment
+                    .unwrap_or_else(|| format!("error {}", status.code)))
+            });
+        }
+    }
+
+    fn send(&mut self, op: u8, data: Value) -> Result<(), String> {
+        let message = json!({ "op": op, "d": data }).to_string();
+        self.socket
+            .send(Message::text(message))
+            .map_err(|error| error.to_string())
+    }
+
+    /// Reads messages until one with `op` arrives; events are skipped.
+    fn read(&mut self, op: u8) -> Result<Value, String> {
+        loop {
+            match self.socket.read().map_err(|error| error.to_string())? {
+                Message::Text(text) => {
+                    let envelope: Envelope = serde_json::from_str(&text)
+                        .map_err(|error| format!("invalid message from OBS: {error}"))?;
+                    if envelope.op == op {
+                        return Ok(envelope.d);
+                    }
+                }
+                Message::Close(frame) => {
+                    return Err(frame.filter(|frame| !frame.reason.is_empty()).map_or_else(
+                        || "OBS closed the connection".into(),
+                        |frame| frame.reason.to_string(),
+                    ));
+                }
+                _ => {}
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::fixtures;
+    use std::net::TcpListener;
+
+    const SALT: &str = "lM1GncleQOaCu9lT1yeUZhFYnqhsLLP1G5lAGo3ixaI=";
+    const CHALLENGE: &str = "+IxH4CnCiqpX1rM9scsNynZzbOe4KhDeYcTNS3PDaeY=";
+
+    #[test]
+    fn answers_the_documented_challenge() {
+        assert_eq!(
+            authentication("supersecretpassword", SALT, CHALLENGE),
+            "1Ct943GAT+6YQUUX47Ia/ncufilbe6+oD6lY+5kaCu4="
+        );
+    }
+
+    #[test]
+    fn the_password_is_not_saved() {
+        let settings = ObsSettings {
+            password: "supersecretpassword".into(),
+            ..ObsSettings::default()
+        };
+        let saved = serde_json::to_string(&settings).unwrap();
+        assert!(!saved.contains("supersecretpassword"));
+
+        let loaded: ObsSettings =
+            serde_json::from_str(r#"{"enabled": true, "password": "old"}"#).unwrap();
+        assert!(loaded.enabled);
+        assert_eq!(loaded.password, "");
+    }
+
+    #[test]
+    fn texts_follow_the_active_line() {
+        let timeline = fixtures::timeline(
+            1,
+            &[
+                ("一", 0.0, 1_000.0),
+                ("二", 1_000.0, 1_000.0),
+                ("三", 2_000.0, 1_000.0),
+            ],
+        );
+        let texts = |previous: &str, current: &str, next: &str| LyricTexts {
+            previous: previous.into(),
+            current: current.into(),
+            next: next.into(),
+        };
+        assert_eq!(LyricTexts::at(&timeline, None), texts("", "", "一"));
+        assert_eq!(LyricTexts::at(&timeline, Some(1)), texts("一", "二", "三"));
+        assert_eq!(LyricTexts::at(&timeline, Some(2)), texts("二", "三", ""));
+
+        let settings = ObsSettings {
+            current_source: " 当前歌词 ".into(),
+            ..ObsSettings::default()
+        };
+        assert_eq!(
+            settings.source_texts(&texts("一", "二", "三")),
+            [("当前歌词".to_owned(), "二".to_owned())]
+        );
+    }
+
+    /// Stands in for OBS: asks for a password, reports each text update and
+    /// drops the first connection after its first request, like a restart.
+    fn stub_obs(listener: TcpListener, reports: mpsc::Sender<Value>) {
+        for connection in 0..2 {
+            let (stream, _) = listener.accept().unwrap();
+            let mut socket = tungstenite::accept(stream).unwrap();
+            let hello = json!({ "op": 0, "d": {
+                "obsWebSocketVersion": "5.5.0",
+                "rpcVersion": 1,
+                "authentication": { "challenge": CHALLENGE, "salt": SALT },
+            }});
+            socket.send(Message::text(hello.to_string())).unwrap();
+            let identify: Value =
+                serde_json::from_str(socket.read().unwrap().to_text().unwrap()).unwrap();
+            reports
+                .send(identify["d"]["authentication"].clone())
+                .unwrap();
+            let identified = json!({ "op": 2, "d": { "negotiatedRpcVersion": 1 } });
+            socket.send(Message::text(identified.to_string())).unwrap();
+
+            while let Ok(message) = socket.read() {
+                let Ok(text) = message.to_text() else { break };
+                let request: Value = serde_json::from_str(text).unwrap();
+                reports.send(request["d"]["requestData"].clone()).unwrap();
+                if connection == 0 {
+                    break;
+                }
+                let response = json!({ "op": 7, "d": {
+                    "requestType": request["d"]["requestType"],
+                    "requestId": request["d"]["requestId"],
+                    "requestStatus": { "result": true, "code": 100 },
+                }});
+                socket.send(Message::text(response.to_string())).unwrap();
+            }
+        }
+    }
+
+    #[test]
+    fn updates_text_sources_and_reconnects_after_a_restart() {
+        let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+        let url = format!("ws://{}", listener.local_addr().unwrap());
+        let (reports, received) = mpsc::channel();
+        thread::spawn(move || stub_obs(listener, reports));
+        let next = || received.recv_timeout(RESPONSE_TIMEOUT).unwrap();
+        let update = |text: &str| {
+            json!({
+                "inputName": "当前歌词",
+                "inputSettings": { "text": text },
+                "overlay": true,
+            })
+        };
+
+        let client = ObsClient::start(url, "supersecretpassword".into()).unwrap();
+        client.update(vec![("当前歌词".into(), "第一句".into())]);
+        assert_eq!(next(), "1Ct943GAT+6YQUUX47Ia/ncufilbe6+oD6lY+5kaCu4=");
+        assert_eq!(next(), update("第一句"));
+
+        // The unanswered update is sent again on the new connection.
+        assert_eq!(next(), "1Ct943GAT+6YQUUX47Ia/ncufilbe6+oD6lY+5kaCu4=");
+        assert_eq!(next(), update("第一句"));
+        client.update(vec![("当前歌词".into(), "第二句".into())]);
+        assert_eq!(next(), update("第二句"));
+        assert_eq!(client.status(), ObsStatus::Connected);
+    }
+
+    #[test]
+    fn connecting_gives_up_on_a_server_that_never_answers() {
+        let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+        let url = format!("ws://{}", listener.local_addr().unwrap());
+        let started = Instant::now();
+        let Err(error) = Session::connect(&url, "") else {
+            panic!("connected to a silent server");
+        };
+        assert_eq!(error, "OBS did not answer the handshake");
+        assert!(started.elapsed() < RESPONSE_TIMEOUT * 2);
+        drop(listener);
+    }
+}
@@ -0,0 +1,182 @@
+//! Lyric timing offsets. The OBS capture pipeline can delay audio and some
+//! WeSing songs carry consistently early lyrics, so the highlight may lead or
+//! lag the reported position, globally and per song.
+
+use std::collections::BTreeMap;
+
+use serde::{Deserialize, Serialize};
+
+use kg_capture_lyrics::timing::locate;
+use kg_capture_protocol::{LyricTimeline, PlaybackPosition};
+
+/// Change applied by one press of an offset button or hotkey.
+pub const OFFSET_STEP_MS: i32 = 50;
+pub const MAX_OFFSET_MS: i32 = 10_000;
+
+/// Offsets in milliseconds. Positive values make the highlight lead the
+/// reported position, negative values delay it. The per-song offset adds to
+/// the global one.
+#[derive(Clone, Debug, Default, PartialEq, Serialize, Deserialize)]
+#[serde(default)]
+pub struct TimingOffsets {
+    pub global_ms: i32,
+    songs: BTreeMap<u64, i32>,
+}
+
+impl TimingOffsets {
+    pub fn song_ms(&self, song: u64) -> i32 {
+        self.songs.get(&song).copied().unwrap_or(0)
+    }
+
+    /// Offset for a song, or the global offset alone without one.
+    pub fn total_ms(&self, song: Option<u64>) -> i32 {
+        self.global_ms + song.map_or(0, |song| self.song_ms(song))
+    }
+
+    pub fn adjust_global(&mut self, delta_ms: i32) {
+        self.global_ms = clamp_offset(self.global_ms + delta_ms);
+    }
+
+    pub fn adjust_song(&mut self, song: u64, delta_ms: i32) {
+        let offset_ms = clamp_offset(self.song_ms(song) + delta_ms);
+        if offset_ms == 0 {
+            self.songs.remove(&song);
+        } else {
+            self.songs.insert(song, offset_ms);
+        }
+    }
+
+    pub fn reset_song(&mut self, song: u64) {
+        self.songs.remove(&song);
+    }
+
+    /// Brings offsets read from a settings file back into range.
+    pub fn clamped(&self) -> Self {
+        Self {
+            global_ms: clamp_offset(self.global_ms),
+            songs: self
+                .songs
+                .iter()
+                .map(|(song, offset_ms)| (*song, clamp_offset(*offset_ms)))
+                .filter(|(_, offset_ms)| *offset_ms != 0)
+                .collect(),
+        }
+    }
+}
+
+fn clamp_offset(offset_ms: i32) -> i32 {
+    offset_ms.clamp(-MAX_OFFSET_MS, MAX_OFFSET_MS)
+}
+
+/// Identifies a song by its lyric content, so an offset is found again when
+/// WeSing reloads the song under a new timeline id. FNV-1a keeps the key
+/// stable across builds.
+pub fn song_key(timeline: &LyricTimeline) -> u64 {
+    const PRIME: u64 = 0x0000_0100_0000_01b3;
+    let mut hash: u64 = 0xcbf2_9ce4_8422_2325;
+    let mut write = |bytes: &[u8]| {
+        for byte in bytes {
+            hash = (hash ^ u64::from(*byte)).wrapping_mul(PRIME);
+        }
+    };
+    for line in &timeline.lines {
+        write(line.text.as_bytes());
+        write(&[0]);
+        write(&(line.start_ms.round() as i64).to_le_bytes());
+        write(&(line.duration_ms.round() as i64).to_le_bytes());
+        for word in &line.words {
+            write(word.text.as_bytes());
+            write(&[0]);
+            write(&(word.start_ms.round() as i64).to_le_bytes());
+        }
+    }
+    hash
+}
+
+/// Moves a position by an offset and finds the line sung at the new position.
+pub fn shift_playback(
+    timeline: &LyricTimeline,
+    playback: &PlaybackPosition,
+    offset_ms: i32,
+) -> PlaybackPosition {
+    if offset_ms == 0 {
+        return playback.clone();
+    }
+    let position_ms = playback.position_ms + offset_ms as f32;
+    let position = locate(timeline, position_ms);
+    PlaybackPosition {
+        position_ms,
+        current_line: position.line,
+        line_progress: position.progress,
+        ..playback.clone()
+    }
+}
+
+/// `+120 ms` style display of an offset.
+pub fn format_offset(offset_ms: i32) -> String {
+    format!("{offset_ms:+} ms")
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::fixtures;
+
+    #[test]
+    fn song_keys_follow_content_not_timeline_ids() {
+        let song = song_key(&fixtures::timeline(1, &[("把爱留在身边", 0.0, 1_000.0)]));
+        assert_eq!(
+            song_key(&fixtures::timeline(2, &[("把爱留在身边", 0.0, 1_000.0)])),
+            song
+        );
+        assert_ne!(
+            song_key(&fixtures::timeline(
+                1,
+                &[("窗外有个蓝蓝的天", 0.0, 1_000.0)]
+            )),
+            song
+        );
+    }
+
+    #[test]
+    fn song_offsets_add_to_the_global_offset() {
+        let mut offsets = TimingOffsets::default();
+        offsets.adjust_global(-OFFSET_STEP_MS);
+        offsets.adjust_song(7, 3 * OFFSET_STEP_MS);
+        assert_eq!(offsets.total_ms(Some(7)), 100);
+        assert_eq!(offsets.total_ms(Some(8)), -50);
+        assert_eq!(offsets.total_ms(None), -50);
+
+        offsets.adjust_song(7, -3 * OFFSET_STEP_MS);
+        assert!(offsets.songs.is_empty());
+
+        offsets.adjust_global(i32::MAX / 2);
+        assert_eq!(offsets.global_ms, MAX_OFFSET_MS);
+    }
+
+    #[test]
+    fn shifted_playback_moves_to_the_offset_line() {
+        let timeline = fixtures::timeline(
+            1,
+            &[
+                ("把爱留在身边", 0.0, 1_000.0),
+                ("窗外有个蓝蓝的天", 1_000.0, 1_000.0),
+            ],
+        );
+        let playback = PlaybackPosition {
+            timeline_id: 1,
+            observed_at_micros: 0,
+            position_ms: 900.0,
+            current_line: Some(0),
+            line_progress: 0.9,
+        };
+
+        assert_eq!(shift_playback(&timeline, &playback, 0), playback);
+        let shifted = shift_playback(&timeline, &playback, 350);
+        assert_eq!(shifted.position_ms, 1_250.0);
+        assert_eq!(shifted.current_line, Some(1));
+        assert_eq!(shifted.line_progress, 0.25);
+        assert_eq!(format_offset(350), "+350 ms");
+        assert_eq!(format_offset(-50), "-50 ms");
+    }
+}
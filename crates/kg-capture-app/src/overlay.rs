@@ -0,0 +1,176 @@
+//! The browser-source overlay page, served over plain HTTP on the WebSocket
+//! server's port so OBS needs a single URL. The page connects back to the
+//! same address for lyrics and draws them with CSS, taking its look from the
+//! lyric window's appearance.
+
+use std::io::{self, ErrorKind, Read, Write};
+use std::net::TcpStream;
+use std::thread;
+use std::time::{Duration, Instant};
+
+const INDEX_HTML: &str = include_str!("../overlay/index.html");
+const OVERLAY_CSS: &str = include_str!("../overlay/overlay.css");
+const OVERLAY_JS: &str = include_str!("../overlay/overlay.js");
+
+const MAX_REQUEST_HEAD: usize = 8 * 1024;
+const PEEK_INTERVAL: Duration = Duration::from_millis(10);
+
+/// The request line and the headers that decide between a page, a WebSocket
+/// and a refusal.
+#[derive(Debug, PartialEq)]
+pub struct Request {
+    pub method: String,
+    pub path: String,
+    pub upgrade: bool,
+    /// Sent by a page from another site. Requests without an `Origin`, from
+    /// a local file (`null`) or from a page this server served are not.
+    pub cross_origin: bool,
+    head_length: usize,
+}
+
+/// Reads the request head without consuming it, so a WebSocket handshake can
+/// still read the whole request.
+pub fn peek_request(stream: &TcpStream, timeout: Duration) -> io::Result<Request> {
+    let deadline = Instant::now() + timeout;
+    let mut buffer = vec![0; MAX_REQUEST_HEAD];
+    loop {
+        let read = stream.peek(&mut buffer)?;
+        if read == 0 {
+            return Err(ErrorKind::UnexpectedEof.into());
+        }
+        if let Some(end) = buffer[..read]
+            .windows(4)
+            .position(|window| window == b"\r\n\r\n")
+        {
+            return parse_request(&buffer[..end + 4]);
+        }
+        if read == buffer.len() {
+            return Err(io::Error::new(
+                ErrorKind::InvalidData,
+                "request head too large",
+            ));
+        }
+        if Instant::now() >= deadline {
+            return Err(ErrorKind::TimedOut.into());
+        }
+        thread::sleep(PEEK_INTERVAL);
+    }
+}
+
+fn parse_request(head: &[u8]) -> io::Result<Request> {
+    let invalid = || io::Error::new(ErrorKind::InvalidData, "malformed HTTP request");
+    let head = std::str::from_utf8(head).map_err(|_| invalid())?;
+    let mut lines = head.split("\r\n");
+    let mut request_line = lines.next().ok_or_else(invalid)?.split_whitespace();
+    let (Some(method), Some(target)) = (request_line.next(), request_line.next()) else {
+        return Err(invalid());
+    };
+    let headers: Vec<_> = lines
+        .filter_map(|line| line.split_once(':'))
+        .map(|(name, value)| (name.trim(), value.trim()))
+        .collect();
+    let header = |wanted: &str| {
+        headers
+            .iter()
+            .find(|(name, _)| name.eq_ignore_ascii_case(wanted))
+            .map(|(_, value)| *value)
+    };
+    let upgrade = header("upgrade").is_some_and(|value| value.eq_ignore_ascii_case("websocket"));
+    let cross_origin = match header("origin") {
+        None | Some("null") => false,
+        Some(origin) => {
+            let site = origin
+                .strip_prefix("http://")
+                .or_else(|| origin.strip_prefix("https://"));
+            site.zip(header("host"))
+                .is_none_or(|(site, host)| !site.eq_ignore_ascii_case(host))
+        }
+    };
+    Ok(Request {
+        method: method.into(),
+        path: target.split(['?', '#']).next().unwrap_or_default().into(),
+        upgrade,
+        cross_origin,
+        head_length: head.len(),
+    })
+}
+
+/// Consumes the peeked request and answers it. Every response closes the
+/// connection. A WebSocket upgrade only gets here from another site's page,
+/// which is refused so it cannot read the lyrics.
+pub fn respond(mut stream: &TcpStream, request: &Request) -> io::Result<()> {
+    let mut head = vec![0; request.head_length];
+    stream.read_exact(&mut head)?;
+    let (status, content_type, body) = if request.upgrade {
+        ("403 Forbidden", "text/plain; charset=utf-8", "forbidden")
+    } else {
+        route(&request.method, &request.path)
+    };
+    write!(
+        stream,
+        "HTTP/1.1 {status}\r\nContent-Type: {content_type}\r\nContent-Length: {}\r\n\
+         Cache-Control: no-store\r\nConnection: close\r\n\r\n",
+        body.len()
+    )?;
+    if request.method != "HEAD" {
+        stream.write_all(body.as_bytes())?;
+    }
+    stream.flush()
+}
+
+fn route(method: &str, path: &str) -> (&'static str, &'static str, &'static str) {
+    if method != "GET" && method != "HEAD" {
+        return ("405 Method Not Allowed", "text/plain; charset=utf-8", "");
+    }
+    match path {
+        "/" | "/index.html" => ("200 OK", "text/html; charset=utf-8", INDEX_HTML),
+        "/overlay.css" => ("200 OK", "text/css; charset=utf-8", OVERLAY_CSS),
+        "/overlay.js" => ("200 OK", "text/javascript; charset=utf-8", OVERLAY_JS),
+        _ => ("404 Not Found", "text/plain; charset=utf-8", "not found"),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn parses_page_requests_and_websocket_upgrades() {
+        let page =
+            parse_request(b"GET /overlay.js?v=2 HTTP/1.1\r\nHost: localhost\r\n\r\n").unwrap();
+        assert_eq!(page.method, "GET");
+        assert_eq!(page.path, "/overlay.js");
+        assert!(!page.upgrade);
+
+        let upgrade = parse_request(
+            b"GET / HTTP/1.1\r\nHost: localhost\r\nConnection: Upgrade\r\nUpgrade: WebSocket\r\n\r\n",
+        )
+        .unwrap();
+        assert!(upgrade.upgrade);
+        assert!(!upgrade.cross_origin);
+
+        assert!(parse_request(b"\r\n\r\n").is_err());
+    }
+
+    #[test]
+    fn only_other_sites_are_cross_origin() {
+        let cross_origin = |origin: &str| {
+            let head = format!("GET / HTTP/1.1\r\nHost: 127.0.0.1:47800\r\n{origin}\r\n\r\n");
+            parse_request(head.as_bytes()).unwrap().cross_origin
+        };
+        assert!(!cross_origin("Origin: null"));
+        assert!(!cross_origin("Origin: http://127.0.0.1:47800"));
+        assert!(!cross_origin("X-Other: none"));
+        assert!(cross_origin("Origin: https://example.com"));
+        assert!(cross_origin("Origin: http://127.0.0.1:8080"));
+        assert!(cross_origin("origin: chrome-extension://abc"));
+    }
+
+    #[test]
+    fn routes_the_overlay_assets() {
+        assert_eq!(route("GET", "/").2, INDEX_HTML);
+        assert_eq!(route("GET", "/overlay.css").1, "text/css; charset=utf-8");
+        assert_eq!(route("GET", "/missing").0, "404 Not Found");
+        assert_eq!(route("POST", "/").0, "405 Method Not Allowed");
+    }
+}
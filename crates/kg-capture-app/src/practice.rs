@@ -0,0 +1,344 @@
+//! Practice mode: an imported lyric file played from the host's own clock, so
+//! singers can rehearse with the KG Lyrics window without WeSing.
+
+use std::collections::VecDeque;
+use std::fs;
+use std::path::{Path, PathBuf};
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::{Arc, Mutex, MutexGuard, PoisonError};
+use std::thread;
+use std::time::{Duration, Instant};
+
+use kg_capture_lyrics::timing::timeline_end_ms;
+use kg_capture_lyrics::{lrc, ttml, webvtt};
+use kg_capture_protocol::{HookEvent, HostCommand, LyricSource, LyricTimeline, PlaybackState};
+
+use crate::source::{EventSource, clock_playback};
+
+pub const MIN_PRACTICE_SPEED: f32 = 0.5;
+pub const MAX_PRACTICE_SPEED: f32 = 2.0;
+const PRACTICE_INTERVAL: Duration = Duration::from_millis(33);
+
+/// File extensions offered by the practice file dialog.
+pub const PRACTICE_EXTENSIONS: [&str; 4] = ["lrc", "ttml", "xml", "vtt"];
+
+/// Reads a lyric file into an imported timeline, choosing the parser from the
+/// extension. Anything unrecognised is read as LRC.
+pub fn load_timeline(path: &Path) -> Result<LyricTimeline, String> {
+    let contents = fs::read_to_string(path)
+        .map_err(|error| format!("read lyrics {}: {error}", path.display()))?;
+    let extension = path
+        .extension()
+        .and_then(|extension| extension.to_str())
+        .map(str::to_ascii_lowercase);
+    let lines = match extension.as_deref() {
+        Some("ttml" | "xml") => ttml::parse_ttml(&contents)?,
+        Some("vtt") => webvtt::parse_webvtt(&contents)?,
+        _ => return lrc::parse_lrc(&contents),
+    };
+    if lines.is_empty() {
+        return Err(format!("{} contains no lyric lines", path.display()));
+    }
+    Ok(LyricTimeline {
+        id: 0,
+        source: LyricSource::Imported,
+        lines,
+    })
+}
+
+/// Play, pause, seek and speed state shared by the practice controls and the
+/// practice source. Position never runs past the end of the timeline.
+#[derive(Debug)]
+pub struct PracticeClock {
+    duration_ms: f32,
+    state: Mutex<ClockState>,
+}
+
+#[derive(Clone, Copy, Debug)]
+struct ClockState {
+    position_ms: f32,
+    resumed_at: Option<Instant>,
+    speed: f32,
+}
+
+impl ClockState {
+    fn position_at(&self, now: Instant, duration_ms: f32) -> f32 {
+        let elapsed = self.resumed_at.map_or(0.0, |resumed_at| {
+            now.saturating_duration_since(resumed_at).as_secs_f32() * 1_000.0 * self.speed
+        });
+        (self.position_ms + elapsed).min(duration_ms)
+    }
+}
+
+impl PracticeClock {
+    pub fn new(duration_ms: f32, speed: f32) -> Self {
+        Self {
+            duration_ms: duration_ms.max(0.0),
+            state: Mutex::new(ClockState {
+                position_ms: 0.0,
+                resumed_at: None,
+                speed: speed.clamp(MIN_PRACTICE_SPEED, MAX_PRACTICE_SPEED),
+            }),
+        }
+    }
+
+    pub fn duration_ms(&self) -> f32 {
+        self.duration_ms
+    }
+
+    pub fn position_ms(&self) -> f32 {
+        self.position_at(Instant::now())
+    }
+
+    /// Playing until paused or until the end of the timeline is reached.
+    pub fn is_playing(&self) -> bool {
+        let state = *self.state();
+        state.resumed_at.is_some()
+            && state.position_at(Instant::now(), self.duration_ms) < self.duration_ms
+    }
+
+    /// The state a WeSing capture would report for the clock.
+    pub fn playback_state(&self) -> PlaybackState {
+        let state = *self.state();
+        if state.position_at(Instant::now(), self.duration_ms) >= self.duration_ms {
+            PlaybackState::SongEnded
+        } else if state.resumed_at.is_some() {
+            PlaybackState::Playing
+        } else {
+            PlaybackState::Paused
+        }
+    }
+
+    /// Pauses a playing clock, or resumes a paused one. Resuming at the end
+    /// starts over.
+    pub fn toggle(&self) {
+        self.toggle_at(Instant::now());
+    }
+
+    pub fn seek(&self, position_ms: f32) {
+        self.seek_at(position_ms, Instant::now());
+    }
+
+    pub fn set_speed(&self, speed: f32) {
+        self.set_speed_at(speed, Instant::now());
+    }
+
+    fn position_at(&self, now: Instant) -> f32 {
+        self.state().position_at(now, self.duration_ms)
+    }
+
+    fn toggle_at(&self, now: Instant) {
+        let mut state = self.state();
+        let position_ms = state.position_at(now, self.duration_ms);
+        if state.resumed_at.is_some() && position_ms < self.duration_ms {
+            state.position_ms = position_ms;
+            state.resumed_at = None;
+        } else {
+            if position_ms >= self.duration_ms {
+                state.position_ms = 0.0;
+            }
+            state.resumed_at = Some(now);
+        }
+    }
+
+    fn seek_at(&self, position_ms: f32, now: Instant) {
+        let mut state = self.state();
+        state.position_ms = position_ms.clamp(0.0, self.duration_ms);
+        if state.resumed_at.is_some() {
+            state.resumed_at = Some(now);
+        }
+    }
+
+    fn set_speed_at(&self, speed: f32, now: Instant) {
+        let mut state = self.state();
+        state.position_ms = state.position_at(now, self.duration_ms);
+        if state.resumed_at.is_some() {
+            state.resumed_at = Some(now);
+        }
+        state.speed = speed.clamp(MIN_PRACTICE_SPEED, MAX_PRACTICE_SPEED);
+    }
+
+    fn state(&self) -> MutexGuard<'_, ClockState> {
+        self.state.lock().unwrap_or_else(PoisonError::into_inner)
+    }
+}
+
+/// Reports the practice clock as playback positions at about 30 Hz.
+#[derive(Debug)]
+pub struct PracticeSource {
+    path: PathBuf,
+    timeline: LyricTimeline,
+    clock: Arc<PracticeClock>,
+    pending: Mutex<VecDeque<HookEvent>>,
+    reported_state: Mutex<Option<PlaybackState>>,
+    started: AtomicBool,
+    stopped: AtomicBool,
+}
+
+impl PracticeSource {
+    pub fn new(path: PathBuf, timeline: LyricTimeline, clock: Arc<PracticeClock>) -> Self {
+        Self {
+            path,
+            timeline,
+            clock,
+            pending: Mutex::new(VecDeque::new()),
+            reported_state: Mutex::new(None),
+            started: AtomicBool::new(false),
+            stopped: AtomicBool::new(false),
+        }
+    }
+}
+
+impl EventSource for PracticeSource {
+    fn describe(&self) -> String {
+        format!(
+            "正在练习 {}（{} 行歌词）。",
+            self.path.display(),
+            self.timeline.lines.len()
+        )
+    }
+
+    fn next_event(&self) -> Result<Option<HookEvent>, String> {
+        loop {
+            if self.stopped.load(Ordering::Acquire) {
+                return Ok(None);
+            }
+            if let Some(event) = self
+                .pending
+                .lock()
+                .ok()
+                .and_then(|mut queue| queue.pop_front())
+            {
+                return Ok(Some(event));
+            }
+            thread::sleep(PRACTICE_INTERVAL);
+            if self.started.load(Ordering::Acquire) {
+                let state = self.clock.playback_state();
+                let mut reported_state = self
+                    .reported_state
+                    .lock()
+                    .map_err(|_| "practice state lock was poisoned".to_owned())?;
+                if reported_state.replace(state) != Some(state) {
+                    return Ok(Some(HookEvent::PlaybackState {
+                        timeline_id: self.timeline.id,
+                        state,
+                    }));
+                }
+                return Ok(Some(HookEvent::Playback(clock_playback(
+                    &self.timeline,
+                    self.clock.position_ms(),
+                ))));
+            }
+        }
+    }
+
+    fn send(&self, command: HostCommand) -> Result<(), String> {
+        let mut pending = self
+            .pending
+            .lock()
+            .map_err(|_| "practice queue lock was poisoned".to_owned())?;
+        match command {
+            HostCommand::StartCapture => {
+                self.started.store(true, Ordering::Release);
+                pending.push_back(HookEvent::CaptureStarted);
+                pending.push_back(HookEvent::Timeline(self.timeline.clone()));
+            }
+            HostCommand::StopCapture => {
+                self.started.store(false, Ordering::Release);
+                pending.push_back(HookEvent::CaptureStopped);
+            }
+            HostCommand::Ping { sequence } => pending.push_back(HookEvent::Pong { sequence }),
+            HostCommand::Shutdown => self.stopped.store(true, Ordering::Release),
+        }
+        Ok(())
+    }
+
+    fn finished_detail(&self) -> String {
+        "练习已结束。".into()
+    }
+}
+
+/// Duration of a practice timeline; an empty one still gets a usable clock.
+pub fn practice_duration_ms(timeline: &LyricTimeline) -> f32 {
+    timeline_end_ms(timeline).unwrap_or(0.0).max(1.0)
+}
+
+/// `m:ss` for the practice position display.
+pub fn format_position(milliseconds: f32) -> String {
+    let seconds = (milliseconds.max(0.0) / 1_000.0) as u64;
+    format!("{}:{:02}", seconds / 60, seconds % 60)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::fixtures;
+    use kg_capture_protocol::PlaybackPosition;
+
+    #[test]
+    fn practice_clock_plays_pauses_and_seeks() {
+        let clock = PracticeClock::new(10_000.0, 1.0);
+        let start = Instant::now();
+        assert_eq!(clock.position_at(start + Duration::from_secs(1)), 0.0);
+
+        clock.toggle_at(start);
+        assert_eq!(clock.position_at(start + Duration::from_secs(2)), 2_000.0);
+
+        clock.set_speed_at(2.0, start + Duration::from_secs(2));
+        assert_eq!(clock.position_at(start + Duration::from_secs(3)), 4_000.0);
+
+        clock.toggle_at(start + Duration::from_secs(3));
+        assert_eq!(clock.position_at(start + Duration::from_secs(9)), 4_000.0);
+
+        clock.seek_at(9_500.0, start + Duration::from_secs(9));
+        clock.toggle_at(start + Duration::from_secs(9));
+        assert_eq!(clock.position_at(start + Duration::from_secs(20)), 10_000.0);
+
+        // Resuming at the end starts over.
+        clock.toggle_at(start + Duration::from_secs(20));
+        assert_eq!(clock.position_at(start + Duration::from_secs(20)), 0.0);
+    }
+
+    #[test]
+    fn practice_source_reports_the_clock_position() {
+        let timeline = fixtures::timeline(0, &[("把爱留在身边", 0.0, 2_000.0)]);
+        let clock = Arc::new(PracticeClock::new(practice_duration_ms(&timeline), 1.0));
+        clock.seek(500.0);
+        let source = PracticeSource::new(PathBuf::from("song.lrc"), timeline, clock);
+
+        source.send(HostCommand::StartCapture).unwrap();
+        assert!(matches!(
+            source.next_event(),
+            Ok(Some(HookEvent::CaptureStarted))
+        ));
+        assert!(matches!(
+            source.next_event(),
+            Ok(Some(HookEvent::Timeline(_)))
+        ));
+        assert!(matches!(
+            source.next_event(),
+            Ok(Some(HookEvent::PlaybackState {
+                state: PlaybackState::Paused,
+                ..
+            }))
+        ));
+        assert!(matches!(
+            source.next_event(),
+            Ok(Some(HookEvent::Playback(PlaybackPosition {
+                position_ms: 500.0,
+                current_line: Some(0),
+                line_progress: 0.25,
+                ..
+            })))
+        ));
+
+        source.send(HostCommand::Shutdown).unwrap();
+        assert!(matches!(source.next_event(), Ok(None)));
+    }
+
+    #[test]
+    fn formats_practice_positions() {
+        assert_eq!(format_position(0.0), "0:00");
+        assert_eq!(format_position(61_999.0), "1:01");
+    }
+}
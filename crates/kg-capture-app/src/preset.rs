@@ -0,0 +1,183 @@
+//! Named lyric window appearances. Built-in presets cover common setups; user
+//! presets are kept in the settings file and can be shared as JSON files.
+
+use serde::{Deserialize, Serialize};
+
+use crate::LyricsAlignment;
+use crate::settings::AppearanceSettings;
+
+pub const PRESET_EXTENSION: &str = "json";
+const PRESET_FORMAT: &str = "kg-capture-appearance";
+const PRESET_VERSION: u16 = 1;
+
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
+pub struct AppearancePreset {
+    pub name: String,
+    #[serde(default)]
+    pub appearance: AppearanceSettings,
+}
+
+/// A shared preset file. The format tag keeps other JSON from being imported
+/// as an appearance.
+#[derive(Debug, Serialize, Deserialize)]
+struct PresetFile {
+    format: String,
+    version: u16,
+    #[serde(flatten)]
+    preset: AppearancePreset,
+}
+
+pub fn built_in_presets() -> Vec<AppearancePreset> {
+    let defaults = AppearanceSettings::default();
+    vec![
+        AppearancePreset {
+            name: "OBS 深色".into(),
+            appearance: defaults.clone(),
+        },
+        AppearancePreset {
+            name: "投影".into(),
+            appearance: AppearanceSettings {
+                background: "#000000".into(),
+                text: "#FFFFFF".into(),
+                highlight: "#FFEB3B".into(),
+                active_font_size: 72.0,
+                candidate_font_size: 40.0,
+                show_previous_line: false,
+                candidate_line_count: 1,
+                ..defaults.clone()
+            },
+        },
+        AppearancePreset {
+            name: "对唱".into(),
+            appearance: AppearanceSettings {
+                background: "#1E1B2E".into(),
+                text: "#E8E6F0".into(),
+                highlight: "#FF7AB6".into(),
+                alignment: LyricsAlignment::Left,
+                active_font_size: 44.0,
+                candidate_font_size: 28.0,
+                candidate_line_count: 2,
+                ..defaults.clone()
+            },
+        },
+        AppearancePreset {
+            name: "游戏叠加".into(),
+            appearance: AppearanceSettings {
+                text: "#FFFFFF".into(),
+                highlight: "#FFD54F".into(),
+                active_font_size: 44.0,
+                candidate_font_size: 26.0,
+                outline_width: 3.0,
+                outline_color: "#000000".into(),
+                shadow_offset: 3.0,
+                shadow_blur: 4.0,
+                shadow_color: "#000000".into(),
+                ..defaults
+            },
+        },
+    ]
+}
+
+pub fn is_built_in(name: &str) -> bool {
+    built_in_presets().iter().any(|preset| preset.name == name)
+}
+
+pub fn to_json(preset: &AppearancePreset) -> String {
+    let file = PresetFile {
+        format: PRESET_FORMAT.into(),
+        version: PRESET_VERSION,
+        preset: preset.clone(),
+    };
+    serde_json::to_string_pretty(&file).unwrap_or_default() + "\n"
+}
+
+/// Suggested export file name for a preset, with characters Windows does not
+/// allow in file names replaced by `_`.
+pub fn file_name(name: &str) -> String {
+    let name = name.trim();
+    let name = if name.is_empty() { "appearance" } else { name };
+    let name: String = name
+        .chars()
+        .map(|character| match character {
+            '/' | '\\' | ':' | '*' | '?' | '"' | '<' | '>' | '|' => '_',
+            character => character,
+        })
+        .collect();
+    format!("{name}.{PRESET_EXTENSION}")
+}
+
+/// Reads a shared preset file. Appearance fields it does not name keep their
+/// defaults, so presets from older builds still load.
+pub fn parse_preset(contents: &str) -> Result<AppearancePreset, String> {
+    let file: PresetFile = serde_json::from_str(contents)
+        .map_err(|error| format!("invalid appearance preset: {error}"))?;
+    if file.format != PRESET_FORMAT {
+        return Err(format!("not an appearance preset: {}", file.format));
+    }
+    if file.version > PRESET_VERSION {
+        return Err(format!(
+            "appearance preset version {} was written by a newer KG Capture",
+            file.version
+        ));
+    }
+    let name = file.preset.name.trim();
+    if name.is_empty() {
+        return Err("appearance preset has no name".into());
+    }
+    Ok(AppearancePreset {
+        name: name.into(),
+        appearance: file.preset.appearance,
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn presets_round_trip_through_shared_files() {
+        let preset = AppearancePreset {
+            name: "主播 A".into(),
+            appearance: AppearanceSettings {
+                highlight: "#00FF00".into(),
+                font_family: None,
+                ..AppearanceSettings::default()
+            },
+        };
+        let contents = to_json(&preset);
+        assert!(contents.contains(r#""format": "kg-capture-appearance""#));
+        assert_eq!(parse_preset(&contents), Ok(preset));
+
+        let partial = parse_preset(
+            r##"{"format":"kg-capture-appearance","version":1,"name":" 绿 ","appearance":{"text":"#00FF00"}}"##,
+        )
+        .unwrap();
+        assert_eq!(partial.name, "绿");
+        assert_eq!(partial.appearance.text, "#00FF00");
+        assert_eq!(partial.appearance.active_font_size, 38.0);
+    }
+
+    #[test]
+    fn rejects_other_files() {
+        assert!(
+            parse_preset(r#"{"format":"kg-capture-recording","version":1,"name":"x"}"#).is_err()
+        );
+        assert!(
+            parse_preset(r#"{"format":"kg-capture-appearance","version":9,"name":"x"}"#).is_err()
+        );
+        assert!(
+            parse_preset(r#"{"format":"kg-capture-appearance","version":1,"name":" "}"#).is_err()
+        );
+        assert!(is_built_in("投影"));
+    }
+
+    #[test]
+    fn file_names_replace_reserved_characters() {
+        assert_eq!(file_name(" 主播 A "), "主播 A.json");
+        assert_eq!(file_name(""), "appearance.json");
+        assert_eq!(
+            file_name(r#"a/b\c:d*e?f"g<h>i|j"#),
+            "a_b_c_d_e_f_g_h_i_j.json"
+        );
+    }
+}
@@ -0,0 +1,325 @@
+//! Session recordings: every hook event with its host arrival time, stored as
+//! JSON lines beside `host.log` so a capture can be replayed without WeSing.
+
+use std::collections::VecDeque;
+use std::fs::{self, File, OpenOptions};
+use std::io::{BufWriter, Write};
+use std::path::{Path, PathBuf};
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::{Arc, Mutex};
+use std::thread;
+use std::time::{Duration, SystemTime, UNIX_EPOCH};
+
+use kg_capture_protocol::{HookEvent, HostCommand, PROTOCOL_VERSION};
+use serde::{Deserialize, Serialize};
+
+use crate::source::EventSource;
+
+pub const RECORDING_FILE_NAME: &str = "events.jsonl";
+const RECORDING_FORMAT: &str = "kg-capture-recording";
+const RECORDING_VERSION: u16 = 1;
+pub const MIN_REPLAY_SPEED: f32 = 0.25;
+pub const MAX_REPLAY_SPEED: f32 = 4.0;
+
+/// First line of every recording. Event payloads are only meaningful for the
+/// protocol version that produced them, so it is kept for diagnostics.
+#[derive(Debug, Serialize, Deserialize)]
+struct RecordingHeader {
+    format: String,
+    version: u16,
+    protocol_version: u16,
+}
+
+#[derive(Clone, Debug, Serialize, Deserialize)]
+pub struct RecordedEvent {
+    pub arrived_at_micros: u64,
+    pub event: HookEvent,
+}
+
+/// Appends events to a recording. Clones share the same file, which is
+/// flushed on timeline and playback state events and when the last clone drops.
+#[derive(Clone, Debug)]
+pub struct Recorder {
+    file: Arc<Mutex<BufWriter<File>>>,
+}
+
+impl Recorder {
+    pub fn create(path: &Path) -> Result<Self, String> {
+        let file = OpenOptions::new()
+            .create(true)
+            .truncate(true)
+            .write(true)
+            .open(path)
+            .map_err(|error| format!("create recording {}: {error}", path.display()))?;
+        let mut file = BufWriter::new(file);
+        let header = RecordingHeader {
+            format: RECORDING_FORMAT.into(),
+            version: RECORDING_VERSION,
+            protocol_version: PROTOCOL_VERSION,
+        };
+        write_line(&mut file, &header)
+            .and_then(|()| file.flush())
+            .map_err(|error| format!("write recording {}: {error}", path.display()))?;
+        Ok(Self {
+            file: Arc::new(Mutex::new(file)),
+        })
+    }
+
+    pub fn record(&self, event: &HookEvent) {
+        let entry = RecordedEvent {
+            arrived_at_micros: timestamp_micros(),
+            event: event.clone(),
+        };
+        if let Ok(mut file) = self.file.lock() {
+            let _ = write_line(&mut *file, &entry);
+            if matches!(
+                event,
+                HookEvent::Timeline(_) | HookEvent::PlaybackState { .. }
+            ) {
+                let _ = file.flush();
+            }
+        }
+    }
+}
+
+#[derive(Clone, Debug)]
+pub struct Recording {
+    pub events: Vec<RecordedEvent>,
+}
+
+impl Recording {
+    pub fn load(path: &Path) -> Result<Self, String> {
+        let contents = fs::read_to_string(path)
+            .map_err(|error| format!("read recording {}: {error}", path.display()))?;
+        Self::parse(&contents)
+    }
+
+    fn parse(contents: &str) -> Result<Self, String> {
+        let mut lines = contents.lines().enumerate();
+        let header = lines
+            .next()
+            .ok_or_else(|| "recording is empty".to_owned())
+            .and_then(|(_, line)| {
+                serde_json::from_str::<RecordingHeader>(line)
+                    .map_err(|error| format!("recording header is invalid: {error}"))
+            })?;
+        if header.format != RECORDING_FORMAT {
+            return Err(format!("not a KG Capture recording: {}", header.format));
+        }
+        if header.version != RECORDING_VERSION {
+            return Err(format!("unsupported recording version {}", header.version));
+        }
+
+        let line_count = contents.lines().count();
+        let mut events = Vec::new();
+        for (index, line) in lines {
+            if line.trim().is_empty() {
+                continue;
+            }
+            match serde_json::from_str::<RecordedEvent>(line) {
+                Ok(event) => events.push(event),
+                // The host may exit while an entry is being flushed; a torn
+                // final line is dropped instead of rejecting the recording.
+                Err(_) if index + 1 == line_count => break,
+                Err(error) => {
+                    return Err(format!("recording line {} is invalid: {error}", index + 1));
+                }
+            }
+        }
+        Ok(Self { events })
+    }
+}
+
+/// Replays a recording with its original spacing divided by `speed`.
+#[derive(Debug)]
+pub struct ReplaySource {
+    path: PathBuf,
+    speed: f32,
+    events: Mutex<VecDeque<RecordedEvent>>,
+    previous: Mutex<Option<RecordedEvent>>,
+    stopped: AtomicBool,
+}
+
+impl ReplaySource {
+    pub fn new(path: PathBuf, recording: Recording, speed: f32) -> Self {
+        Self {
+            path,
+            speed,
+            events: Mutex::new(recording.events.into()),
+            previous: Mutex::new(None),
+            stopped: AtomicBool::new(false),
+        }
+    }
+}
+
+impl EventSource for ReplaySource {
+    fn describe(&self) -> String {
+        let count = self.events.lock().map_or(0, |events| events.len());
+        format!("正在回放 {}（{count} 个事件）…", self.path.display())
+    }
+
+    fn next_event(&self) -> Result<Option<HookEvent>, String> {
+        let Some(next) = self
+            .events
+            .lock()
+            .map_err(|_| "replay queue lock was poisoned".to_owned())?
+            .pop_front()
+        else {
+            return Ok(None);
+        };
+        let mut previous = self
+            .previous
+            .lock()
+            .map_err(|_| "replay clock lock was poisoned".to_owned())?;
+        if let Some(previous) = previous.as_ref() {
+            thread::sleep(replay_delay(previous, &next, self.speed));
+        }
+        if self.stopped.load(Ordering::Acquire) {
+            return Ok(None);
+        }
+        let event = next.event.clone();
+        *previous = Some(next);
+        Ok(Some(event))
+    }
+
+    fn send(&self, command: HostCommand) -> Result<(), String> {
+        if matches!(command, HostCommand::Shutdown) {
+            self.stopped.store(true, Ordering::Release);
+        }
+        Ok(())
+    }
+
+    fn finished_detail(&self) -> String {
+        "回放结束。".into()
+    }
+}
+
+/// Time to wait between two recorded events when replaying at `speed`.
+pub fn replay_delay(previous: &RecordedEvent, next: &RecordedEvent, speed: f32) -> Duration {
+    let elapsed = next
+        .arrived_at_micros
+        .saturating_sub(previous.arrived_at_micros);
+    let speed = speed.clamp(MIN_REPLAY_SPEED, MAX_REPLAY_SPEED);
+    Duration::from_micros(elapsed).div_f32(speed)
+}
+
+fn write_line(file: &mut impl Write, value: &impl Serialize) -> std::io::Result<()> {
+    serde_json::to_writer(&mut *file, value)?;
+    file.write_all(b"\n")
+}
+
+fn timestamp_micros() -> u64 {
+    SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .map(|duration| duration.as_micros() as u64)
+        .unwrap_or(0)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use kg_capture_protocol::{PlaybackPosition, PlaybackState};
+
+    fn playback(arrived_at_micros: u64) -> RecordedEvent {
+        RecordedEvent {
+            arrived_at_micros,
+            event: HookEvent::Playback(PlaybackPosition {
+                timeline_id: 1,
+                observed_at_micros: arrived_at_micros,
+                position_ms: 0.0,
+                current_line: Some(0),
+                line_progress: 0.0,
+            }),
+        }
+    }
+
+    #[test]
+    fn recording_round_trip() {
+        let path = std::env::temp_dir().join(format!(
+            "kg-capture-recording-test-{}.jsonl",
+            std::process::id()
+        ));
+        let recorder = Recorder::create(&path).expect("create recording");
+        recorder.record(&HookEvent::CaptureStarted);
+        recorder.record(&playback(0).event);
+        drop(recorder);
+
+        let recording = Recording::load(&path).expect("load recording");
+        let _ = fs::remove_file(&path);
+        assert_eq!(recording.events.len(), 2);
+        assert!(matches!(
+            recording.events[0].event,
+            HookEvent::CaptureStarted
+        ));
+        assert!(matches!(recording.events[1].event, HookEvent::Playback(_)));
+    }
+
+    #[test]
+    fn state_changes_flush_buffered_events() {
+        let path = std::env::temp_dir().join(format!(
+            "kg-capture-recording-flush-test-{}.jsonl",
+            std::process::id()
+        ));
+        let recorder = Recorder::create(&path).expect("create recording");
+        recorder.record(&playback(0).event);
+        let buffered = Recording::load(&path).expect("load recording");
+        assert!(buffered.events.is_empty());
+
+        recorder.record(&HookEvent::PlaybackState {
+            timeline_id: 1,
+            state: PlaybackState::Paused,
+        });
+        let recording = Recording::load(&path).expect("load recording");
+        drop(recorder);
+        let _ = fs::remove_file(&path);
+        assert_eq!(recording.events.len(), 2);
+    }
+
+    #[test]
+    fn torn_final_line_is_ignored() {
+        let header = r#"{"format":"kg-capture-recording","version":1,"protocol_version":2}"#;
+        let event = r#"{"arrived_at_micros":5,"event":"CaptureStarted"}"#;
+        let recording = Recording::parse(&format!("{header}\n{event}\n{{\"arrived_at"))
+            .expect("parse recording");
+        assert_eq!(recording.events.len(), 1);
+
+        assert!(Recording::parse(&format!("{header}\n{{\"arrived_at\n{event}\n")).is_err());
+        assert!(
+            Recording::parse(r#"{"format":"other","version":1,"protocol_version":2}"#).is_err()
+        );
+    }
+
+    #[test]
+    fn replay_source_yields_events_in_order_until_shutdown() {
+        let recording = Recording {
+            events: vec![playback(0), playback(1_000), playback(2_000)],
+        };
+        let source = ReplaySource::new(PathBuf::from("events.jsonl"), recording, 4.0);
+        assert!(matches!(
+            source.next_event(),
+            Ok(Some(HookEvent::Playback(_)))
+        ));
+        assert!(matches!(
+            source.next_event(),
+            Ok(Some(HookEvent::Playback(_)))
+        ));
+
+        source.send(HostCommand::Shutdown).unwrap();
+        assert!(matches!(source.next_event(), Ok(None)));
+    }
+
+    #[test]
+    fn replay_delay_scales_with_speed() {
+        let first = playback(1_000_000);
+        let second = playback(1_500_000);
+        assert_eq!(
+            replay_delay(&first, &second, 1.0),
+            Duration::from_millis(500)
+        );
+        assert_eq!(
+            replay_delay(&first, &second, 2.0),
+            Duration::from_millis(250)
+        );
+        assert_eq!(replay_delay(&second, &first, 1.0), Duration::ZERO);
+    }
+}
@@ -0,0 +1,417 @@
+//! Headless rendering of the lyric window. The view the window shows is laid
+//! out and drawn with the tiny-skia CPU rasterizer and written as a PNG, so
+//! thumbnails and layout snapshots need neither a display nor Windows.
+//!
+//! `kg-capture-render frame <lyrics> <time-ms> <output.png>` renders one frame from
+//! a lyric file; `--appearance <preset.json>` and `--size <width>x<height>`
+//! change the look and the window size.
+
+use std::fs;
+use std::io::BufWriter;
+use std::path::{Path, PathBuf};
+
+use iced::advanced::renderer::{self, Headless};
+use iced::{Color, Font, Pixels, Size, mouse};
+use iced_runtime::user_interface::{Cache, UserInterface};
+use kg_capture_lyrics::timing::locate;
+use kg_capture_protocol::{LyricTimeline, PlaybackPosition, PlaybackState};
+
+use crate::settings::AppearanceSettings;
+use crate::transition::TransitionFrame;
+use crate::{
+    BackgroundMode, LyricsAppearance, LyricsFont, lyrics_theme, lyrics_window_content, practice,
+    preset,
+};
+
+/// The lyric window's initial size.
+pub const DEFAULT_SIZE: Size<u32> = Size::new(900, 420);
+
+const USAGE: &str = "usage: kg-capture-render frame <lyrics.lrc|ttml|xml|vtt> <time-ms> <output.png> \
+                     [--appearance <preset.json>] [--size <width>x<height>]";
+
+/// Renders the lyric window at `time_ms` into PNG bytes. A line that started
+/// less than the transition length ago is drawn mid-transition, as the
+/// window would show it.
+pub fn render_png(
+    timeline: &LyricTimeline,
+    appearance: &LyricsAppearance,
+    time_ms: f32,
+    size: Size<u32>,
+) -> Result<Vec<u8>, String> {
+    let rgba = render_rgba(timeline, appearance, time_ms, size)?;
+    encode_png(&rgba, size)
+}
+
+/// Straight-alpha RGBA pixels, row by row.
+pub fn render_rgba(
+    timeline: &LyricTimeline,
+    appearance: &LyricsAppearance,
+    time_ms: f32,
+    size: Size<u32>,
+) -> Result<Vec<u8>, String> {
+    let mut pixels = FrameRenderer::new(size)?.render(timeline, appearance, time_ms);
+    unpremultiply(&mut pixels);
+    Ok(pixels)
+}
+
+/// A CPU renderer kept across frames, so a video does not set one up per
+/// frame.
+pub struct FrameRenderer {
+    renderer: iced::Renderer,
+    size: Size<u32>,
+}
+
+impl FrameRenderer {
+    pub fn new(size: Size<u32>) -> Result<Self, String> {
+        if size.width == 0 || size.height == 0 {
+            return Err(format!("invalid size {}x{}", size.width, size.height));
+        }
+        let renderer = iced::futures::executor::block_on(<iced::Renderer as Headless>::new(
+            Font::DEFAULT,
+            Pixels(16.0),
+            Some("tiny-skia"),
+        ))
+        .ok_or("the tiny-skia renderer is not available")?;
+        Ok(Self { renderer, size })
+    }
+
+    pub fn size(&self) -> Size<u32> {
+        self.size
+    }
+
+    /// Premultiplied RGBA pixels of the lyric window at `time_ms`.
+    pub fn render(
+        &mut self,
+        timeline: &LyricTimeline,
+        appearance: &LyricsAppearance,
+        time_ms: f32,
+    ) -> Vec<u8> {
+        let position = locate(timeline, time_ms);
+        let playback = PlaybackPosition {
+            timeline_id: timeline.id,
+            observed_at_micros: 0,
+            position_ms: time_ms,
+            current_line: position.line,
+            line_progress: position.progress,
+        };
+        self.render_window(
+            Some(timeline),
+            Some(&playback),
+            None,
+            appearance,
+            transition_at(timeline, &playback, appearance),
+        )
+    }
+
+    /// Premultiplied RGBA pixels of the lyric window in any state. A
+    /// chroma-keyed window gets hard edges, see [`key_matte`].
+    pub(crate) fn render_window(
+        &mut self,
+        timeline: Option<&LyricTimeline>,
+        playback: Option<&PlaybackPosition>,
+        playback_state: Option<PlaybackState>,
+        appearance: &LyricsAppearance,
+        transition: Option<TransitionFrame>,
+    ) -> Vec<u8> {
+        let keyed = appearance.background_mode == BackgroundMode::ChromaKey;
+        let background = if keyed {
+            Color::TRANSPARENT
+        } else {
+            appearance.window_background()
+        };
+        let content = lyrics_window_content(
+            timeline,
+            playback,
+            playback_state,
+            appearance,
+            background,
+            transition,
+        );
+        let mut interface = UserInterface::build(
+            content,
+            Size::new(self.size.width as f32, self.size.height as f32),
+            Cache::default(),
+            &mut self.renderer,
+        );
+        interface.draw(
+            &mut self.renderer,
+            &lyrics_theme(),
+            &renderer::Style {
+                text_color: appearance.text,
+            },
+            mouse::Cursor::Unavailable,
+        );
+        let mut pixels = self.renderer.screenshot(self.size, 1.0, Color::TRANSPARENT);
+        if keyed {
+            key_matte(&mut pixels, appearance.chroma_key);
+        }
+        pixels
+    }
+}
+
+/// Thresholds the coverage of lyrics drawn over nothing and fills the rest
+/// with the key. Pixels the lyrics cover at least half of become fully
+/// opaque and the others become the key, so no pixel mixes the key with the
+/// text and a color-key filter leaves no fringe.
+fn key_matte(pixels: &mut [u8], key: Color) {
+    let key = key.into_rgba8();
+    for pixel in pixels.as_chunks_mut::<4>().0 {
+        if pixel[3] < 128 {
+            *pixel = key;
+        } else {
+            unpremultiply_pixel(pixel);
+            pixel[3] = 255;
+        }
+    }
+}
+
+fn transition_at(
+    timeline: &LyricTimeline,
+    playback: &PlaybackPosition,
+    appearance: &LyricsAppearance,
+) -> Option<TransitionFrame> {
+    let index = usize::try_from(playback.current_line?).ok()?;
+    let from = index.checked_sub(1)?;
+    let duration_ms = appearance.transition_duration().as_secs_f32() * 1_000.0;
+    let elapsed_ms = playback.position_ms - timeline.lines.get(index)?.start_ms;
+    (elapsed_ms < duration_ms).then(|| TransitionFrame {
+        from,
+        progress: appearance.easing.apply(elapsed_ms / duration_ms),
+    })
+}
+
+/// The rasterizer keeps premultiplied colors; PNG stores straight alpha.
+pub fn unpremultiply(pixels: &mut [u8]) {
+    for pixel in pixels.as_chunks_mut::<4>().0 {
+        unpremultiply_pixel(pixel);
+    }
+}
+
+fn unpremultiply_pixel(pixel: &mut [u8; 4]) {
+    let alpha = u16::from(pixel[3]);
+    if alpha > 0 && alpha < 255 {
+        for channel in &mut pixel[..3] {
+            *channel = (u16::from(*channel) * 255 / alpha).min(255) as u8;
+        }
+    }
+}
+
+pub fn encode_png(rgba: &[u8], size: Size<u32>) -> Result<Vec<u8>, String> {
+    let mut bytes = Vec::new();
+    let mut encoder = png::Encoder::new(BufWriter::new(&mut bytes), size.width, size.height);
+    encoder.set_color(png::ColorType::Rgba);
+    encoder.set_depth(png::BitDepth::Eight);
+    encoder
+        .write_header()
+        .and_then(|mut writer| writer.write_image_data(rgba))
+        .map_err(|error| format!("encode PNG: {error}"))?;
+    Ok(bytes)
+}
+
+/// Applies saved appearance for rendering. The font family is used by name,
+/// since installed fonts are not enumerated outside the GUI.
+pub fn appearance(settings: &AppearanceSettings) -> LyricsAppearance {
+    let fonts: Vec<LyricsFont> = settings
+        .font_family
+        .iter()
+        .map(|family| LyricsFont::named(family.clone(), family.clone()))
+        .collect();
+    LyricsAppearance::from_settings(settings, &fonts)
+}
+
+struct Arguments {
+    lyrics: PathBuf,
+    time_ms: f32,
+    output: PathBuf,
+    appearance: Option<PathBuf>,
+    size: Size<u32>,
+}
+
+/// Runs `kg-capture-render frame` with the arguments after the subcommand.
+pub fn run(arguments: impl IntoIterator<Item = String>) -> Result<(), String> {
+    let arguments = parse_arguments(arguments)?;
+    let timeline = practice::load_timeline(&arguments.lyrics)?;
+    let appearance = load_appearance(arguments.appearance.as_deref())?;
+    let png = render_png(&timeline, &appearance, arguments.time_ms, arguments.size)?;
+    fs::write(&arguments.output, png)
+        .map_err(|error| format!("write {}: {error}", arguments.output.display()))
+}
+
+/// The appearance in an exported preset file, or the default one.
+pub fn load_appearance(preset_path: Option<&Path>) -> Result<LyricsAppearance, String> {
+    let settings = match preset_path {
+        Some(path) => {
+            fs::read_to_string(path)
+                .map_err(|error| format!("read {}: {error}", path.display()))
+                .and_then(|contents| preset::parse_preset(&contents))?
+                .appearance
+        }
+        None => AppearanceSettings::default(),
+    };
+    Ok(appearance(&settings))
+}
+
+fn parse_arguments(arguments: impl IntoIterator<Item = String>) -> Result<Arguments, String> {
+    let mut positional = Vec::new();
+    let mut appearance = None;
+    let mut size = DEFAULT_SIZE;
+    let mut arguments = arguments.into_iter();
+    while let Some(argument) = arguments.next() {
+        match argument.as_str() {
+            "--appearance" => {
+                appearance = Some(PathBuf::from(arguments.next().ok_or(USAGE)?));
+            }
+            "--size" => size = parse_size(&arguments.next().ok_or(USAGE)?)?,
+            _ if argument.starts_with("--") => {
+                return Err(format!("unknown option {argument}\n{USAGE}"));
+            }
+            _ => positional.push(argument),
+        }
+    }
+    let [lyrics, time_ms, output] = <[String; 3]>::try_from(positional).map_err(|_| USAGE)?;
+    let time_ms = time_ms
+        .parse::<f32>()
+        .ok()
+        .filter(|time_ms| time_ms.is_finite())
+        .ok_or_else(|| format!("invalid time {time_ms}"))?;
+    Ok(Arguments {
+        lyrics: lyrics.into(),
+        time_ms,
+        output: output.into(),
+        appearance,
+        size,
+    })
+}
+
+pub fn parse_size(input: &str) -> Result<Size<u32>, String> {
+    input
+        .split_once(['x', 'X'])
+        .and_then(|(width, height)| Some(Size::new(width.parse().ok()?, height.parse().ok()?)))
+        .filter(|size: &Size<u32>| size.width > 0 && size.height > 0)
+        .ok_or_else(|| format!("invalid size {input}; expected <width>x<height>"))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::fixtures;
+
+    #[test]
+    fn renders_the_window_background_into_a_png() {
+        let settings = AppearanceSettings {
+            background: "#102030".into(),
+            ..AppearanceSettings::default()
+        };
+        let size = Size::new(320, 160);
+        let timeline = fixtures::timeline(1, &[("把爱留在身边", 0.0, 2_000.0)]);
+        let png = render_png(&timeline, &appearance(&settings), 500.0, size).unwrap();
+
+        let decoder = png::Decoder::new(std::io::Cursor::new(png));
+        let mut reader = decoder.read_info().unwrap();
+        let mut pixels = vec![0; reader.output_buffer_size().unwrap()];
+        let info = reader.next_frame(&mut pixels).unwrap();
+        assert_eq!((info.width, info.height), (320, 160));
+        assert_eq!(&pixels[..4], &[0x10, 0x20, 0x30, 0xff]);
+    }
+
+    #[test]
+    fn transparent_windows_render_with_alpha() {
+        let settings = AppearanceSettings {
+            background_mode: crate::BackgroundMode::Transparent,
+            ..AppearanceSettings::default()
+        };
+        let timeline = fixtures::timeline(1, &[("把爱留在身边", 0.0, 2_000.0)]);
+        let rgba =
+            render_rgba(&timeline, &appearance(&settings), 500.0, Size::new(64, 64)).unwrap();
+        assert_eq!(&rgba[..4], &[0, 0, 0, 0]);
+    }
+
+    #[test]
+    fn chroma_keyed_frames_never_blend_with_the_key() {
+        // Gray text and outline over a green key: any pixel mixing the two
+        // would have more green than red or blue.
+        let settings = AppearanceSettings {
+            background_mode: crate::BackgroundMode::ChromaKey,
+            chroma_key_color: "#00FF00".into(),
+            text: "#F0F0F0".into(),
+            highlight: "#FFFFFF".into(),
+            outline_color: "#202020".into(),
+            ..AppearanceSettings::default()
+        };
+        let timeline = fixtures::timeline(
+            1,
+            &[
+                ("把爱留在身边", 0.0, 2_000.0),
+                ("窗外有个蓝蓝的天", 2_000.0, 2_000.0),
+            ],
+        );
+        let rgba = render_rgba(
+            &timeline,
+            &appearance(&settings),
+            500.0,
+            Size::new(320, 160),
+        )
+        .unwrap();
+        let pixels = rgba.as_chunks::<4>().0;
+        let key = [0, 0xff, 0, 0xff];
+        assert!(pixels.iter().any(|pixel| *pixel != key));
+        for pixel in pixels.iter().filter(|pixel| **pixel != key) {
+            let [red, green, blue, alpha] = *pixel;
+            assert_eq!(alpha, 0xff);
+            assert!(
+                green.abs_diff(red) <= 2 && green.abs_diff(blue) <= 2,
+                "{pixel:?} blends with the key"
+            );
+        }
+    }
+
+    #[test]
+    fn frames_just_after_a_line_change_are_mid_transition() {
+        let timeline = fixtures::timeline(
+            1,
+            &[
+                ("把爱留在身边", 0.0, 2_000.0),
+                ("窗外有个蓝蓝的天", 2_000.0, 2_000.0),
+            ],
+        );
+        let appearance = appearance(&AppearanceSettings::default());
+        let playback = |position_ms: f32| {
+            let position = locate(&timeline, position_ms);
+            PlaybackPosition {
+                timeline_id: 1,
+                observed_at_micros: 0,
+                position_ms,
+                current_line: position.line,
+                line_progress: position.progress,
+            }
+        };
+        assert_eq!(
+            transition_at(&timeline, &playback(2_100.0), &appearance).map(|frame| frame.from),
+            Some(0)
+        );
+        assert_eq!(
+            transition_at(&timeline, &playback(3_000.0), &appearance),
+            None
+        );
+        assert_eq!(
+            transition_at(&timeline, &playback(100.0), &appearance),
+            None
+        );
+    }
+
+    #[test]
+    fn parses_command_line_arguments() {
+        let arguments = parse_arguments(
+            ["song.lrc", "1500", "frame.png", "--size", "640x360"].map(String::from),
+        )
+        .unwrap();
+        assert_eq!(arguments.lyrics, PathBuf::from("song.lrc"));
+        assert_eq!(arguments.time_ms, 1_500.0);
+        assert_eq!(arguments.size, Size::new(640, 360));
+        assert!(arguments.appearance.is_none());
+
+        assert!(parse_arguments(["song.lrc", "1500"].map(String::from)).is_err());
+        assert!(parse_arguments(["a", "soon", "b"].map(String::from)).is_err());
+        assert!(parse_size("0x10").is_err());
+    }
+}
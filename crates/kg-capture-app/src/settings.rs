@@ -0,0 +1,323 @@
+//! Control-window state kept across launches in `settings.json` under the
+//! user's config directory. Values are validated again when they are applied,
+//! so a hand-edited file cannot put a control out of range.
+
+use std::env;
+use std::fs;
+use std::path::{Path, PathBuf};
+
+use serde::{Deserialize, Serialize};
+use serde_json::Value;
+
+use crate::export::ExportFormat;
+use crate::karaoke::HighlightMode;
+use crate::now_singing::TextFileSettings;
+use crate::obs::ObsSettings;
+use crate::offset::TimingOffsets;
+use crate::preset::AppearancePreset;
+use crate::transition::{Easing, LineTransition};
+use crate::websocket::WebSocketSettings;
+use crate::{BackgroundMode, LyricsAlignment, LyricsLayout};
+
+pub const SETTINGS_FILE_NAME: &str = "settings.json";
+const SETTINGS_VERSION: u16 = 1;
+
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
+#[serde(default)]
+pub struct Settings {
+    pub version: u16,
+    pub executable_path: String,
+    pub replay_speed: f32,
+    pub practice_speed: f32,
+    pub export_format: ExportFormat,
+    pub timing_offsets: TimingOffsets,
+    pub appearance: AppearanceSettings,
+    /// User presets; the built-in ones are not stored.
+    pub presets: Vec<AppearancePreset>,
+    pub websocket: WebSocketSettings,
+    pub obs: ObsSettings,
+    pub text_files: TextFileSettings,
+}
+
+impl Default for Settings {
+    fn default() -> Self {
+        Self {
+            version: SETTINGS_VERSION,
+            executable_path: String::new(),
+            replay_speed: 1.0,
+            practice_speed: 1.0,
+            export_format: ExportFormat::Lrc,
+            timing_offsets: TimingOffsets::default(),
+            appearance: AppearanceSettings::default(),
+            presets: Vec::new(),
+            websocket: WebSocketSettings::default(),
+            obs: ObsSettings::default(),
+            text_files: TextFileSettings::default(),
+        }
+    }
+}
+
+/// Lyric window appearance. Colors are `#RRGGBB`; a missing font family means
+/// the system default font.
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
+#[serde(default)]
+pub struct AppearanceSettings {
+    pub background_mode: BackgroundMode,
+    /// Painted in [`BackgroundMode::Solid`] only.
+    pub background: String,
+    pub chroma_key_color: String,
+    pub text: String,
+    pub highlight: String,
+    pub font_family: Option<String>,
+    pub layout: LyricsLayout,
+    /// Used by the stacked layout; the two-line layout aligns its own rows.
+    pub alignment: LyricsAlignment,
+    pub highlight_mode: HighlightMode,
+    pub active_font_size: f32,
+    pub candidate_font_size: f32,
+    pub show_previous_line: bool,
+    pub candidate_line_count: usize,
+    /// Stroke width in pixels; zero turns the outline off.
+    pub outline_width: f32,
+    pub outline_color: String,
+    /// Down-right shadow offset in pixels. Zero offset and blur turn the
+    /// shadow off.
+    pub shadow_offset: f32,
+    pub shadow_blur: f32,
+    pub shadow_color: String,
+    /// Glow radius in pixels; zero turns the glow off.
+    pub glow_radius: f32,
+    pub glow_color: String,
+    pub transition: LineTransition,
+    /// Length of a line transition; zero switches lines at once.
+    pub transition_ms: f32,
+    pub easing: Easing,
+}
+
+impl Default for AppearanceSettings {
+    fn default() -> Self {
+        Self {
+            background_mode: BackgroundMode::Solid,
+            background: "#292B2F".into(),
+            chroma_key_color: "#00FF00".into(),
+            text: "#F5F5F5".into(),
+            highlight: "#FFD54F".into(),
+            font_family: Some("Microsoft YaHei".into()),
+            layout: LyricsLayout::Stacked,
+            alignment: LyricsAlignment::Center,
+            highlight_mode: HighlightMode::Wipe,
+            active_font_size: 38.0,
+            candidate_font_size: 24.0,
+            show_previous_line: true,
+            candidate_line_count: 3,
+            outline_width: 0.0,
+            outline_color: "#000000".into(),
+            shadow_offset: 0.0,
+            shadow_blur: 0.0,
+            shadow_color: "#000000".into(),
+            glow_radius: 0.0,
+            glow_color: "#FFD54F".into(),
+            transition: LineTransition::ScrollUp,
+            transition_ms: 250.0,
+            easing: Easing::EaseOut,
+        }
+    }
+}
+
+/// `%APPDATA%\kg-capture\settings.json` on Windows; elsewhere the XDG config
+/// directory. `None` when no config directory can be found.
+pub fn settings_path() -> Option<PathBuf> {
+    config_directory().map(|directory| directory.join("kg-capture").join(SETTINGS_FILE_NAME))
+}
+
+#[cfg(windows)]
+fn config_directory() -> Option<PathBuf> {
+    env::var_os("APPDATA").map(PathBuf::from)
+}
+
+#[cfg(not(windows))]
+fn config_directory() -> Option<PathBuf> {
+    env::var_os("XDG_CONFIG_HOME")
+        .map(PathBuf::from)
+        .filter(|path| path.is_absolute())
+        .or_else(|| env::var_os("HOME").map(|home| PathBuf::from(home).join(".config")))
+}
+
+/// Why the settings file could not be loaded.
+#[derive(Clone, Debug, Eq, PartialEq)]
+pub enum LoadError {
+    /// The file could not be read this time; it may still be valid.
+    Read(String),
+    /// The file holds nothing this version can use and never will.
+    Invalid(String),
+    /// A newer KG Capture wrote the file, which must not be overwritten.
+    Newer(String),
+}
+
+impl std::fmt::Display for LoadError {
+    fn fmt(&self, formatter: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Self::Read(message) | Self::Invalid(message) | Self::Newer(message) => {
+                formatter.write_str(message)
+            }
+        }
+    }
+}
+
+/// Reads the settings file. A missing file is not an error: the first launch
+/// starts from defaults.
+pub fn load(path: &Path) -> Result<Option<Settings>, LoadError> {
+    let contents = match fs::read_to_string(path) {
+        Ok(contents) => contents,
+        Err(error) if error.kind() == std::io::ErrorKind::NotFound => return Ok(None),
+        Err(error) => {
+            return Err(LoadError::Read(format!(
+                "read settings {}: {error}",
+                path.display()
+            )));
+        }
+    };
+    parse(&contents).map(Some).map_err(|error| match error {
+        LoadError::Read(message) => LoadError::Read(message),
+        LoadError::Invalid(message) => {
+            LoadError::Invalid(format!("settings {}: {message}", path.display()))
+        }
+        LoadError::Newer(message) => {
+            LoadError::Newer(format!("settings {}: {message}", path.display()))
+        }
+    })
+}
+
+/// Keeps an unusable settings file beside the new one instead of silently
+/// replacing it on the next save. Earlier backups are kept too: each one gets
+/// the first free numbered name.
+pub fn set_aside(path: &Path) -> Result<PathBuf, String> {
+    let backup = (1..)
+        .map(|number| match number {
+            1 => path.with_extension("json.bak"),
+            number => path.with_extension(format!("json.{number}.bak")),
+        })
+        .find(|backup| !backup.exists())
+        .expect("backup numbers are unbounded");
+    fs::rename(path, &backup)
+        .map_err(|error| format!("move settings {}: {error}", path.display()))?;
+    Ok(backup)
+}
+
+pub fn save(path: &Path, settings: &Settings) -> Result<(), String> {
+    if let Some(directory) = path.parent() {
+        fs::create_dir_all(directory).map_err(|error| {
+            format!("create settings directory {}: {error}", directory.display())
+        })?;
+    }
+    let contents = serde_json::to_string_pretty(settings)
+        .map_err(|error| format!("serialize settings: {error}"))?;
+    // Written beside the target and renamed over it, so an interrupted save
+    // leaves the previous settings intact.
+    let temporary = path.with_extension("json.tmp");
+    fs::write(&temporary, contents + "\n")
+        .map_err(|error| format!("write settings {}: {error}", temporary.display()))?;
+    fs::rename(&temporary, path)
+        .map_err(|error| format!("replace settings {}: {error}", path.display()))
+}
+
+fn parse(contents: &str) -> Result<Settings, LoadError> {
+    let value: Value = serde_json::from_str(contents)
+        .map_err(|error| LoadError::Invalid(format!("invalid JSON: {error}")))?;
+    let version = value
+        .get("version")
+        .and_then(Value::as_u64)
+        .ok_or_else(|| LoadError::Invalid("missing settings version".into()))?;
+    let value = migrate(value, version)?;
+    serde_json::from_value(value)
+        .map_err(|error| LoadError::Invalid(format!("invalid settings: {error}")))
+}
+
+/// Upgrades an older settings document one version at a time. Version 1 is
+/// the first persisted layout, so there is nothing to upgrade yet.
+fn migrate(value: Value, version: u64) -> Result<Value, LoadError> {
+    match version {
+        version if version == u64::from(SETTINGS_VERSION) => Ok(value),
+        version if version > u64::from(SETTINGS_VERSION) => Err(LoadError::Newer(format!(
+            "settings version {version} was written by a newer KG Capture"
+        ))),
+        version => Err(LoadError::Invalid(format!(
+            "unsupported settings version {version}"
+        ))),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn settings_round_trip_and_fill_missing_fields() {
+        let mut settings = Settings {
+            executable_path: r"C:\Program Files\WeSing\WeSing.exe".into(),
+            export_format: ExportFormat::Ttml,
+            ..Settings::default()
+        };
+        settings.timing_offsets.adjust_global(-150);
+        settings
+            .timing_offsets
+            .adjust_song(0x1234_5678_9abc_def0, 50);
+        settings.appearance.alignment = LyricsAlignment::Left;
+        settings.appearance.background_mode = BackgroundMode::ChromaKey;
+        let contents = serde_json::to_string_pretty(&settings).unwrap();
+        assert_eq!(parse(&contents), Ok(settings));
+
+        let partial = parse(r##"{"version":1,"appearance":{"highlight":"#00FF00"}}"##).unwrap();
+        assert_eq!(partial.appearance.highlight, "#00FF00");
+        assert_eq!(partial.appearance.active_font_size, 38.0);
+        assert_eq!(partial.appearance.background_mode, BackgroundMode::Solid);
+        assert_eq!(partial.replay_speed, 1.0);
+    }
+
+    #[test]
+    fn rejects_unversioned_and_newer_settings() {
+        assert!(matches!(parse("{}"), Err(LoadError::Invalid(_))));
+        assert!(matches!(
+            parse(r#"{"version":0}"#),
+            Err(LoadError::Invalid(_))
+        ));
+        assert!(matches!(
+            parse(r#"{"version":2}"#),
+            Err(LoadError::Newer(message)) if message.contains("newer KG Capture")
+        ));
+        assert!(matches!(
+            parse(r#"{"version":1,"export_format":"midi"}"#),
+            Err(LoadError::Invalid(_))
+        ));
+    }
+
+    #[test]
+    fn saves_through_a_temporary_file() {
+        let directory = env::temp_dir().join(format!("kg-capture-settings-{}", std::process::id()));
+        let path = directory.join("nested").join(SETTINGS_FILE_NAME);
+        assert_eq!(load(&path), Ok(None));
+
+        let settings = Settings {
+            practice_speed: 0.75,
+            ..Settings::default()
+        };
+        save(&path, &settings).unwrap();
+        assert_eq!(load(&path), Ok(Some(settings)));
+        assert!(!path.with_extension("json.tmp").exists());
+
+        fs::write(&path, "not json").unwrap();
+        assert!(matches!(load(&path), Err(LoadError::Invalid(_))));
+        let backup = set_aside(&path).unwrap();
+        assert!(backup.exists() && !path.exists());
+
+        fs::write(&path, "still not json").unwrap();
+        let second_backup = set_aside(&path).unwrap();
+        assert_ne!(second_backup, backup);
+        assert_eq!(fs::read_to_string(&backup).unwrap(), "not json");
+        assert_eq!(
+            fs::read_to_string(&second_backup).unwrap(),
+            "still not json"
+        );
+        fs::remove_dir_all(directory).unwrap();
+    }
+}
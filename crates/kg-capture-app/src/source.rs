@@ -0,0 +1,213 @@
+//! Event sources drive the host state machine. The injected hook is one of
+//! them; recordings and the in-process fixture work without WeSing.
+
+use std::collections::VecDeque;
+use std::sync::Mutex;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::thread;
+use std::time::{Duration, Instant, SystemTime, UNIX_EPOCH};
+
+use kg_capture_lyrics::timing::{locate, timeline_end_ms};
+use kg_capture_protocol::{
+    HookEvent, HostCommand, LyricLine, LyricSource, LyricTimeline, LyricWord, PlaybackPosition,
+};
+
+const FIXTURE_LINE_DURATION_MS: f32 = 2_400.0;
+const FIXTURE_INTERVAL: Duration = Duration::from_millis(50);
+
+pub trait EventSource: std::fmt::Debug + Send + Sync {
+    /// Status text shown once the source accepted `StartCapture`.
+    fn describe(&self) -> String;
+
+    /// Blocks until the next event is available. `Ok(None)` ends the stream
+    /// normally, while an error reports a lost connection.
+    fn next_event(&self) -> Result<Option<HookEvent>, String>;
+
+    fn send(&self, command: HostCommand) -> Result<(), String>;
+
+    /// Status text shown after `next_event` returned `Ok(None)`.
+    fn finished_detail(&self) -> String {
+        "事件流已结束。".into()
+    }
+
+    fn log_event(&self, _event: &HookEvent) {}
+}
+
+/// Synthetic source that plays a looping timeline from its own clock, the
+/// in-process counterpart of the hook's `cargo xtask smoke` fixture.
+#[derive(Debug)]
+pub struct FixtureSource {
+    timeline: LyricTimeline,
+    started: Mutex<Option<Instant>>,
+    pending: Mutex<VecDeque<HookEvent>>,
+    stopped: AtomicBool,
+}
+
+impl FixtureSource {
+    pub fn new() -> Self {
+        Self::with_timeline(fixture_timeline())
+    }
+
+    pub fn with_timeline(timeline: LyricTimeline) -> Self {
+        Self {
+            timeline,
+            started: Mutex::new(None),
+            pending: Mutex::new(VecDeque::new()),
+            stopped: AtomicBool::new(false),
+        }
+    }
+
+    fn playback(&self, elapsed: Duration) -> PlaybackPosition {
+        let total = timeline_end_ms(&self.timeline).unwrap_or(0.0).max(1.0);
+        clock_playback(&self.timeline, (elapsed.as_secs_f32() * 1_000.0) % total)
+    }
+}
+
+impl EventSource for FixtureSource {
+    fn describe(&self) -> String {
+        "已连接到内置演示数据源，正在初始化歌词同步…".into()
+    }
+
+    fn next_event(&self) -> Result<Option<HookEvent>, String> {
+        loop {
+            if self.stopped.load(Ordering::Acquire) {
+                return Ok(None);
+            }
+            if let Some(event) = self
+                .pending
+                .lock()
+                .ok()
+                .and_then(|mut queue| queue.pop_front())
+            {
+                return Ok(Some(event));
+            }
+            thread::sleep(FIXTURE_INTERVAL);
+            let started = *self
+                .started
+                .lock()
+                .map_err(|_| "fixture clock lock was poisoned".to_owned())?;
+            if let Some(started) = started {
+                return Ok(Some(HookEvent::Playback(self.playback(started.elapsed()))));
+            }
+        }
+    }
+
+    fn send(&self, command: HostCommand) -> Result<(), String> {
+        let mut pending = self
+            .pending
+            .lock()
+            .map_err(|_| "fixture queue lock was poisoned".to_owned())?;
+        let mut started = self
+            .started
+            .lock()
+            .map_err(|_| "fixture clock lock was poisoned".to_owned())?;
+        match command {
+            HostCommand::StartCapture => {
+                *started = Some(Instant::now());
+                pending.push_back(HookEvent::CaptureStarted);
+                pending.push_back(HookEvent::Timeline(self.timeline.clone()));
+            }
+            HostCommand::StopCapture => {
+                *started = None;
+                pending.push_back(HookEvent::CaptureStopped);
+            }
+            HostCommand::Ping { sequence } => pending.push_back(HookEvent::Pong { sequence }),
+            HostCommand::Shutdown => self.stopped.store(true, Ordering::Release),
+        }
+        Ok(())
+    }
+
+    fn finished_detail(&self) -> String {
+        "演示已停止。".into()
+    }
+}
+
+/// Position report for a source that keeps its own clock.
+pub fn clock_playback(timeline: &LyricTimeline, position_ms: f32) -> PlaybackPosition {
+    let position = locate(timeline, position_ms);
+    PlaybackPosition {
+        timeline_id: timeline.id,
+        observed_at_micros: SystemTime::now()
+            .duration_since(UNIX_EPOCH)
+            .map(|duration| duration.as_micros() as u64)
+            .unwrap_or(0),
+        position_ms,
+        current_line: position.line,
+        line_progress: position.progress,
+    }
+}
+
+fn fixture_timeline() -> LyricTimeline {
+    let lines = [
+        ["把爱", "留在", "身边"].as_slice(),
+        ["窗外", "有个", "蓝蓝的天"].as_slice(),
+        ["落叶", "那一瞬间", "记得"].as_slice(),
+    ]
+    .iter()
+    .enumerate()
+    .map(|(line_index, words)| {
+        let start = line_index as f32 * FIXTURE_LINE_DURATION_MS;
+        let duration = FIXTURE_LINE_DURATION_MS / words.len() as f32;
+        LyricLine {
+            index: line_index as u32,
+            text: words.concat(),
+            start_ms: start,
+            duration_ms: FIXTURE_LINE_DURATION_MS,
+            words: words
+                .iter()
+                .enumerate()
+                .map(|(word_index, text)| LyricWord {
+                    text: (*text).into(),
+                    start_ms: start + duration * word_index as f32,
+                    duration_ms: duration,
+                })
+                .collect(),
+        }
+    })
+    .collect();
+    LyricTimeline {
+        id: 1,
+        source: LyricSource::Fixture,
+        lines,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn fixture_emits_timeline_after_start_and_ends_on_shutdown() {
+        let source = FixtureSource::new();
+        source.send(HostCommand::StartCapture).unwrap();
+        assert!(matches!(
+            source.next_event(),
+            Ok(Some(HookEvent::CaptureStarted))
+        ));
+        assert!(matches!(
+            source.next_event(),
+            Ok(Some(HookEvent::Timeline(timeline))) if timeline.lines.len() == 3
+        ));
+        assert!(matches!(
+            source.next_event(),
+            Ok(Some(HookEvent::Playback(PlaybackPosition {
+                current_line: Some(0),
+                ..
+            })))
+        ));
+
+        source.send(HostCommand::Shutdown).unwrap();
+        assert!(matches!(source.next_event(), Ok(None)));
+    }
+
+    #[test]
+    fn fixture_playback_loops_over_the_timeline() {
+        let source = FixtureSource::new();
+        let playback = source.playback(Duration::from_millis(3_600));
+        assert_eq!(playback.current_line, Some(1));
+        assert!((playback.line_progress - 0.5).abs() < 1e-3);
+
+        let looped = source.playback(Duration::from_millis(7_200 + 100));
+        assert_eq!(looped.current_line, Some(0));
+    }
+}
@@ -0,0 +1,401 @@
+//! Line transitions in the lyric window. When the active line advances by
+//! one, the window animates from the old layout to the new one for a short
+//! time instead of jumping; seeks and song changes still switch at once.
+
+use std::time::{Duration, Instant};
+
+use iced::advanced::layout::{self, Layout};
+use iced::advanced::renderer;
+use iced::advanced::widget::{Tree, Widget, tree};
+use iced::{Element, Length, Rectangle, Size, Transformation, Vector, mouse};
+use serde::{Deserialize, Serialize};
+
+#[derive(Clone, Copy, Debug, Default, Eq, PartialEq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum LineTransition {
+    Off,
+    /// Every line slides up by one slot.
+    #[default]
+    ScrollUp,
+    /// The old layout fades out while the new one fades in.
+    Crossfade,
+    /// The new active line grows to full size while fading in.
+    ScaleIn,
+    /// The finished line fades out where it was sung.
+    Fade,
+}
+
+impl LineTransition {
+    pub const ALL: [Self; 5] = [
+        Self::Off,
+        Self::ScrollUp,
+        Self::Crossfade,
+        Self::ScaleIn,
+        Self::Fade,
+    ];
+}
+
+impl std::fmt::Display for LineTransition {
+    fn fmt(&self, formatter: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        formatter.write_str(match self {
+            Self::Off => "无",
+            Self::ScrollUp => "上滚",
+            Self::Crossfade => "交叉淡化",
+            Self::ScaleIn => "放大进入",
+            Self::Fade => "淡出上一句",
+        })
+    }
+}
+
+#[derive(Clone, Copy, Debug, Default, Eq, PartialEq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum Easing {
+    Linear,
+    #[default]
+    EaseOut,
+    EaseInOut,
+}
+
+impl Easing {
+    pub const ALL: [Self; 3] = [Self::Linear, Self::EaseOut, Self::EaseInOut];
+
+    /// Maps linear time in `0..=1` to animation progress in `0..=1`.
+    pub fn apply(self, time: f32) -> f32 {
+        let time = time.clamp(0.0, 1.0);
+        match self {
+            Self::Linear => time,
+            Self::EaseOut => 1.0 - (1.0 - time).powi(3),
+            Self::EaseInOut if time < 0.5 => 4.0 * time.powi(3),
+            Self::EaseInOut => 1.0 - (2.0 - 2.0 * time).powi(3) / 2.0,
+        }
+    }
+}
+
+impl std::fmt::Display for Easing {
+    fn fmt(&self, formatter: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        formatter.write_str(match self {
+            Self::Linear => "线性",
+            Self::EaseOut => "减速",
+            Self::EaseInOut => "缓入缓出",
+        })
+    }
+}
+
+/// Follows the line shown in the lyric window and remembers when it last
+/// advanced.
+#[derive(Debug, Default)]
+pub struct LineTracker {
+    shown: Option<usize>,
+    change: Option<LineChange>,
+}
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+struct LineChange {
+    from: usize,
+    started_at: Instant,
+}
+
+/// A transition in progress: the line that was active before and the eased
+/// progress towards the new layout.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct TransitionFrame {
+    pub from: usize,
+    pub progress: f32,
+}
+
+impl LineTracker {
+    /// Records the line shown now. Only a step to the next line animates.
+    pub fn follow(&mut self, line: Option<usize>, now: Instant) {
+        if line == self.shown {
+            return;
+        }
+        self.change = match (self.shown, line) {
+            (Some(from), Some(to)) if to == from + 1 => Some(LineChange {
+                from,
+                started_at: now,
+            }),
+            _ => None,
+        };
+        self.shown = line;
+    }
+
+    /// The transition at `now`, or `None` once it has finished.
+    pub fn frame(
+        &self,
+        now: Instant,
+        duration: Duration,
+        easing: Easing,
+    ) -> Option<TransitionFrame> {
+        let change = self.change?;
+        let elapsed = now.saturating_duration_since(change.started_at);
+        (elapsed < duration).then(|| TransitionFrame {
+            from: change.from,
+            progress: easing.apply(elapsed.as_secs_f32() / duration.as_secs_f32()),
+        })
+    }
+}
+
+/// Draws its content scaled about its center without changing the layout, so
+/// an animated line does not push its neighbours around.
+pub struct Transformed<'a, Message, Theme, Renderer> {
+    content: Element<'a, Message, Theme, Renderer>,
+    scale: f32,
+}
+
+impl<'a, Message, Theme, Renderer> Transformed<'a, Message, Theme, Renderer> {
+    pub fn new(content: impl Into<Element<'a, Message, Theme, Renderer>>) -> Self {
+        Self {
+            content: content.into(),
+            scale: 1.0,
+        }
+    }
+
+    pub fn scale(mut self, scale: f32) -> Self {
+        self.scale = scale;
+        self
+    }
+}
+
+impl<Message, Theme, Renderer> Widget<Message, Theme, Renderer>
+    for Transformed<'_, Message, Theme, Renderer>
+where
+    Renderer: renderer::Renderer,
+{
+    fn tag(&self) -> tree::Tag {
+        self.content.as_widget().tag()
+    }
+
+    fn state(&self) -> tree::State {
+        self.content.as_widget().state()
+    }
+
+    fn children(&self) -> Vec<Tree> {
+        self.content.as_widget().children()
+    }
+
+    fn diff(&self, tree: &mut Tree) {
+        self.content.as_widget().diff(tree);
+    }
+
+    fn size(&self) -> Size<Length> {
+        self.content.as_widget().size()
+    }
+
+    fn layout(
+        &mut self,
+        tree: &mut Tree,
+        renderer: &Renderer,
+        limits: &layout::Limits,
+    ) -> layout::Node {
+        self.content.as_widget_mut().layout(tree, renderer, limits)
+    }
+
+    fn draw(
+        &self,
+        tree: &Tree,
+        renderer: &mut Renderer,
+        theme: &Theme,
+        style: &renderer::Style,
+        layout: Layout<'_>,
+        cursor: mouse::Cursor,
+        viewport: &Rectangle,
+    ) {
+        let center = layout.bounds().center();
+        let transformation = Transformation::translate(center.x, center.y)
+            * Transformation::scale(self.scale)
+            * Transformation::translate(-center.x, -center.y);
+        renderer.with_transformation(transformation, |renderer| {
+            self.content.as_widget().draw(
+                tree,
+                renderer,
+                theme,
+                style,
+                layout,
+                cursor,
+                &(*viewport * transformation.inverse()),
+            );
+        });
+    }
+}
+
+impl<'a, Message, Theme, Renderer> From<Transformed<'a, Message, Theme, Renderer>>
+    for Element<'a, Message, Theme, Renderer>
+where
+    Message: 'a,
+    Theme: 'a,
+    Renderer: renderer::Renderer + 'a,
+{
+    fn from(transformed: Transformed<'a, Message, Theme, Renderer>) -> Self {
+        Element::new(transformed)
+    }
+}
+
+/// Draws its content moved back by one line and lets it scroll into place.
+/// The line that scrolled away is laid out beside the content but not drawn,
+/// so the distance is its real size plus the spacing between lines.
+pub struct ScrollIn<'a, Message, Theme, Renderer> {
+    content: Element<'a, Message, Theme, Renderer>,
+    departed: Element<'a, Message, Theme, Renderer>,
+    spacing: f32,
+    columns: bool,
+    remaining: f32,
+}
+
+impl<'a, Message, Theme, Renderer> ScrollIn<'a, Message, Theme, Renderer> {
+    pub fn new(
+        content: impl Into<Element<'a, Message, Theme, Renderer>>,
+        departed: impl Into<Element<'a, Message, Theme, Renderer>>,
+    ) -> Self {
+        Self {
+            content: content.into(),
+            departed: departed.into(),
+            spacing: 0.0,
+            columns: false,
+            remaining: 0.0,
+        }
+    }
+
+    pub fn spacing(mut self, spacing: f32) -> Self {
+        self.spacing = spacing;
+        self
+    }
+
+    /// Lines are columns placed right to left, so they move right instead of
+    /// up.
+    pub fn columns(mut self, columns: bool) -> Self {
+        self.columns = columns;
+        self
+    }
+
+    /// How much of the scroll is left, from 1 at the start to 0.
+    pub fn remaining(mut self, remaining: f32) -> Self {
+        self.remaining = remaining;
+        self
+    }
+}
+
+impl<Message, Theme, Renderer> Widget<Message, Theme, Renderer>
+    for ScrollIn<'_, Message, Theme, Renderer>
+where
+    Renderer: renderer::Renderer,
+{
+    fn children(&self) -> Vec<Tree> {
+        vec![Tree::new(&self.content), Tree::new(&self.departed)]
+    }
+
+    fn diff(&self, tree: &mut Tree) {
+        tree.diff_children(&[&self.content, &self.departed]);
+    }
+
+    fn size(&self) -> Size<Length> {
+        self.content.as_widget().size()
+    }
+
+    fn layout(
+        &mut self,
+        tree: &mut Tree,
+        renderer: &Renderer,
+        limits: &layout::Limits,
+    ) -> layout::Node {
+        let content = self
+            .content
+            .as_widget_mut()
+            .layout(&mut tree.children[0], renderer, limits);
+        let departed =
+            self.departed
+                .as_widget_mut()
+                .layout(&mut tree.children[1], renderer, limits);
+        layout::Node::with_children(content.size(), vec![content, departed])
+    }
+
+    fn draw(
+        &self,
+        tree: &Tree,
+        renderer: &mut Renderer,
+        theme: &Theme,
+        style: &renderer::Style,
+        layout: Layout<'_>,
+        cursor: mouse::Cursor,
+        viewport: &Rectangle,
+    ) {
+        let mut children = layout.children();
+        let (Some(content), Some(departed)) = (children.next(), children.next()) else {
+            return;
+        };
+        let departed = departed.bounds();
+        let shift = if self.columns {
+            Vector::new(-(departed.width + self.spacing), 0.0)
+        } else {
+            Vector::new(0.0, departed.height + self.spacing)
+        } * self.remaining;
+        let transformation = Transformation::translate(shift.x, shift.y);
+        renderer.with_transformation(transformation, |renderer| {
+            self.content.as_widget().draw(
+                &tree.children[0],
+                renderer,
+                theme,
+                style,
+                content,
+                cursor,
+                &(*viewport * transformation.inverse()),
+            );
+        });
+    }
+}
+
+impl<'a, Message, Theme, Renderer> From<ScrollIn<'a, Message, Theme, Renderer>>
+    for Element<'a, Message, Theme, Renderer>
+where
+    Message: 'a,
+    Theme: 'a,
+    Renderer: renderer::Renderer + 'a,
+{
+    fn from(scroll: ScrollIn<'a, Message, Theme, Renderer>) -> Self {
+        Element::new(scroll)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn easings_start_and_end_in_place() {
+        for easing in Easing::ALL {
+            assert_eq!(easing.apply(0.0), 0.0);
+            assert_eq!(easing.apply(1.0), 1.0);
+            assert_eq!(easing.apply(2.0), 1.0);
+        }
+        assert_eq!(Easing::Linear.apply(0.25), 0.25);
+        assert!(Easing::EaseOut.apply(0.25) > 0.25);
+        assert!(Easing::EaseInOut.apply(0.25) < 0.25);
+        assert_eq!(Easing::EaseInOut.apply(0.5), 0.5);
+    }
+
+    #[test]
+    fn only_stepping_to_the_next_line_animates() {
+        let start = Instant::now();
+        let duration = Duration::from_millis(200);
+        let mut tracker = LineTracker::default();
+        tracker.follow(Some(0), start);
+        assert_eq!(tracker.frame(start, duration, Easing::Linear), None);
+
+        tracker.follow(Some(1), start);
+        assert_eq!(
+            tracker.frame(start + duration / 2, duration, Easing::Linear),
+            Some(TransitionFrame {
+                from: 0,
+                progress: 0.5
+            })
+        );
+        assert_eq!(
+            tracker.frame(start + duration, duration, Easing::Linear),
+            None
+        );
+
+        tracker.follow(Some(5), start);
+        assert_eq!(tracker.frame(start, duration, Easing::Linear), None);
+        tracker.follow(Some(4), start);
+        assert_eq!(tracker.frame(start, duration, Easing::Linear), None);
+    }
+}
@@ -0,0 +1,274 @@
+//! Full-song lyric video export. Every frame of a timeline is drawn with the
+//! headless renderer at a fixed rate, so nothing is dropped the way a
+//! real-time screen recording drops frames.
+//!
+//! `kg-capture-render video <lyrics> <output> [--fps 30] [--size WxH]
+//! [--appearance preset.json]` writes a YUV4MPEG2 stream when the output
+//! ends in `.y4m` or is `-` for standard output, and otherwise a directory of
+//! `frame-000000.png` files that keep the window's transparency.
+
+use std::fs;
+use std::io::{self, BufWriter, Write};
+use std::path::{Path, PathBuf};
+
+use iced::Size;
+use kg_capture_protocol::LyricTimeline;
+
+use crate::LyricsAppearance;
+use crate::practice::{self, practice_duration_ms};
+use crate::render::{self, DEFAULT_SIZE, FrameRenderer};
+
+pub const DEFAULT_FPS: u32 = 30;
+const MAX_FPS: u32 = 240;
+/// Kept after the last line so its highlight is seen complete.
+const TAIL_MS: f32 = 2_000.0;
+
+const USAGE: &str = "usage: kg-capture-render video <lyrics.lrc|ttml|xml|vtt> <output.y4m|-|directory> \
+                     [--fps <rate>] [--size <width>x<height>] [--appearance <preset.json>]";
+
+pub fn frame_count(timeline: &LyricTimeline, fps: u32) -> u64 {
+    let duration_ms = f64::from(practice_duration_ms(timeline) + TAIL_MS);
+    (duration_ms * f64::from(fps) / 1_000.0).ceil() as u64
+}
+
+fn frame_time_ms(frame: u64, fps: u32) -> f32 {
+    (frame as f64 * 1_000.0 / f64::from(fps)) as f32
+}
+
+/// Writes `frame-000000.png`, `frame-000001.png`, … into `directory`. The
+/// progress callback receives the frames written so far and the total.
+pub fn write_png_sequence(
+    timeline: &LyricTimeline,
+    appearance: &LyricsAppearance,
+    size: Size<u32>,
+    fps: u32,
+    directory: &Path,
+    progress: &mut dyn FnMut(u64, u64),
+) -> Result<u64, String> {
+    fs::create_dir_all(directory)
+        .map_err(|error| format!("create {}: {error}", directory.display()))?;
+    let mut renderer = FrameRenderer::new(size)?;
+    let frames = frame_count(timeline, fps);
+    for frame in 0..frames {
+        let mut pixels = renderer.render(timeline, appearance, frame_time_ms(frame, fps));
+        render::unpremultiply(&mut pixels);
+        let path = directory.join(format!("frame-{frame:06}.png"));
+        fs::write(&path, render::encode_png(&pixels, size)?)
+            .map_err(|error| format!("write {}: {error}", path.display()))?;
+        progress(frame + 1, frames);
+    }
+    Ok(frames)
+}
+
+/// Writes an uncompressed 4:4:4 YUV4MPEG2 stream. Transparent pixels are
+/// composited over black, since the format has no alpha channel.
+pub fn write_y4m(
+    timeline: &LyricTimeline,
+    appearance: &LyricsAppearance,
+    size: Size<u32>,
+    fps: u32,
+    output: &mut dyn Write,
+    progress: &mut dyn FnMut(u64, u64),
+) -> Result<u64, String> {
+    let mut renderer = FrameRenderer::new(size)?;
+    let frames = frame_count(timeline, fps);
+    let write_error = |error: io::Error| format!("write video: {error}");
+    writeln!(
+        output,
+        "YUV4MPEG2 W{} H{} F{fps}:1 Ip A1:1 C444",
+        size.width, size.height
+    )
+    .map_err(write_error)?;
+    for frame in 0..frames {
+        let pixels = renderer.render(timeline, appearance, frame_time_ms(frame, fps));
+        output.write_all(b"FRAME\n").map_err(write_error)?;
+        output
+            .write_all(&yuv444_planes(&pixels))
+            .map_err(write_error)?;
+        progress(frame + 1, frames);
+    }
+    output.flush().map_err(write_error)?;
+    Ok(frames)
+}
+
+/// Converts premultiplied RGBA to limited-range BT.601 Y, Cb and Cr planes,
+/// the colorimetry ffmpeg assumes for an untagged stream. Premultiplied
+/// colors are already composited over black.
+fn yuv444_planes(rgba: &[u8]) -> Vec<u8> {
+    let pixels = rgba.len() / 4;
+    let mut planes = vec![0; pixels * 3];
+    let (luma, chroma) = planes.split_at_mut(pixels);
+    let (blue, red) = chroma.split_at_mut(pixels);
+    for (index, pixel) in rgba.as_chunks::<4>().0.iter().enumerate() {
+        let [r, g, b] = [pixel[0], pixel[1], pixel[2]].map(|channel| f32::from(channel) / 255.0);
+        luma[index] = (16.0 + 65.481 * r + 128.553 * g + 24.966 * b).round() as u8;
+        blue[index] = (128.0 - 37.797 * r - 74.203 * g + 112.0 * b).round() as u8;
+        red[index] = (128.0 + 112.0 * r - 93.786 * g - 18.214 * b).round() as u8;
+    }
+    planes
+}
+
+struct Arguments {
+    lyrics: PathBuf,
+    output: PathBuf,
+    fps: u32,
+    size: Size<u32>,
+    appearance: Option<PathBuf>,
+}
+
+/// Runs `kg-capture-render video` with the arguments after the subcommand.
+pub fn run(arguments: impl IntoIterator<Item = String>) -> Result<(), String> {
+    let arguments = parse_arguments(arguments)?;
+    let timeline = practice::load_timeline(&arguments.lyrics)?;
+    let appearance = render::load_appearance(arguments.appearance.as_deref())?;
+    let mut progress = |written: u64, total: u64| {
+        if written.is_multiple_of(u64::from(arguments.fps)) || written == total {
+            eprint!("\rrendered {written}/{total} frames");
+        }
+    };
+
+    let (size, fps) = (arguments.size, arguments.fps);
+    let stream: Option<Box<dyn Write>> = if arguments.output == Path::new("-") {
+        Some(Box::new(io::stdout().lock()))
+    } else if arguments
+        .output
+        .extension()
+        .is_some_and(|extension| extension.eq_ignore_ascii_case("y4m"))
+    {
+        let file = fs::File::create(&arguments.output)
+            .map_err(|error| format!("create {}: {error}", arguments.output.display()))?;
+        Some(Box::new(file))
+    } else {
+        None
+    };
+    let frames = match stream {
+        Some(stream) => write_y4m(
+            &timeline,
+            &appearance,
+            size,
+            fps,
+            &mut BufWriter::new(stream),
+            &mut progress,
+        )?,
+        None => write_png_sequence(
+            &timeline,
+            &appearance,
+            size,
+            fps,
+            &arguments.output,
+            &mut progress,
+        )?,
+    };
+    eprintln!("\rrendered {frames} frames at {fps} fps");
+    Ok(())
+}
+
+fn parse_arguments(arguments: impl IntoIterator<Item = String>) -> Result<Arguments, String> {
+    let mut positional = Vec::new();
+    let mut fps = DEFAULT_FPS;
+    let mut size = DEFAULT_SIZE;
+    let mut appearance = None;
+    let mut arguments = arguments.into_iter();
+    while let Some(argument) = arguments.next() {
+        match argument.as_str() {
+            "--fps" => {
+                let rate = arguments.next().ok_or(USAGE)?;
+                fps = rate
+                    .parse()
+                    .ok()
+                    .filter(|fps| (1..=MAX_FPS).contains(fps))
+                    .ok_or_else(|| format!("invalid frame rate {rate}; expected 1 to {MAX_FPS}"))?;
+            }
+            "--size" => size = render::parse_size(&arguments.next().ok_or(USAGE)?)?,
+            "--appearance" => appearance = Some(PathBuf::from(arguments.next().ok_or(USAGE)?)),
+            "-" => positional.push(argument),
+            _ if argument.starts_with('-') => {
+                return Err(format!("unknown option {argument}\n{USAGE}"));
+            }
+            _ => positional.push(argument),
+        }
+    }
+    let [lyrics, output] = <[String; 2]>::try_from(positional).map_err(|_| USAGE)?;
+    Ok(Arguments {
+        lyrics: lyrics.into(),
+        output: output.into(),
+        fps,
+        size,
+        appearance,
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::fixtures;
+    use crate::settings::AppearanceSettings;
+
+    #[test]
+    fn frames_cover_the_song_and_its_tail() {
+        let timeline = fixtures::timeline(1, &[("把爱留在身边", 0.0, 1_000.0)]);
+        assert_eq!(frame_count(&timeline, 30), 90);
+        assert_eq!(frame_count(&timeline, 1), 3);
+        assert_eq!(frame_time_ms(45, 30), 1_500.0);
+    }
+
+    #[test]
+    fn y4m_streams_hold_a_header_and_every_frame() {
+        let timeline = fixtures::timeline(1, &[("把爱留在身边", 0.0, 1_000.0)]);
+        let appearance = render::appearance(&AppearanceSettings::default());
+        let size = Size::new(16, 8);
+        let mut output = Vec::new();
+        let mut reported = 0;
+        let mut progress = |written, _| reported = written;
+        let frames =
+            write_y4m(&timeline, &appearance, size, 2, &mut output, &mut progress).unwrap();
+
+        let header = b"YUV4MPEG2 W16 H8 F2:1 Ip A1:1 C444\n";
+        assert_eq!(frames, 6);
+        assert_eq!(reported, 6);
+        assert!(output.starts_with(header));
+        assert_eq!(output.len(), header.len() + 6 * (6 + 3 * 16 * 8));
+    }
+
+    #[test]
+    fn converts_to_limited_range_yuv() {
+        assert_eq!(
+            yuv444_planes(&[0, 0, 0, 255, 255, 255, 255, 255]),
+            [16, 235, 128, 128, 128, 128]
+        );
+        assert_eq!(yuv444_planes(&[255, 0, 0, 255]), [81, 90, 240]);
+    }
+
+    #[test]
+    fn png_sequences_are_numbered_from_zero() {
+        let directory =
+            std::env::temp_dir().join(format!("kg-capture-video-{}", std::process::id()));
+        let timeline = fixtures::timeline(1, &[("把爱留在身边", 0.0, 1_000.0)]);
+        let appearance = render::appearance(&AppearanceSettings::default());
+        let frames = write_png_sequence(
+            &timeline,
+            &appearance,
+            Size::new(16, 8),
+            1,
+            &directory,
+            &mut |_, _| {},
+        )
+        .unwrap();
+        assert_eq!(frames, 3);
+        assert!(directory.join("frame-000000.png").exists());
+        assert!(directory.join("frame-000002.png").exists());
+        fs::remove_dir_all(directory).unwrap();
+    }
+
+    #[test]
+    fn parses_command_line_arguments() {
+        let arguments =
+            parse_arguments(["song.lrc", "-", "--fps", "60"].map(String::from)).unwrap();
+        assert_eq!(arguments.output, PathBuf::from("-"));
+        assert_eq!(arguments.fps, 60);
+        assert_eq!(arguments.size, DEFAULT_SIZE);
+
+        assert!(parse_arguments(["song.lrc", "out", "--fps", "0"].map(String::from)).is_err());
+        assert!(parse_arguments(["song.lrc"].map(String::from)).is_err());
+    }
+}
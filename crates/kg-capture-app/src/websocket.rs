@@ -0,0 +1,562 @@
+//! Local WebSocket server for browser overlays such as OBS browser sources,
+//! which cannot read the hook's IPC channel. Every timeline, playback
+//! position and playback state the app handles is sent to each client as a
+//! JSON text message, along with the lyric window's appearance and timing
+//! offset, and a disconnect clears the song; a client that connects mid-song
+//! first receives the current state. Plain HTTP requests on the same port get
+//! the built-in [overlay](crate::overlay) page.
+
+use std::io::ErrorKind;
+use std::net::{Ipv4Addr, SocketAddr, TcpListener, TcpStream};
+use std::sync::atomic::{AtomicBool, AtomicUsize, Ordering};
+use std::sync::{Arc, Mutex, mpsc};
+use std::thread::{self, JoinHandle};
+use std::time::Duration;
+
+use kg_capture_protocol::{LyricTimeline, PlaybackPosition, PlaybackState};
+use serde::{Deserialize, Serialize};
+use tungstenite::{Message, Utf8Bytes};
+
+use crate::overlay;
+use crate::settings::AppearanceSettings;
+
+pub const DEFAULT_PORT: u16 = 47_800;
+const ACCEPT_POLL_INTERVAL: Duration = Duration::from_millis(50);
+/// Connections beyond this are closed at once, so a runaway page or a port
+/// scan cannot start a thread per connection.
+const MAX_CLIENTS: usize = 32;
+/// A client that stops reading for this long is dropped instead of holding
+/// its messages forever.
+const CLIENT_TIMEOUT: Duration = Duration::from_secs(5);
+/// How long a client thread waits for a frame from the browser before it
+/// sends queued messages again.
+const CLIENT_POLL_INTERVAL: Duration = Duration::from_millis(20);
+
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
+#[serde(default)]
+pub struct WebSocketSettings {
+    pub enabled: bool,
+    pub port: u16,
+    /// Listens on every interface instead of loopback only, so overlays on
+    /// another machine can connect.
+    pub allow_remote: bool,
+}
+
+impl Default for WebSocketSettings {
+    fn default() -> Self {
+        Self {
+            enabled: false,
+            port: DEFAULT_PORT,
+            allow_remote: false,
+        }
+    }
+}
+
+impl WebSocketSettings {
+    pub fn address(&self) -> SocketAddr {
+        let ip = if self.allow_remote {
+            Ipv4Addr::UNSPECIFIED
+        } else {
+            Ipv4Addr::LOCALHOST
+        };
+        SocketAddr::from((ip, self.port))
+    }
+}
+
+#[derive(Serialize)]
+#[serde(tag = "type", rename_all = "snake_case")]
+enum OverlayMessage<'a> {
+    Timeline {
+        timeline: &'a LyricTimeline,
+    },
+    Playback {
+        playback: &'a PlaybackPosition,
+    },
+    PlaybackState {
+        timeline_id: u64,
+        state: PlaybackState,
+    },
+    /// The app disconnected and shows no song.
+    Cleared,
+    Appearance {
+        appearance: &'a AppearanceSettings,
+    },
+    TimingOffset {
+        offset_ms: i32,
+    },
+}
+
+impl OverlayMessage<'_> {
+    fn encode(&self) -> Utf8Bytes {
+        serde_json::to_string(self)
+            .expect("overlay messages serialize")
+            .into()
+    }
+}
+
+/// Runs until dropped. Each client gets its own thread and queue, so a slow
+/// browser never blocks the UI thread.
+pub struct LyricsServer {
+    address: SocketAddr,
+    shared: Arc<Mutex<Shared>>,
+    stopped: Arc<AtomicBool>,
+    accept_thread: Option<JoinHandle<()>>,
+}
+
+#[derive(Default)]
+struct Shared {
+    appearance: Option<Utf8Bytes>,
+    timing_offset: Option<Utf8Bytes>,
+    /// The current timeline, or the cleared message once the song is gone.
+    timeline: Option<Utf8Bytes>,
+    playback: Option<Utf8Bytes>,
+    playback_state: Option<Utf8Bytes>,
+    clients: Vec<mpsc::Sender<Utf8Bytes>>,
+    closed: bool,
+}
+
+impl LyricsServer {
+    pub fn start(address: SocketAddr) -> Result<Self, String> {
+        let listener =
+            TcpListener::bind(address).map_err(|error| format!("listen on {address}: {error}"))?;
+        let address = listener
+            .local_addr()
+            .map_err(|error| format!("listen on {address}: {error}"))?;
+        listener
+            .set_nonblocking(true)
+            .map_err(|error| format!("listen on {address}: {error}"))?;
+        let shared = Arc::new(Mutex::new(Shared::default()));
+        let stopped = Arc::new(AtomicBool::new(false));
+        let accept_thread = {
+            let shared = Arc::clone(&shared);
+            let stopped = Arc::clone(&stopped);
+            thread::Builder::new()
+                .name("websocket-server".into())
+                .spawn(move || accept_clients(&listener, &shared, &stopped))
+                .map_err(|error| format!("start WebSocket server: {error}"))?
+        };
+        Ok(Self {
+            address,
+            shared,
+            stopped,
+            accept_thread: Some(accept_thread),
+        })
+    }
+
+    pub fn address(&self) -> SocketAddr {
+        self.address
+    }
+
+    /// Sends a new song and forgets the previous song's position and state.
+    pub fn publish_timeline(&self, timeline: &LyricTimeline) {
+        self.replace_song(OverlayMessage::Timeline { timeline }.encode());
+    }
+
+    /// Tells overlays that no song is shown any more.
+    pub fn publish_cleared(&self) {
+        self.replace_song(OverlayMessage::Cleared.encode());
+    }
+
+    fn replace_song(&self, message: Utf8Bytes) {
+        if let Ok(mut shared) = self.shared.lock() {
+            shared.timeline = Some(message.clone());
+            shared.playback = None;
+            shared.playback_state = None;
+            shared.broadcast(message);
+        }
+    }
+
+    pub fn publish_playback(&self, playback: &PlaybackPosition) {
+        let message = OverlayMessage::Playback { playback }.encode();
+        if let Ok(mut shared) = self.shared.lock() {
+            shared.playback = Some(message.clone());
+            shared.broadcast(message);
+        }
+    }
+
+    pub fn publish_playback_state(&self, timeline_id: u64, state: PlaybackState) {
+        let message = OverlayMessage::PlaybackState { timeline_id, state }.encode();
+        if let Ok(mut shared) = self.shared.lock() {
+            shared.playback_state = Some(message.clone());
+            shared.broadcast(message);
+        }
+    }
+
+    /// Sends the lyric window's appearance when it differs from the last one
+    /// sent.
+    pub fn publish_appearance(&self, appearance: &AppearanceSettings) {
+        let message = OverlayMessage::Appearance { appearance }.encode();
+        if let Ok(mut shared) = self.shared.lock()
+            && shared.appearance.as_ref() != Some(&message)
+        {
+            shared.appearance = Some(message.clone());
+            shared.broadcast(message);
+        }
+    }
+
+    /// Sends the total timing offset when it differs from the last one sent.
+    pub fn publish_timing_offset(&self, offset_ms: i32) {
+        let message = OverlayMessage::TimingOffset { offset_ms }.encode();
+        if let Ok(mut shared) = self.shared.lock()
+            && shared.timing_offset.as_ref() != Some(&message)
+        {
+            shared.timing_offset = Some(message.clone());
+            shared.broadcast(message);
+        }
+    }
+}
+
+impl Drop for LyricsServer {
+    /// Waits for the listener to close, so the port can be bound again at
+    /// once.
+    fn drop(&mut self) {
+        self.stopped.store(true, Ordering::Relaxed);
+        if let Some(thread) = self.accept_thread.take() {
+            let _ = thread.join();
+        }
+        if let Ok(mut shared) = self.shared.lock() {
+            shared.clients.clear();
+            shared.closed = true;
+        }
+    }
+}
+
+impl Shared {
+    /// Clients whose thread has ended are removed here.
+    fn broadcast(&mut self, message: Utf8Bytes) {
+        self.clients
+            .retain(|client| client.send(message.clone()).is_ok());
+    }
+}
+
+fn accept_clients(listener: &TcpListener, shared: &Arc<Mutex<Shared>>, stopped: &AtomicBool) {
+    let clients = Arc::new(AtomicUsize::new(0));
+    while !stopped.load(Ordering::Relaxed) {
+        match listener.accept() {
+            Ok((stream, peer)) => {
+                if clients.load(Ordering::Relaxed) >= MAX_CLIENTS {
+                    tracing::warn!(%peer, "too many WebSocket clients; closing a connection");
+                    continue;
+                }
+                let slot = ClientSlot::take(&clients);
+                let shared = Arc::clone(shared);
+                let spawned = thread::Builder::new()
+                    .name("websocket-client".into())
+                    .spawn(move || {
+                        let _slot = slot;
+                        serve_client(stream, peer, &shared);
+                    });
+                if let Err(error) = spawned {
+                    tracing::warn!(%error, "failed to start a WebSocket client thread");
+                }
+            }
+            Err(error) if error.kind() == ErrorKind::WouldBlock => {
+                thread::sleep(ACCEPT_POLL_INTERVAL);
+            }
+            Err(error) => {
+                tracing::warn!(%error, "failed to accept a WebSocket client");
+                thread::sleep(ACCEPT_POLL_INTERVAL);
+            }
+        }
+    }
+}
+
+/// Counts a connection until its thread ends, or until it fails to start.
+struct ClientSlot(Arc<AtomicUsize>);
+
+impl ClientSlot {
+    fn take(clients: &Arc<AtomicUsize>) -> Self {
+        clients.fetch_add(1, Ordering::Relaxed);
+        Self(Arc::clone(clients))
+    }
+}
+
+impl Drop for ClientSlot {
+    fn drop(&mut self) {
+        self.0.fetch_sub(1, Ordering::Relaxed);
+    }
+}
+
+fn serve_client(stream: TcpStream, peer: SocketAddr, shared: &Mutex<Shared>) {
+    // Accepted sockets inherit non-blocking mode from the listener on Windows.
+    let configured = stream
+        .set_nonblocking(false)
+        .and_then(|()| stream.set_read_timeout(Some(CLIENT_TIMEOUT)))
+        .and_then(|()| stream.set_write_timeout(Some(CLIENT_TIMEOUT)));
+    if let Err(error) = configured {
+        tracing::warn!(%peer, %error, "failed to configure a WebSocket client");
+        return;
+    }
+    match overlay::peek_request(&stream, CLIENT_TIMEOUT) {
+        Ok(request) if request.upgrade && !request.cross_origin => {}
+        Ok(request) => {
+            if let Err(error) = overlay::respond(&stream, &request) {
+                tracing::debug!(%peer, %error, "failed to serve the overlay page");
+            }
+            return;
+        }
+        Err(error) => {
+            tracing::debug!(%peer, %error, "failed to read an HTTP request");
+            return;
+        }
+    }
+    let mut socket = match tungstenite::accept(stream) {
+        Ok(socket) => socket,
+        Err(error) => {
+            tracing::debug!(%peer, %error, "WebSocket handshake failed");
+            return;
+        }
+    };
+    tracing::info!(%peer, "WebSocket client connected");
+
+    let (sender, receiver) = mpsc::channel();
+    match shared.lock() {
+        Ok(mut shared) if !shared.closed => {
+            // Queued under the lock, so the snapshot always precedes updates.
+            let snapshot = [
+                &shared.appearance,
+                &shared.timing_offset,
+                &shared.timeline,
+                &shared.playback,
+                &shared.playback_state,
+            ];
+            for message in snapshot.into_iter().flatten() {
+                let _ = sender.send(message.clone());
+            }
+            shared.clients.push(sender);
+        }
+        _ => return,
+    }
+    if let Err(error) = socket
+        .get_ref()
+        .set_read_timeout(Some(CLIENT_POLL_INTERVAL))
+    {
+        tracing::warn!(%peer, %error, "failed to configure a WebSocket client");
+        return;
+    }
+    // Reading between sends answers pings and close frames, and notices a
+    // closed tab while no lyrics are sent.
+    loop {
+        loop {
+            match receiver.try_recv() {
+                Ok(message) => {
+                    if let Err(error) = socket.send(Message::Text(message)) {
+                        tracing::info!(%peer, %error, "WebSocket client disconnected");
+                        return;
+                    }
+                }
+                Err(mpsc::TryRecvError::Empty) => break,
+                Err(mpsc::TryRecvError::Disconnected) => {
+                    let _ = socket.close(None);
+                    let _ = socket.flush();
+                    return;
+                }
+            }
+        }
+        match socket.read() {
+            Ok(_) => {}
+            Err(tungstenite::Error::Io(error))
+                if matches!(error.kind(), ErrorKind::WouldBlock | ErrorKind::TimedOut) => {}
+            Err(tungstenite::Error::ConnectionClosed) => {
+                tracing::info!(%peer, "WebSocket client disconnected");
+                return;
+            }
+            Err(error) => {
+                tracing::info!(%peer, %error, "WebSocket client disconnected");
+                return;
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::fixtures;
+    use serde_json::Value;
+    use std::io::{Read, Write};
+    use tungstenite::client::IntoClientRequest;
+
+    fn read_json(socket: &mut tungstenite::WebSocket<TcpStream>) -> Value {
+        let message = socket.read().unwrap();
+        serde_json::from_str(message.to_text().unwrap()).unwrap()
+    }
+
+    fn playback(position_ms: f32) -> PlaybackPosition {
+        PlaybackPosition {
+            timeline_id: 7,
+            observed_at_micros: 0,
+            position_ms,
+            current_line: Some(0),
+            line_progress: 0.5,
+        }
+    }
+
+    #[test]
+    fn late_joiners_receive_a_snapshot_then_updates() {
+        let server = LyricsServer::start(SocketAddr::from((Ipv4Addr::LOCALHOST, 0))).unwrap();
+        server.publish_timeline(&fixtures::timeline(7, &[("把爱留在身边", 0.0, 2_000.0)]));
+        server.publish_playback(&playback(1_000.0));
+
+        let stream = TcpStream::connect(server.address()).unwrap();
+        stream.set_read_timeout(Some(CLIENT_TIMEOUT)).unwrap();
+        let url = format!("ws://{}/", server.address());
+        let (mut socket, _) = tungstenite::client(url.as_str(), stream).unwrap();
+
+        let timeline = read_json(&mut socket);
+        assert_eq!(timeline["type"], "timeline");
+        assert_eq!(timeline["timeline"]["id"], 7);
+        assert_eq!(timeline["timeline"]["lines"][0]["text"], "把爱留在身边");
+        let snapshot = read_json(&mut socket);
+        assert_eq!(snapshot["type"], "playback");
+        assert_eq!(snapshot["playback"]["position_ms"], 1_000.0);
+
+        server.publish_playback(&playback(1_500.0));
+        assert_eq!(read_json(&mut socket)["playback"]["position_ms"], 1_500.0);
+
+        drop(server);
+        assert!(matches!(
+            socket.read(),
+            Ok(Message::Close(_)) | Err(tungstenite::Error::ConnectionClosed)
+        ));
+    }
+
+    #[test]
+    fn stops_and_disconnects_replace_the_song_for_late_joiners() {
+        let server = LyricsServer::start(SocketAddr::from((Ipv4Addr::LOCALHOST, 0))).unwrap();
+        server.publish_timeline(&fixtures::timeline(7, &[]));
+        server.publish_playback(&playback(1_000.0));
+        server.publish_playback_state(7, PlaybackState::Stopped);
+        let snapshot: Vec<Value> = {
+            let shared = server.shared.lock().unwrap();
+            [&shared.timeline, &shared.playback, &shared.playback_state]
+                .into_iter()
+                .flatten()
+                .map(|message| serde_json::from_str(message).unwrap())
+                .collect()
+        };
+        assert_eq!(snapshot[2]["type"], "playback_state");
+        assert_eq!(snapshot[2]["timeline_id"], 7);
+        assert_eq!(snapshot[2]["state"], "Stopped");
+
+        server.publish_cleared();
+        let shared = server.shared.lock().unwrap();
+        assert_eq!(shared.timeline.as_deref(), Some(r#"{"type":"cleared"}"#));
+        assert!(shared.playback.is_none() && shared.playback_state.is_none());
+    }
+
+    #[test]
+    fn idle_clients_answer_pings_and_close_frames() {
+        let server = LyricsServer::start(SocketAddr::from((Ipv4Addr::LOCALHOST, 0))).unwrap();
+        let stream = TcpStream::connect(server.address()).unwrap();
+        stream.set_read_timeout(Some(CLIENT_TIMEOUT)).unwrap();
+        let url = format!("ws://{}/", server.address());
+        let (mut socket, _) = tungstenite::client(url.as_str(), stream).unwrap();
+
+        socket.send(Message::Ping("keepalive".into())).unwrap();
+        assert_eq!(socket.read().unwrap(), Message::Pong("keepalive".into()));
+
+        socket.close(None).unwrap();
+        assert!(matches!(socket.read(), Ok(Message::Close(_))));
+        assert!(matches!(
+            socket.read(),
+            Err(tungstenite::Error::ConnectionClosed)
+        ));
+
+        // The client's thread has ended, so its queue is gone.
+        let deadline = std::time::Instant::now() + CLIENT_TIMEOUT;
+        loop {
+            let mut shared = server.shared.lock().unwrap();
+            shared.broadcast(OverlayMessage::Cleared.encode());
+            if shared.clients.is_empty() {
+                break;
+            }
+            assert!(std::time::Instant::now() < deadline);
+            drop(shared);
+            thread::sleep(CLIENT_POLL_INTERVAL);
+        }
+    }
+
+    #[test]
+    fn plain_http_requests_get_the_overlay_page() {
+        let server = LyricsServer::start(SocketAddr::from((Ipv4Addr::LOCALHOST, 0))).unwrap();
+        let mut stream = TcpStream::connect(server.address()).unwrap();
+        stream.set_read_timeout(Some(CLIENT_TIMEOUT)).unwrap();
+        stream
+            .write_all(b"GET / HTTP/1.1\r\nHost: localhost\r\n\r\n")
+            .unwrap();
+        let mut response = String::new();
+        stream.read_to_string(&mut response).unwrap();
+        assert!(response.starts_with("HTTP/1.1 200 OK\r\n"));
+        assert!(response.contains("Content-Type: text/html; charset=utf-8"));
+        assert!(response.contains("<script src=\"overlay.js\"></script>"));
+    }
+
+    #[test]
+    fn appearance_is_only_resent_when_it_changes() {
+        let server = LyricsServer::start(SocketAddr::from((Ipv4Addr::LOCALHOST, 0))).unwrap();
+        let appearance = AppearanceSettings::default();
+        server.publish_appearance(&appearance);
+        let (sender, receiver) = mpsc::channel();
+        server.shared.lock().unwrap().clients.push(sender);
+
+        server.publish_appearance(&appearance);
+        server.publish_timing_offset(0);
+        server.publish_timing_offset(0);
+        server.publish_appearance(&AppearanceSettings {
+            active_font_size: 48.0,
+            ..appearance
+        });
+        let types: Vec<String> = receiver
+            .try_iter()
+            .map(|message| serde_json::from_str::<Value>(&message).unwrap()["type"].to_string())
+            .collect();
+        assert_eq!(types, ["\"timing_offset\"", "\"appearance\""]);
+    }
+
+    #[test]
+    fn listens_on_loopback_unless_remote_access_is_allowed() {
+        let settings = WebSocketSettings::default();
+        assert_eq!(
+            settings.address(),
+            SocketAddr::from((Ipv4Addr::LOCALHOST, DEFAULT_PORT))
+        );
+        let remote = WebSocketSettings {
+            allow_remote: true,
+            ..settings
+        };
+        assert!(remote.address().ip().is_unspecified());
+    }
+
+    #[test]
+    fn other_sites_cannot_open_a_websocket() {
+        let server = LyricsServer::start(SocketAddr::from((Ipv4Addr::LOCALHOST, 0))).unwrap();
+        let stream = TcpStream::connect(server.address()).unwrap();
+        stream.set_read_timeout(Some(CLIENT_TIMEOUT)).unwrap();
+        let mut request = format!("ws://{}/", server.address())
+            .into_client_request()
+            .unwrap();
+        request
+            .headers_mut()
+            .insert("Origin", "https://example.com".parse().unwrap());
+        let Err(tungstenite::HandshakeError::Failure(tungstenite::Error::Http(response))) =
+            tungstenite::client(request, stream)
+        else {
+            panic!("a cross-origin handshake succeeded");
+        };
+        assert_eq!(response.status(), 403);
+    }
+
+    #[test]
+    fn connections_beyond_the_limit_are_closed() {
+        let server = LyricsServer::start(SocketAddr::from((Ipv4Addr::LOCALHOST, 0))).unwrap();
+        // Idle connections hold their slot while the server waits for a
+        // request.
+        let _held: Vec<_> = (0..MAX_CLIENTS)
+            .map(|_| TcpStream::connect(server.address()).unwrap())
+            .collect();
+        let mut refused = TcpStream::connect(server.address()).unwrap();
+        refused.set_read_timeout(Some(CLIENT_TIMEOUT)).unwrap();
+        assert_eq!(refused.read(&mut [0; 1]).unwrap(), 0);
+    }
+}
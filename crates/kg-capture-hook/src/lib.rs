@@ -1,5 +1,7 @@
 //! x86 DLL loaded into WeSing. It extracts lyric model state rather than pixels.
 
+mod playback;
+
 use std::cell::Cell;
 use std::ffi::c_void;
 use std::fs::{self, File, OpenOptions};
@@ -8,16 +10,17 @@ use std::mem::{size_of, transmute};
 use std::path::Path;
 use std::ptr;
 use std::sync::atomic::{AtomicBool, AtomicU64, Ordering};
-use std::sync::mpsc::{self, SyncSender};
+use std::sync::mpsc::{self, RecvTimeoutError, SyncSender};
 use std::sync::{Mutex, OnceLock};
 use std::thread;
-use std::time::{Duration, SystemTime, UNIX_EPOCH};
+use std::time::{Duration, Instant, SystemTime, UNIX_EPOCH};
 
 use ipc_channel::ipc::{self, IpcSender};
 use kg_capture_protocol::{
     HookBootstrap, HookEvent, HookHandshake, HookHello, HostCommand, LyricLine, LyricSource,
     LyricTimeline, LyricWord, PROTOCOL_VERSION, PlaybackPosition,
 };
+use playback::PlaybackTracker;
 use retour::GenericDetour;
 use windows::Win32::Foundation::{HINSTANCE, TRUE};
 use windows::Win32::System::LibraryLoader::{DisableThreadLibraryCalls, GetModuleHandleW};
@@ -180,7 +183,17 @@ fn run_hook(endpoint: String, nonce: kg_capture_protocol::SessionNonce) {
     if thread::Builder::new()
         .name("kg-capture-events".into())
         .spawn(move || {
-            while let Ok(event) = queue_receiver.recv() {
+            loop {
+                // WeSing may stop calling the render update on pause, so stalls
+                // are also checked whenever the queue is idle.
+                let event = match queue_receiver.recv_timeout(playback::STALL_AFTER / 4) {
+                    Ok(event) => event,
+                    Err(RecvTimeoutError::Timeout) => match stalled_playback_state() {
+                        Some(event) => event,
+                        None => continue,
+                    },
+                    Err(RecvTimeoutError::Disconnected) => break,
+                };
                 if worker_sender.send(event).is_err() {
                     break;
                 }
@@ -426,6 +439,7 @@ fn capture_render_state(
             {
                 state.identity = Some(identity);
                 state.timeline_id = id;
+                state.tracker = PlaybackTracker::new(&lines);
                 hook_log(
                     LogLevel::Info,
                     format_args!("timeline extracted id={id} lines={}", lines.len()),
@@ -451,9 +465,38 @@ fn capture_render_state(
             current_line,
             line_progress: snapshot.line_progress.clamp(0.0, 1.0),
         }));
+        if let Some(playback_state) = state.tracker.observe(position_ms, Instant::now()) {
+            hook_log(
+                LogLevel::Debug,
+                format_args!("playback state {playback_state:?} position={position_ms}"),
+            );
+            queue(HookEvent::PlaybackState {
+                timeline_id: state.timeline_id,
+                state: playback_state,
+            });
+        }
     });
 }
 
+fn stalled_playback_state() -> Option<HookEvent> {
+    if !HOOKS_ACTIVE.load(Ordering::Acquire) {
+        return None;
+    }
+    let mut state = CAPTURE_STATE.get()?.lock().ok()?;
+    if state.timeline_id == 0 {
+        return None;
+    }
+    let playback_state = state.tracker.tick(Instant::now())?;
+    hook_log(
+        LogLevel::Debug,
+        format_args!("playback state {playback_state:?} after render updates stalled"),
+    );
+    Some(HookEvent::PlaybackState {
+        timeline_id: state.timeline_id,
+        state: playback_state,
+    })
+}
+
 fn emit_fixture(position_ms: f32) {
     const DURATION: f32 = 2_400.0;
     let lines = fixture_lines();
@@ -608,6 +651,7 @@ fn timestamp_micros() -> u64 {
 struct CaptureState {
     identity: Option<(LyricSource, usize, usize)>,
     timeline_id: u64,
+    tracker: PlaybackTracker,
 }
 
 struct ResetCell<'a>(&'a Cell<bool>);
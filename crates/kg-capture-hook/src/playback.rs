@@ -0,0 +1,243 @@
+//! Playback state derived from the positions WeSing passes to its lyric render
+//! update. WeSing has no state callback we hook, so pauses, seeks and the end
+//! of a song are recognised from how the position moves against wall time.
+
+use std::time::{Duration, Instant};
+
+use kg_capture_protocol::{LyricLine, PlaybackState};
+
+/// A position that has not moved for this long is stalled, whether WeSing
+/// keeps calling the update with the same value or stops calling it.
+pub const STALL_AFTER: Duration = Duration::from_millis(400);
+/// Movement this far from the elapsed wall time is a seek.
+const SEEK_TOLERANCE_MS: f32 = 1_000.0;
+/// Smaller changes are rounding noise in a paused position.
+const MOVE_EPSILON_MS: f32 = 1.0;
+/// A stall at or before this position is a stopped song.
+const STOPPED_POSITION_MS: f32 = 50.0;
+/// A stall within this distance of the end of the last line is a finished
+/// song; WeSing may stop slightly before the final line's nominal end.
+const END_TOLERANCE_MS: f32 = 1_000.0;
+
+#[derive(Debug, Default)]
+pub struct PlaybackTracker {
+    end_ms: f32,
+    last: Option<Observation>,
+    state: Option<PlaybackState>,
+}
+
+#[derive(Clone, Copy, Debug)]
+struct Observation {
+    position_ms: f32,
+    observed_at: Instant,
+    moved_at: Instant,
+}
+
+impl PlaybackTracker {
+    /// Starts tracking a new timeline.
+    pub fn new(lines: &[LyricLine]) -> Self {
+        Self {
+            end_ms: lines
+                .iter()
+                .map(|line| line.start_ms + line.duration_ms.max(0.0))
+                .fold(0.0, f32::max),
+            last: None,
+            state: None,
+        }
+    }
+
+    /// Records a render position and returns the new state if it changed.
+    pub fn observe(&mut self, position_ms: f32, now: Instant) -> Option<PlaybackState> {
+        let Some(last) = self.last else {
+            self.last = Some(Observation {
+                position_ms,
+                observed_at: now,
+                moved_at: now,
+            });
+            return None;
+        };
+        let elapsed = now.saturating_duration_since(last.observed_at);
+        // After a gap in updates the position is expected to have held.
+        let expected_ms = if elapsed < STALL_AFTER {
+            elapsed.as_secs_f32() * 1_000.0
+        } else {
+            0.0
+        };
+        let advanced_ms = position_ms - last.position_ms;
+        let moved = advanced_ms.abs() > MOVE_EPSILON_MS;
+        self.last = Some(Observation {
+            position_ms,
+            observed_at: now,
+            moved_at: if moved { now } else { last.moved_at },
+        });
+
+        let state = if moved && (advanced_ms - expected_ms).abs() > SEEK_TOLERANCE_MS {
+            PlaybackState::Seeking
+        } else if moved {
+            PlaybackState::Playing
+        } else if now.saturating_duration_since(last.moved_at) >= STALL_AFTER {
+            self.stalled_state(position_ms)
+        } else {
+            return None;
+        };
+        self.transition(state)
+    }
+
+    /// Checks for a stall while no updates arrive, and returns the new state
+    /// if it changed.
+    pub fn tick(&mut self, now: Instant) -> Option<PlaybackState> {
+        let last = self.last?;
+        if now.saturating_duration_since(last.moved_at) < STALL_AFTER {
+            return None;
+        }
+        self.transition(self.stalled_state(last.position_ms))
+    }
+
+    fn stalled_state(&self, position_ms: f32) -> PlaybackState {
+        if position_ms <= STOPPED_POSITION_MS {
+            PlaybackState::Stopped
+        } else if position_ms >= self.end_ms - END_TOLERANCE_MS {
+            PlaybackState::SongEnded
+        } else {
+            PlaybackState::Paused
+        }
+    }
+
+    fn transition(&mut self, state: PlaybackState) -> Option<PlaybackState> {
+        (self.state != Some(state)).then(|| {
+            self.state = Some(state);
+            state
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// A song whose last line ends at 10 s.
+    fn song_tracker() -> PlaybackTracker {
+        PlaybackTracker::new(&[
+            LyricLine {
+                index: 0,
+                text: "把爱留在身边".into(),
+                start_ms: 2_000.0,
+                duration_ms: 4_000.0,
+                words: Vec::new(),
+            },
+            LyricLine {
+                index: 1,
+                text: "窗外有个蓝蓝的天".into(),
+                start_ms: 6_000.0,
+                duration_ms: 4_000.0,
+                words: Vec::new(),
+            },
+        ])
+    }
+
+    fn at(start: Instant, elapsed_ms: u64) -> Instant {
+        start + Duration::from_millis(elapsed_ms)
+    }
+
+    #[test]
+    fn steady_positions_report_playing_once() {
+        let start = Instant::now();
+        let mut tracker = song_tracker();
+        assert_eq!(tracker.observe(3_000.0, start), None);
+        assert_eq!(
+            tracker.observe(3_016.0, at(start, 16)),
+            Some(PlaybackState::Playing)
+        );
+        assert_eq!(tracker.observe(3_032.0, at(start, 32)), None);
+        assert_eq!(tracker.tick(at(start, 100)), None);
+    }
+
+    #[test]
+    fn jumps_against_wall_time_are_seeks() {
+        let start = Instant::now();
+        let mut tracker = song_tracker();
+        tracker.observe(3_000.0, start);
+        tracker.observe(3_016.0, at(start, 16));
+        assert_eq!(
+            tracker.observe(8_000.0, at(start, 32)),
+            Some(PlaybackState::Seeking)
+        );
+        assert_eq!(
+            tracker.observe(8_016.0, at(start, 48)),
+            Some(PlaybackState::Playing)
+        );
+        assert_eq!(
+            tracker.observe(2_000.0, at(start, 64)),
+            Some(PlaybackState::Seeking)
+        );
+    }
+
+    #[test]
+    fn a_held_position_pauses_after_the_stall_time() {
+        let start = Instant::now();
+        let mut tracker = song_tracker();
+        tracker.observe(4_000.0, start);
+        tracker.observe(4_016.0, at(start, 16));
+        assert_eq!(tracker.observe(4_016.0, at(start, 200)), None);
+        assert_eq!(
+            tracker.observe(4_016.5, at(start, 16 + 400)),
+            Some(PlaybackState::Paused)
+        );
+
+        // Resuming after a gap in updates is not mistaken for a seek.
+        assert_eq!(
+            tracker.observe(4_032.0, at(start, 3_000)),
+            Some(PlaybackState::Playing)
+        );
+    }
+
+    #[test]
+    fn missing_updates_stall_through_tick() {
+        let start = Instant::now();
+        let mut tracker = song_tracker();
+        assert_eq!(tracker.tick(start), None);
+        tracker.observe(4_000.0, start);
+        tracker.observe(4_016.0, at(start, 16));
+        assert_eq!(tracker.tick(at(start, 300)), None);
+        assert_eq!(tracker.tick(at(start, 416)), Some(PlaybackState::Paused));
+        assert_eq!(tracker.tick(at(start, 800)), None);
+    }
+
+    #[test]
+    fn a_stall_at_the_start_is_a_stop() {
+        let start = Instant::now();
+        let mut tracker = song_tracker();
+        tracker.observe(4_000.0, start);
+        tracker.observe(4_016.0, at(start, 16));
+        assert_eq!(
+            tracker.observe(50.0, at(start, 32)),
+            Some(PlaybackState::Seeking)
+        );
+        assert_eq!(tracker.tick(at(start, 432)), Some(PlaybackState::Stopped));
+
+        let mut tracker = song_tracker();
+        tracker.observe(51.0, start);
+        assert_eq!(
+            tracker.observe(51.0, at(start, 400)),
+            Some(PlaybackState::Paused)
+        );
+    }
+
+    #[test]
+    fn a_stall_near_the_last_line_end_is_the_song_end() {
+        let start = Instant::now();
+        let mut tracker = song_tracker();
+        tracker.observe(9_000.0, start);
+        assert_eq!(
+            tracker.observe(9_000.0, at(start, 400)),
+            Some(PlaybackState::SongEnded)
+        );
+
+        let mut tracker = song_tracker();
+        tracker.observe(8_990.0, start);
+        assert_eq!(
+            tracker.observe(8_990.0, at(start, 400)),
+            Some(PlaybackState::Paused)
+        );
+    }
+}
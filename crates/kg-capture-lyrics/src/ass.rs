@@ -0,0 +1,235 @@
+//! ASS karaoke subtitles: one Dialogue event per line and a `\kf` sweep per
+//! word, styled after the KG Lyrics window.
+
+use std::fmt::Write;
+
+use kg_capture_protocol::{LyricLine, LyricTimeline};
+
+use crate::lrc::word_segments;
+use crate::style::{Alignment, LyricsStyle, Rgb};
+
+/// Family written when the lyric window uses the system default font, which
+/// has no portable name.
+pub const DEFAULT_FONT_FAMILY: &str = "Microsoft YaHei";
+const STYLE_NAME: &str = "Karaoke";
+
+#[derive(Clone, Copy, Debug, Eq, PartialEq)]
+pub struct AssOptions {
+    /// `PlayResX`/`PlayResY`; font sizes are interpreted in this space.
+    pub width: u32,
+    pub height: u32,
+}
+
+impl Default for AssOptions {
+    fn default() -> Self {
+        Self {
+            width: 1280,
+            height: 720,
+        }
+    }
+}
+
+/// Formats an ASS script. Unsung text uses the style's text color
+/// (`SecondaryColour`) and sweeps to the highlight color (`PrimaryColour`).
+pub fn to_ass(timeline: &LyricTimeline, style: &LyricsStyle, options: &AssOptions) -> String {
+    let mut output = String::new();
+    let _ = writeln!(output, "[Script Info]");
+    let _ = writeln!(output, "; Exported by {}", crate::lrc::CREDIT);
+    let _ = writeln!(output, "ScriptType: v4.00+");
+    let _ = writeln!(output, "WrapStyle: 0");
+    let _ = writeln!(output, "ScaledBorderAndShadow: yes");
+    let _ = writeln!(output, "PlayResX: {}", options.width);
+    let _ = writeln!(output, "PlayResY: {}", options.height);
+    let _ = writeln!(output);
+
+    let _ = writeln!(output, "[V4+ Styles]");
+    let _ = writeln!(
+        output,
+        "Format: Name, Fontname, Fontsize, PrimaryColour, SecondaryColour, OutlineColour, \
+         BackColour, Bold, Italic, Underline, StrikeOut, ScaleX, ScaleY, Spacing, Angle, \
+         BorderStyle, Outline, Shadow, Alignment, MarginL, MarginR, MarginV, Encoding"
+    );
+    let _ = writeln!(
+        output,
+        "Style: {STYLE_NAME},{},{},{},{},{},{},0,0,0,0,100,100,0,0,1,2,0,{},60,60,48,1",
+        style
+            .font_family
+            .as_deref()
+            .unwrap_or(DEFAULT_FONT_FAMILY)
+            .replace(',', " "),
+        style.active_font_size.round().max(1.0),
+        colour(style.highlight, 0x00),
+        colour(style.text, 0x00),
+        colour(style.background, 0x00),
+        colour(style.background, 0x80),
+        match style.alignment {
+            Alignment::Left => 1,
+            Alignment::Center => 2,
+            Alignment::Right => 3,
+        }
+    );
+    let _ = writeln!(output);
+
+    let _ = writeln!(output, "[Events]");
+    let _ = writeln!(
+        output,
+        "Format: Layer, Start, End, Style, Name, MarginL, MarginR, MarginV, Effect, Text"
+    );
+    for line in &timeline.lines {
+        let (text, end) = karaoke_text(line);
+        let _ = writeln!(
+            output,
+            "Dialogue: 0,{},{},{STYLE_NAME},,0,0,0,,{text}",
+            format_time(centiseconds(line.start_ms)),
+            format_time(end)
+        );
+    }
+    output
+}
+
+/// Builds the `\kf` text of a line and returns it with the line's end in
+/// centiseconds. Durations are derived from rounded word boundaries so the
+/// sweep does not drift from the word stamps over a long line.
+fn karaoke_text(line: &LyricLine) -> (String, u64) {
+    let start = centiseconds(line.start_ms);
+    let line_end = centiseconds(line.start_ms + line.duration_ms.max(0.0)).max(start);
+    let segments = word_segments(line);
+    let mut text = String::new();
+    if segments.is_empty() {
+        let _ = write!(text, "{{\\kf{}}}{}", line_end - start, escape(&line.text));
+        return (text, line_end);
+    }
+
+    let mut cursor = start;
+    for segment in segments {
+        let word_start = centiseconds(segment.start_ms).max(cursor);
+        let word_end =
+            centiseconds(segment.start_ms + segment.duration_ms.max(0.0)).max(word_start);
+        text.push_str(segment.gap);
+        if word_start > cursor {
+            let _ = write!(text, "{{\\k{}}}", word_start - cursor);
+        }
+        let _ = write!(
+            text,
+            "{{\\kf{}}}{}",
+            word_end - word_start,
+            escape(segment.text)
+        );
+        cursor = word_end;
+    }
+    (text, line_end.max(cursor))
+}
+
+fn centiseconds(milliseconds: f32) -> u64 {
+    (milliseconds.max(0.0) / 10.0).round() as u64
+}
+
+/// `H:MM:SS.cc`, the only time format ASS accepts.
+fn format_time(centiseconds: u64) -> String {
+    format!(
+        "{}:{:02}:{:02}.{:02}",
+        centiseconds / 360_000,
+        centiseconds / 6_000 % 60,
+        centiseconds / 100 % 60,
+        centiseconds % 100
+    )
+}
+
+/// `&HAABBGGRR`, where alpha 0x00 is opaque.
+fn colour(color: Rgb, alpha: u8) -> String {
+    format!(
+        "&H{alpha:02X}{:02X}{:02X}{:02X}",
+        color.blue, color.green, color.red
+    )
+}
+
+/// Braces open override blocks and backslashes start escapes, so both are
+/// replaced with their full-width forms; line breaks become spaces.
+fn escape(text: &str) -> String {
+    text.chars()
+        .map(|character| match character {
+            '{' => '｛',
+            '}' => '｝',
+            '\\' => '＼',
+            '\r' | '\n' => ' ',
+            other => other,
+        })
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::fixtures::{self, word};
+
+    fn style() -> LyricsStyle {
+        LyricsStyle {
+            font_family: None,
+            active_font_size: 38.0,
+            candidate_font_size: 24.0,
+            background: Rgb::new(0x29, 0x2b, 0x2f),
+            text: Rgb::new(0xf5, 0xf5, 0xf5),
+            highlight: Rgb::new(0xff, 0xd5, 0x4f),
+            alignment: Alignment::Center,
+        }
+    }
+
+    #[test]
+    fn formats_ass_times_and_colours() {
+        assert_eq!(format_time(0), "0:00:00.00");
+        assert_eq!(format_time(367_123), "1:01:11.23");
+        assert_eq!(colour(Rgb::new(0xff, 0xd5, 0x4f), 0x80), "&H804FD5FF");
+    }
+
+    #[test]
+    fn exports_karaoke_dialogue_with_window_style() {
+        let timeline = fixtures::timeline(vec![
+            LyricLine {
+                index: 0,
+                text: "把爱留在身边".into(),
+                start_ms: 1_000.0,
+                duration_ms: 1_500.0,
+                words: vec![
+                    word("把爱", 1_000.0, 500.0),
+                    word("留在", 1_600.0, 400.0),
+                    word("身边", 2_000.0, 700.0),
+                ],
+            },
+            LyricLine {
+                index: 1,
+                text: "Hello {world}".into(),
+                start_ms: 3_000.0,
+                duration_ms: 1_100.0,
+                words: vec![
+                    word("Hello", 3_000.0, 500.0),
+                    word("{world}", 3_500.0, 600.0),
+                ],
+            },
+            LyricLine {
+                index: 2,
+                text: "纯文本".into(),
+                start_ms: 5_000.0,
+                duration_ms: 1_500.0,
+                words: Vec::new(),
+            },
+        ]);
+        let exported = to_ass(&timeline, &style(), &AssOptions::default());
+
+        assert!(exported.contains(
+            "Style: Karaoke,Microsoft YaHei,38,&H004FD5FF,&H00F5F5F5,&H002F2B29,&H802F2B29,\
+             0,0,0,0,100,100,0,0,1,2,0,2,60,60,48,1\n"
+        ));
+        assert!(exported.contains(
+            "Dialogue: 0,0:00:01.00,0:00:02.70,Karaoke,,0,0,0,,\
+             {\\kf50}把爱{\\k10}{\\kf40}留在{\\kf70}身边\n"
+        ));
+        assert!(exported.contains(
+            "Dialogue: 0,0:00:03.00,0:00:04.10,Karaoke,,0,0,0,,\
+             {\\kf50}Hello {\\kf60}｛world｝\n"
+        ));
+        assert!(
+            exported
+                .contains("Dialogue: 0,0:00:05.00,0:00:06.50,Karaoke,,0,0,0,,{\\kf150}纯文本\n")
+        );
+    }
+}
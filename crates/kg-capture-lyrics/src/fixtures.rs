@@ -0,0 +1,29 @@
+//! Lyric timelines and words shared by the unit tests.
+
+use kg_capture_protocol::{LyricLine, LyricSource, LyricTimeline, LyricWord};
+
+/// An imported timeline of `lines`, numbered in order, as the parsers
+/// return it.
+pub fn timeline(lines: Vec<LyricLine>) -> LyricTimeline {
+    LyricTimeline {
+        id: 0,
+        source: LyricSource::Imported,
+        lines: lines
+            .into_iter()
+            .enumerate()
+            .map(|(index, line)| LyricLine {
+                index: index as u32,
+                ..line
+            })
+            .collect(),
+    }
+}
+
+/// A word of `text` timed from `start_ms` for `duration_ms`.
+pub fn word(text: &str, start_ms: f32, duration_ms: f32) -> LyricWord {
+    LyricWord {
+        text: text.into(),
+        start_ms,
+        duration_ms,
+    }
+}
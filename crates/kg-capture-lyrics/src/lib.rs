@@ -0,0 +1,11 @@
+//! Portable lyric file formats for semantic timelines. Nothing here depends on
+//! Windows, so exporters and parsers are tested on any host.
+
+pub mod ass;
+#[cfg(test)]
+mod fixtures;
+pub mod lrc;
+pub mod style;
+pub mod timing;
+pub mod ttml;
+pub mod webvtt;
@@ -0,0 +1,564 @@
+//! LRC lyric sheets with `[mm:ss.xx]` line tags, optionally extended with
+//! Enhanced LRC (A2) `<mm:ss.xx>` word stamps.
+
+use std::fmt::Write;
+
+use kg_capture_protocol::{LyricLine, LyricSource, LyricTimeline, LyricWord};
+
+use crate::timing::timeline_end_ms;
+
+pub const CREDIT: &str = "kg-capture";
+
+#[derive(Clone, Debug, Default, Eq, PartialEq)]
+pub struct LrcOptions {
+    /// Written as `[offset:]`. Players subtract it from every time tag, so a
+    /// positive value shows the lyrics earlier.
+    pub offset_ms: Option<i32>,
+    /// Writes `[length:]` from the end of the last line.
+    pub include_length: bool,
+    /// Writes `[by:kg-capture]`.
+    pub include_credit: bool,
+}
+
+/// Formats a line-level LRC sheet. A blank tag is appended at the end of the
+/// final line so players clear it instead of holding it until the song ends.
+pub fn to_lrc(timeline: &LyricTimeline, options: &LrcOptions) -> String {
+    write_sheet(timeline, options, |line| single_line(&line.text))
+}
+
+/// Formats an Enhanced LRC sheet. Each word is preceded by its start stamp and
+/// the line ends with a stamp for the end of its last word. Lines without word
+/// timing fall back to their plain text.
+pub fn to_enhanced_lrc(timeline: &LyricTimeline, options: &LrcOptions) -> String {
+    write_sheet(timeline, options, |line| {
+        let segments = word_segments(line);
+        let Some(last) = line.words.iter().rfind(|word| !word.text.trim().is_empty()) else {
+            return single_line(&line.text);
+        };
+        let mut body = String::new();
+        for segment in segments {
+            let _ = write!(
+                body,
+                "{}<{}>{}",
+                segment.gap,
+                format_timestamp(segment.start_ms),
+                segment.text
+            );
+        }
+        let _ = write!(
+            body,
+            "<{}>",
+            format_timestamp(last.start_ms + last.duration_ms.max(0.0))
+        );
+        body
+    })
+}
+
+fn write_sheet(
+    timeline: &LyricTimeline,
+    options: &LrcOptions,
+    line_body: impl Fn(&LyricLine) -> String,
+) -> String {
+    let mut output = String::new();
+    write_headers(&mut output, timeline, options);
+    for line in &timeline.lines {
+        let _ = writeln!(
+            output,
+            "[{}]{}",
+            format_timestamp(line.start_ms),
+            line_body(line)
+        );
+    }
+    if let Some(end) = timeline_end_ms(timeline) {
+        let _ = writeln!(output, "[{}]", format_timestamp(end));
+    }
+    output
+}
+
+fn write_headers(output: &mut String, timeline: &LyricTimeline, options: &LrcOptions) {
+    if options.include_credit {
+        let _ = writeln!(output, "[by:{CREDIT}]");
+    }
+    if options.include_length
+        && let Some(end) = timeline_end_ms(timeline)
+    {
+        let seconds = (end / 1_000.0).round() as u64;
+        let _ = writeln!(output, "[length:{:02}:{:02}]", seconds / 60, seconds % 60);
+    }
+    if let Some(offset) = options.offset_ms {
+        let _ = writeln!(output, "[offset:{offset:+}]");
+    }
+}
+
+/// `mm:ss.xx` with centisecond precision. Negative times clamp to zero and
+/// minutes grow past two digits for very long recordings.
+pub fn format_timestamp(milliseconds: f32) -> String {
+    let centiseconds = (milliseconds.max(0.0) / 10.0).round() as u64;
+    format!(
+        "{:02}:{:02}.{:02}",
+        centiseconds / 6_000,
+        centiseconds / 100 % 60,
+        centiseconds % 100
+    )
+}
+
+/// Parses an LRC or Enhanced LRC sheet into an imported timeline with id 0.
+///
+/// A line with several time tags is repeated at each of them, with its word
+/// stamps shifted along. Metadata tags are skipped except `[offset:]`, which is
+/// subtracted from every time. A line ends at its closing word stamp or where
+/// the next line starts; blank lines such as the final end tag only end the
+/// line before them.
+pub fn parse_lrc(contents: &str) -> Result<LyricTimeline, String> {
+    let mut offset_ms = 0_i64;
+    // (line time, shift applied to word stamps, body)
+    let mut entries: Vec<(i64, i64, &str)> = Vec::new();
+    for (number, row) in contents.lines().enumerate() {
+        let mut rest = row.trim_start_matches('\u{feff}').trim();
+        let mut times = Vec::new();
+        while let Some(tag) = rest.strip_prefix('[') {
+            let Some((content, after)) = tag.split_once(']') else {
+                break;
+            };
+            if let Some(time) = parse_tag_time(content) {
+                times.push(time);
+            } else if let Some((key, value)) = metadata_tag(content) {
+                if key.eq_ignore_ascii_case("offset") {
+                    offset_ms =
+                        value.trim().trim_start_matches('+').parse().map_err(|_| {
+                            format!("line {}: invalid offset {value:?}", number + 1)
+                        })?;
+                }
+            } else {
+                break;
+            }
+            rest = after;
+        }
+        if let Some(first) = times.first().copied() {
+            entries.extend(times.iter().map(|time| (*time, time - first, rest.trim())));
+        }
+    }
+    entries.sort_by_key(|(time, _, _)| *time);
+
+    let mut lines = Vec::new();
+    for (position, (time, shift, body)) in entries.iter().enumerate() {
+        if body.is_empty() {
+            continue;
+        }
+        let next = entries.get(position + 1).map(|(next, _, _)| *next);
+        let mut line = parse_line_body(
+            time.saturating_sub(offset_ms),
+            shift.saturating_sub(offset_ms),
+            body,
+            next.map(|next| next.saturating_sub(offset_ms)),
+        );
+        line.index = lines.len() as u32;
+        lines.push(line);
+    }
+    if lines.is_empty() {
+        return Err("no timed lyric lines found".into());
+    }
+    Ok(LyricTimeline {
+        id: 0,
+        source: LyricSource::Imported,
+        lines,
+    })
+}
+
+/// Splits a line body at its `<mm:ss.xx>` word stamps. Text before the first
+/// stamp starts with the line, and a stamp with no text after it closes the
+/// last word. Times are clamped to zero once the offset has been applied.
+fn parse_line_body(start: i64, shift: i64, body: &str, next: Option<i64>) -> LyricLine {
+    let start = start.max(0);
+    let mut segments: Vec<(i64, &str)> = Vec::new();
+    let mut segment_start = start;
+    let mut segment_text = 0;
+    let mut search = 0;
+    while let Some(open) = body[search..].find('<').map(|open| search + open) {
+        let stamp = body[open + 1..]
+            .split_once('>')
+            .and_then(|(tag, _)| Some((parse_tag_time(tag)?, open + tag.len() + 2)));
+        let Some((stamp, after)) = stamp else {
+            search = open + 1;
+            continue;
+        };
+        segments.push((segment_start, &body[segment_text..open]));
+        segment_start = stamp.saturating_add(shift).max(0);
+        segment_text = after;
+        search = after;
+    }
+    segments.push((segment_start, &body[segment_text..]));
+
+    let timed = segments.len() > 1;
+    let closing = segments
+        .last()
+        .filter(|(_, text)| timed && text.trim().is_empty())
+        .map(|(stamp, _)| *stamp);
+    let end = closing.or(next).unwrap_or(start).max(start);
+    let mut words = Vec::new();
+    for (position, (word_start, text)) in segments.iter().enumerate() {
+        if !timed || text.trim().is_empty() {
+            continue;
+        }
+        let word_end = segments.get(position + 1).map_or(end, |(next, _)| *next);
+        words.push(LyricWord {
+            text: text.trim().into(),
+            start_ms: *word_start as f32,
+            duration_ms: (word_end - word_start).max(0) as f32,
+        });
+    }
+    LyricLine {
+        index: 0,
+        text: segments
+            .iter()
+            .map(|(_, text)| *text)
+            .collect::<String>()
+            .trim()
+            .into(),
+        start_ms: start as f32,
+        duration_ms: (end - start) as f32,
+        words,
+    }
+}
+
+/// `mm:ss`, `mm:ss.x` to `mm:ss.xxx`, or `mm:ss:xx`, in milliseconds. A time
+/// too large for an `i64` is not a time tag.
+fn parse_tag_time(tag: &str) -> Option<i64> {
+    let is_number = |text: &str| !text.is_empty() && text.bytes().all(|byte| byte.is_ascii_digit());
+    let (minutes, seconds) = tag.trim().split_once(':')?;
+    let (seconds, fraction) = seconds.split_once(['.', ':']).unwrap_or((seconds, ""));
+    if !is_number(minutes)
+        || !is_number(seconds)
+        || fraction.len() > 3
+        || !(fraction.is_empty() || is_number(fraction))
+    {
+        return None;
+    }
+    let fraction_ms = match fraction.len() {
+        0 => 0,
+        length => fraction.parse::<i64>().ok()? * 10_i64.pow(3 - length as u32),
+    };
+    minutes
+        .parse::<i64>()
+        .ok()?
+        .checked_mul(60_000)?
+        .checked_add(seconds.parse::<i64>().ok()?.checked_mul(1_000)?)?
+        .checked_add(fraction_ms)
+}
+
+/// `[key:value]` tags such as `[ti:]`, `[ar:]` or `[offset:]`.
+fn metadata_tag(tag: &str) -> Option<(&str, &str)> {
+    let (key, value) = tag.split_once(':')?;
+    (!key.is_empty()
+        && key
+            .bytes()
+            .all(|byte| byte.is_ascii_alphabetic() || byte == b'#'))
+    .then_some((key, value))
+}
+
+/// A timed word together with the whitespace that separates it from the
+/// previous word in the displayed line.
+#[derive(Clone, Debug, PartialEq)]
+pub struct WordSegment<'a> {
+    pub gap: &'a str,
+    pub text: &'a str,
+    pub start_ms: f32,
+    pub duration_ms: f32,
+}
+
+/// Splits a line into its timed words and the spacing between them.
+///
+/// WeSing's words carry no separators of their own, so the spacing is taken
+/// from `LyricLine::text`: CJK lines stay contiguous and Latin lines keep their
+/// spaces even when a word is split into syllables. If the words do not match
+/// the line text, a space is inserted between adjacent non-CJK letters.
+pub fn word_segments(line: &LyricLine) -> Vec<WordSegment<'_>> {
+    let words = line
+        .words
+        .iter()
+        .map(|word| (word, word.text.trim()))
+        .filter(|(_, text)| !text.is_empty());
+    let mut segments = Vec::with_capacity(line.words.len());
+    let mut rest = line.text.as_str();
+    for (word, text) in words.clone() {
+        let trimmed = rest.trim_start();
+        let Some(after) = trimmed.strip_prefix(text) else {
+            return guessed_segments(words);
+        };
+        segments.push(WordSegment {
+            gap: if trimmed.len() < rest.len() { " " } else { "" },
+            text,
+            start_ms: word.start_ms,
+            duration_ms: word.duration_ms,
+        });
+        rest = after;
+    }
+    segments
+}
+
+fn guessed_segments<'a>(
+    words: impl Iterator<Item = (&'a LyricWord, &'a str)>,
+) -> Vec<WordSegment<'a>> {
+    let mut previous: Option<char> = None;
+    words
+        .map(|(word, text)| {
+            let spaced = previous.is_some_and(is_spaced_script)
+                && text.chars().next().is_some_and(is_spaced_script);
+            previous = text.chars().last();
+            WordSegment {
+                gap: if spaced { " " } else { "" },
+                text,
+                start_ms: word.start_ms,
+                duration_ms: word.duration_ms,
+            }
+        })
+        .collect()
+}
+
+/// Letters and digits of scripts that separate words with spaces.
+fn is_spaced_script(character: char) -> bool {
+    character.is_alphanumeric() && !is_cjk(character)
+}
+
+/// Han, kana, Hangul and their punctuation, which are written without spaces
+/// between words.
+pub fn is_cjk(character: char) -> bool {
+    matches!(
+        u32::from(character),
+        0x1100..=0x11ff
+            | 0x2e80..=0x2fdf
+            | 0x3000..=0x30ff
+            | 0x3100..=0x31ff
+            | 0x3400..=0x4dbf
+            | 0x4e00..=0x9fff
+            | 0xac00..=0xd7af
+            | 0xf900..=0xfaff
+            | 0xff00..=0xffef
+            | 0x20000..=0x3134f
+    )
+}
+
+pub(crate) fn single_line(text: &str) -> String {
+    text.split(['\r', '\n'])
+        .filter(|part| !part.is_empty())
+        .collect::<Vec<_>>()
+        .join(" ")
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::fixtures;
+    use kg_capture_protocol::LyricLine;
+
+    #[test]
+    fn formats_centisecond_timestamps() {
+        assert_eq!(format_timestamp(0.0), "00:00.00");
+        assert_eq!(format_timestamp(1_234.0), "00:01.23");
+        assert_eq!(format_timestamp(59_996.0), "01:00.00");
+        assert_eq!(format_timestamp(-50.0), "00:00.00");
+        assert_eq!(format_timestamp(6_000_000.0), "100:00.00");
+    }
+
+    #[test]
+    fn exports_line_level_lrc_with_headers() {
+        let options = LrcOptions {
+            offset_ms: Some(-150),
+            include_length: true,
+            include_credit: true,
+        };
+        assert_eq!(
+            to_lrc(
+                &fixtures::timeline(vec![
+                    LyricLine {
+                        index: 0,
+                        text: "把爱留在身边".into(),
+                        start_ms: 1_234.0,
+                        duration_ms: 2_000.0,
+                        words: Vec::new(),
+                    },
+                    LyricLine {
+                        index: 0,
+                        text: "窗外\n有个蓝蓝的天".into(),
+                        start_ms: 63_005.0,
+                        duration_ms: 2_500.0,
+                        words: Vec::new(),
+                    },
+                ]),
+                &options
+            ),
+            "[by:kg-capture]\n\
+             [length:01:06]\n\
+             [offset:-150]\n\
+             [00:01.23]把爱留在身边\n\
+             [01:03.01]窗外 有个蓝蓝的天\n\
+             [01:05.51]\n"
+        );
+    }
+
+    fn line(text: &str, words: &[(&str, f32, f32)]) -> LyricLine {
+        LyricLine {
+            index: 0,
+            text: text.into(),
+            start_ms: words.first().map_or(0.0, |word| word.1),
+            duration_ms: words
+                .first()
+                .zip(words.last())
+                .map_or(0.0, |(first, last)| last.1 + last.2 - first.1),
+            words: words
+                .iter()
+                .map(|(text, start_ms, duration_ms)| LyricWord {
+                    text: (*text).into(),
+                    start_ms: *start_ms,
+                    duration_ms: *duration_ms,
+                })
+                .collect(),
+        }
+    }
+
+    #[test]
+    fn exports_enhanced_lrc_word_stamps() {
+        let timeline = fixtures::timeline(vec![
+            line(
+                "把爱留在身边",
+                &[
+                    ("把爱", 1_000.0, 500.0),
+                    ("留在", 1_500.0, 500.0),
+                    ("身边", 2_000.0, 700.0),
+                ],
+            ),
+            line(
+                "Hello beautiful world",
+                &[
+                    ("Hello", 3_000.0, 400.0),
+                    ("beau", 3_400.0, 200.0),
+                    ("ti", 3_600.0, 200.0),
+                    ("ful", 3_800.0, 200.0),
+                    ("world", 4_000.0, 500.0),
+                ],
+            ),
+            line("纯文本", &[]),
+        ]);
+        assert_eq!(
+            to_enhanced_lrc(&timeline, &LrcOptions::default()),
+            "[00:01.00]<00:01.00>把爱<00:01.50>留在<00:02.00>身边<00:02.70>\n\
+             [00:03.00]<00:03.00>Hello <00:03.40>beau<00:03.60>ti<00:03.80>ful <00:04.00>world<00:04.50>\n\
+             [00:00.00]纯文本\n\
+             [00:04.50]\n"
+        );
+    }
+
+    #[test]
+    fn mismatched_words_guess_spacing_by_script() {
+        let line = line(
+            "",
+            &[
+                ("I", 0.0, 1.0),
+                ("love", 1.0, 1.0),
+                ("你", 2.0, 1.0),
+                ("们", 3.0, 1.0),
+            ],
+        );
+        let segments = word_segments(&line);
+        let gaps: Vec<_> = segments.iter().map(|segment| segment.gap).collect();
+        assert_eq!(gaps, ["", " ", "", ""]);
+    }
+
+    #[test]
+    fn enhanced_lrc_round_trips_through_the_parser() {
+        let timeline = fixtures::timeline(vec![
+            line(
+                "把爱留在身边",
+                &[
+                    ("把爱", 1_000.0, 500.0),
+                    ("留在", 1_500.0, 500.0),
+                    ("身边", 2_000.0, 700.0),
+                ],
+            ),
+            line(
+                "Hello beautiful world",
+                &[
+                    ("Hello", 3_000.0, 400.0),
+                    ("beau", 3_400.0, 200.0),
+                    ("ti", 3_600.0, 200.0),
+                    ("ful", 3_800.0, 200.0),
+                    ("world", 4_000.0, 500.0),
+                ],
+            ),
+        ]);
+        let exported = to_enhanced_lrc(&timeline, &LrcOptions::default());
+        assert_eq!(parse_lrc(&exported), Ok(timeline));
+    }
+
+    #[test]
+    fn parser_repeats_lines_and_applies_offset() {
+        let sheet = "\u{feff}[ti:Test]\n\
+                     [ar:Someone]\n\
+                     [offset:+500]\n\
+                     [00:10.00][00:30.00]副歌 <00:10.5>la<00:11.000>\n\
+                     [00:20.5]主歌\n\
+                     plain text without a tag\n\
+                     [00:40:00]\n";
+        let timeline = parse_lrc(sheet).expect("parse lrc");
+        let summary: Vec<_> = timeline
+            .lines
+            .iter()
+            .map(|line| {
+                (
+                    line.index,
+                    line.text.as_str(),
+                    line.start_ms,
+                    line.duration_ms,
+                )
+            })
+            .collect();
+        assert_eq!(
+            summary,
+            [
+                (0, "副歌 la", 9_500.0, 1_000.0),
+                (1, "主歌", 20_000.0, 9_500.0),
+                (2, "副歌 la", 29_500.0, 1_000.0),
+            ]
+        );
+        assert_eq!(
+            timeline.lines[2].words,
+            [
+                LyricWord {
+                    text: "副歌".into(),
+                    start_ms: 29_500.0,
+                    duration_ms: 500.0,
+                },
+                LyricWord {
+                    text: "la".into(),
+                    start_ms: 30_000.0,
+                    duration_ms: 500.0,
+                },
+            ]
+        );
+        assert!(parse_lrc("[ti:Nothing]\n").is_err());
+    }
+
+    #[test]
+    fn oversized_times_are_not_time_tags() {
+        assert_eq!(parse_tag_time("999999999999999999:00"), None);
+        assert_eq!(parse_tag_time("153722867280912:59.999"), None);
+        assert!(parse_lrc("[999999999999999999:00]副歌\n").is_err());
+
+        let timeline =
+            parse_lrc("[offset:-9223372036854775807]\n[00:01.00]副歌 <153722867:00.00>\n").unwrap();
+        assert_eq!(timeline.lines.len(), 1);
+    }
+
+    #[test]
+    fn headers_are_optional() {
+        let timeline = fixtures::timeline(vec![LyricLine {
+            index: 0,
+            text: "把爱留在身边".into(),
+            start_ms: 1_234.0,
+            duration_ms: 2_000.0,
+            words: Vec::new(),
+        }]);
+        let exported = to_lrc(&timeline, &LrcOptions::default());
+        assert!(exported.starts_with("[00:01.23]"));
+    }
+}
@@ -0,0 +1,34 @@
+//! Renderer-neutral description of the KG Lyrics window appearance, shared by
+//! exporters that reproduce the on-stream look.
+
+#[derive(Clone, Copy, Debug, Eq, PartialEq)]
+pub struct Rgb {
+    pub red: u8,
+    pub green: u8,
+    pub blue: u8,
+}
+
+impl Rgb {
+    pub const fn new(red: u8, green: u8, blue: u8) -> Self {
+        Self { red, green, blue }
+    }
+}
+
+#[derive(Clone, Copy, Debug, Eq, PartialEq)]
+pub enum Alignment {
+    Left,
+    Center,
+    Right,
+}
+
+#[derive(Clone, Debug, PartialEq)]
+pub struct LyricsStyle {
+    /// `None` selects the renderer's default family.
+    pub font_family: Option<String>,
+    pub active_font_size: f32,
+    pub candidate_font_size: f32,
+    pub background: Rgb,
+    pub text: Rgb,
+    pub highlight: Rgb,
+    pub alignment: Alignment,
+}
@@ -0,0 +1,79 @@
+//! Playback lookups for sources that run their own clock instead of reading
+//! WeSing's render state.
+
+use kg_capture_protocol::LyricTimeline;
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct LinePosition {
+    /// `LyricLine::index` of the line being sung.
+    pub line: Option<u32>,
+    pub progress: f32,
+}
+
+/// Finds the line sung at `position_ms`. Like WeSing, a finished line stays
+/// current until the next one starts.
+pub fn locate(timeline: &LyricTimeline, position_ms: f32) -> LinePosition {
+    let current = timeline
+        .lines
+        .iter()
+        .rev()
+        .find(|line| line.start_ms <= position_ms);
+    LinePosition {
+        line: current.map(|line| line.index),
+        progress: current
+            .filter(|line| line.duration_ms > 0.0)
+            .map_or(0.0, |line| {
+                ((position_ms - line.start_ms) / line.duration_ms).clamp(0.0, 1.0)
+            }),
+    }
+}
+
+/// End of the last line, or `None` for an empty timeline.
+pub fn timeline_end_ms(timeline: &LyricTimeline) -> Option<f32> {
+    timeline
+        .lines
+        .iter()
+        .map(|line| line.start_ms + line.duration_ms.max(0.0))
+        .reduce(f32::max)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::fixtures;
+    use kg_capture_protocol::LyricLine;
+
+    #[test]
+    fn locates_the_last_started_line() {
+        let timeline = fixtures::timeline(
+            [(1_000.0, 1_000.0), (3_000.0, 2_000.0)]
+                .into_iter()
+                .map(|(start_ms, duration_ms)| LyricLine {
+                    index: 0,
+                    text: String::new(),
+                    start_ms,
+                    duration_ms,
+                    words: Vec::new(),
+                })
+                .collect(),
+        );
+
+        assert_eq!(locate(&timeline, 500.0).line, None);
+        assert_eq!(
+            locate(&timeline, 1_250.0),
+            LinePosition {
+                line: Some(0),
+                progress: 0.25
+            }
+        );
+        assert_eq!(
+            locate(&timeline, 2_500.0),
+            LinePosition {
+                line: Some(0),
+                progress: 1.0
+            }
+        );
+        assert_eq!(locate(&timeline, 4_000.0).line, Some(1));
+        assert_eq!(timeline_end_ms(&timeline), Some(5_000.0));
+    }
+}
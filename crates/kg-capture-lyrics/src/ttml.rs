@@ -0,0 +1,229 @@
+//! Word-synced TTML in the form used by Apple Music: one `<p>` per line and
+//! one `<span begin end>` per word.
+
+use std::fmt::Write;
+
+use kg_capture_protocol::{LyricLine, LyricTimeline, LyricWord};
+
+use crate::lrc::{single_line, word_segments};
+use crate::webvtt::format_timestamp;
+
+const TTML_NAMESPACE: &str = "http://www.w3.org/ns/ttml";
+const ITUNES_NAMESPACE: &str = "http://music.apple.com/lyric-ttml-internal";
+
+/// Formats a TTML document. Lines without word timing are written as plain
+/// paragraphs, and the whitespace between spans follows the line text.
+pub fn to_ttml(timeline: &LyricTimeline) -> String {
+    let end = timeline.lines.iter().map(line_end).fold(0.0, f32::max);
+    let mut output = String::new();
+    let _ = writeln!(output, r#"<?xml version="1.0" encoding="UTF-8"?>"#);
+    let _ = writeln!(
+        output,
+        r#"<tt xmlns="{TTML_NAMESPACE}" xmlns:itunes="{ITUNES_NAMESPACE}" itunes:timing="Word">"#
+    );
+    let _ = writeln!(output, r#"  <body dur="{}">"#, format_timestamp(end));
+    let _ = writeln!(
+        output,
+        r#"    <div begin="{}" end="{}">"#,
+        format_timestamp(timeline.lines.first().map_or(0.0, |line| line.start_ms)),
+        format_timestamp(end)
+    );
+    for line in &timeline.lines {
+        let _ = write!(
+            output,
+            r#"      <p begin="{}" end="{}">"#,
+            format_timestamp(line.start_ms),
+            format_timestamp(line_end(line))
+        );
+        let segments = word_segments(line);
+        if segments.is_empty() {
+            output.push_str(&escape(&single_line(&line.text)));
+        }
+        for segment in segments {
+            let _ = write!(
+                output,
+                r#"{}<span begin="{}" end="{}">{}</span>"#,
+                segment.gap,
+                format_timestamp(segment.start_ms),
+                format_timestamp(segment.start_ms + segment.duration_ms.max(0.0)),
+                escape(segment.text)
+            );
+        }
+        let _ = writeln!(output, "</p>");
+    }
+    let _ = writeln!(output, "    </div>");
+    let _ = writeln!(output, "  </body>");
+    let _ = writeln!(output, "</tt>");
+    output
+}
+
+/// Reads every `<p>` of a TTML document back into lyric lines, with one word
+/// per timed `<span>`. Times may be clock times or offsets such as `1.5s`.
+pub fn parse_ttml(contents: &str) -> Result<Vec<LyricLine>, String> {
+    let document = roxmltree::Document::parse(contents)
+        .map_err(|error| format!("TTML is not valid XML: {error}"))?;
+    let root = document.root_element();
+    if !root.has_tag_name((TTML_NAMESPACE, "tt")) {
+        return Err("not a TTML document".into());
+    }
+
+    let mut lines = Vec::new();
+    for paragraph in root
+        .descendants()
+        .filter(|node| node.has_tag_name((TTML_NAMESPACE, "p")))
+    {
+        let start = time_attribute(paragraph, "begin")?;
+        let end = time_attribute(paragraph, "end")?.max(start);
+        let mut text = String::new();
+        let mut words = Vec::new();
+        for node in paragraph.descendants().skip(1) {
+            if node.has_tag_name((TTML_NAMESPACE, "span")) && node.attribute("begin").is_some() {
+                let word_start = time_attribute(node, "begin")?;
+                let word_end = time_attribute(node, "end")?.max(word_start);
+                let word_text: String = node
+                    .descendants()
+                    .filter(|child| child.is_text())
+                    .filter_map(|child| child.text())
+                    .collect();
+                if !word_text.trim().is_empty() {
+                    words.push(LyricWord {
+                        text: word_text.trim().into(),
+                        start_ms: word_start,
+                        duration_ms: word_end - word_start,
+                    });
+                }
+            } else if node.has_tag_name((TTML_NAMESPACE, "br")) {
+                text.push(' ');
+            } else if let Some(fragment) = node.text().filter(|_| node.is_text()) {
+                text.push_str(fragment);
+            }
+        }
+        lines.push(LyricLine {
+            index: lines.len() as u32,
+            text: text.trim().into(),
+            start_ms: start,
+            duration_ms: end - start,
+            words,
+        });
+    }
+    Ok(lines)
+}
+
+fn line_end(line: &LyricLine) -> f32 {
+    line.start_ms + line.duration_ms.max(0.0)
+}
+
+fn time_attribute(node: roxmltree::Node<'_, '_>, name: &str) -> Result<f32, String> {
+    let value = node
+        .attribute(name)
+        .ok_or_else(|| format!("<{}> is missing {name}", node.tag_name().name()))?;
+    parse_time(value).ok_or_else(|| format!("invalid TTML time {value:?}"))
+}
+
+/// Parses a TTML clock time (`hh:mm:ss.fff`, `mm:ss.fff` or `ss.fff`) or an
+/// offset time in hours, minutes, seconds or milliseconds into milliseconds.
+pub fn parse_time(text: &str) -> Option<f32> {
+    let text = text.trim();
+    for (suffix, scale) in [
+        ("ms", 1.0),
+        ("h", 3_600_000.0),
+        ("m", 60_000.0),
+        ("s", 1_000.0),
+    ] {
+        if let Some(value) = text.strip_suffix(suffix) {
+            return value
+                .parse::<f64>()
+                .ok()
+                .filter(|value| value.is_finite() && *value >= 0.0)
+                .map(|value| (value * scale) as f32);
+        }
+    }
+
+    let mut total = 0.0;
+    for (position, part) in text.split(':').enumerate() {
+        if position > 2 || part.is_empty() {
+            return None;
+        }
+        let value = part.parse::<f64>().ok().filter(|value| *value >= 0.0)?;
+        total = total * 60.0 + value;
+    }
+    Some((total * 1_000.0).round() as f32)
+}
+
+fn escape(text: &str) -> String {
+    text.replace('&', "&amp;")
+        .replace('<', "&lt;")
+        .replace('>', "&gt;")
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::fixtures::{self, word};
+
+    #[test]
+    fn parses_clock_and_offset_times() {
+        assert_eq!(parse_time("00:01:02.500"), Some(62_500.0));
+        assert_eq!(parse_time("01:02.5"), Some(62_500.0));
+        assert_eq!(parse_time("2.25"), Some(2_250.0));
+        assert_eq!(parse_time("1.5s"), Some(1_500.0));
+        assert_eq!(parse_time("250ms"), Some(250.0));
+        assert_eq!(parse_time("1:2:3:4"), None);
+        assert_eq!(parse_time("later"), None);
+    }
+
+    #[test]
+    fn ttml_round_trips_through_the_parser() {
+        let lines = vec![
+            LyricLine {
+                index: 0,
+                text: "把爱留在身边".into(),
+                start_ms: 1_000.0,
+                duration_ms: 1_700.0,
+                words: vec![
+                    word("把爱", 1_000.0, 500.0),
+                    word("留在", 1_600.0, 400.0),
+                    word("身边", 2_000.0, 700.0),
+                ],
+            },
+            LyricLine {
+                index: 1,
+                text: "Rock & <roll> tonight".into(),
+                start_ms: 3_000.0,
+                duration_ms: 1_500.0,
+                words: vec![
+                    word("Rock", 3_100.0, 300.0),
+                    word("&", 3_400.0, 200.0),
+                    word("<roll>", 3_600.0, 400.0),
+                    word("to", 4_000.0, 200.0),
+                    word("night", 4_200.0, 250.0),
+                ],
+            },
+            LyricLine {
+                index: 2,
+                text: "纯文本".into(),
+                start_ms: 5_000.0,
+                duration_ms: 1_500.0,
+                words: Vec::new(),
+            },
+        ];
+        let timeline = fixtures::timeline(lines.clone());
+        let exported = to_ttml(&timeline);
+        assert!(exported.contains(r#"<body dur="00:00:06.500">"#));
+        assert!(exported.contains(
+            r#"<p begin="00:00:03.000" end="00:00:04.500"><span begin="00:00:03.100" end="00:00:03.400">Rock</span> <span begin="00:00:03.400" end="00:00:03.600">&amp;</span>"#
+        ));
+        assert!(exported.contains(r#"<p begin="00:00:05.000" end="00:00:06.500">纯文本</p>"#));
+
+        assert_eq!(parse_ttml(&exported), Ok(lines));
+    }
+
+    #[test]
+    fn parser_rejects_other_documents() {
+        assert!(parse_ttml("<html/>").is_err());
+        assert!(parse_ttml("<tt").is_err());
+        let missing_end =
+            format!(r#"<tt xmlns="{TTML_NAMESPACE}"><body><p begin="1s">la</p></body></tt>"#);
+        assert!(parse_ttml(&missing_end).is_err());
+    }
+}
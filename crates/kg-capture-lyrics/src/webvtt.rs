@@ -0,0 +1,270 @@
+//! WebVTT captions with one cue per line and inline `<hh:mm:ss.ttt>` cue
+//! timestamps marking where each word starts.
+
+use std::fmt::Write;
+
+use kg_capture_protocol::{LyricLine, LyricTimeline, LyricWord};
+
+use crate::lrc::{single_line, word_segments};
+
+/// Formats a WebVTT file. Cue timestamps have to lie after the cue start, so
+/// a word sung exactly at the start of its line is written without one.
+pub fn to_webvtt(timeline: &LyricTimeline) -> String {
+    let mut output = String::from("WEBVTT\n");
+    for (number, line) in timeline.lines.iter().enumerate() {
+        let start = line.start_ms.max(0.0);
+        let end = (line.start_ms + line.duration_ms.max(0.0)).max(start);
+        let _ = writeln!(output);
+        let _ = writeln!(output, "{}", number + 1);
+        let _ = writeln!(
+            output,
+            "{} --> {}",
+            format_timestamp(start),
+            format_timestamp(end)
+        );
+
+        let segments = word_segments(line);
+        if segments.is_empty() {
+            let _ = writeln!(output, "{}", escape(&single_line(&line.text)));
+            continue;
+        }
+        for segment in segments {
+            output.push_str(segment.gap);
+            if segment.start_ms.round() > start.round() {
+                let _ = write!(output, "<{}>", format_timestamp(segment.start_ms));
+            }
+            output.push_str(&escape(segment.text));
+        }
+        output.push('\n');
+    }
+    output
+}
+
+/// Reads the cues of a WebVTT file back into lyric lines. Words end where the
+/// next one starts and the last word ends with its cue; cues without inline
+/// timestamps become lines without word timing. Formatting tags such as
+/// `<c>` or `<v>` are dropped.
+pub fn parse_webvtt(contents: &str) -> Result<Vec<LyricLine>, String> {
+    let contents = contents.replace("\r\n", "\n");
+    let contents = contents.strip_prefix('\u{feff}').unwrap_or(&contents);
+    let mut blocks = contents.split("\n\n");
+    let signature = blocks.next().unwrap_or_default();
+    if !signature
+        .strip_prefix("WEBVTT")
+        .is_some_and(|rest| rest.is_empty() || rest.starts_with([' ', '\t', '\r', '\n']))
+    {
+        return Err("missing WEBVTT signature".into());
+    }
+
+    let mut lines = Vec::new();
+    for block in blocks {
+        let mut rows = block.lines().skip_while(|row| row.trim().is_empty());
+        let Some(mut timing) = rows.next() else {
+            continue;
+        };
+        if !timing.contains("-->") {
+            // NOTE, STYLE and REGION blocks have no timing line; a cue may
+            // have an identifier before it.
+            match rows.next() {
+                Some(row) if row.contains("-->") && !timing.starts_with("NOTE") => timing = row,
+                _ => continue,
+            }
+        }
+        let (start, end) = parse_timing(timing)?;
+        let payload = rows.collect::<Vec<_>>().join(" ");
+        lines.push(parse_cue(lines.len() as u32, &payload, start, end)?);
+    }
+    Ok(lines)
+}
+
+fn parse_timing(row: &str) -> Result<(f32, f32), String> {
+    let (start, rest) = row
+        .split_once("-->")
+        .ok_or_else(|| format!("cue timing is invalid: {row}"))?;
+    let end = rest.split_whitespace().next().unwrap_or_default();
+    let start =
+        parse_timestamp(start.trim()).ok_or_else(|| format!("cue start time is invalid: {row}"))?;
+    let end = parse_timestamp(end).ok_or_else(|| format!("cue end time is invalid: {row}"))?;
+    Ok((start, end.max(start)))
+}
+
+fn parse_cue(index: u32, payload: &str, start: f32, end: f32) -> Result<LyricLine, String> {
+    let mut text = String::new();
+    let mut words: Vec<LyricWord> = Vec::new();
+    let mut word_start = start;
+    let mut word_text = String::new();
+    let mut timed = false;
+    let mut rest = payload;
+    while let Some(open) = rest.find('<') {
+        word_text.push_str(&unescape(&rest[..open]));
+        let close = rest[open..]
+            .find('>')
+            .map(|close| open + close)
+            .ok_or_else(|| format!("unterminated tag in cue: {payload}"))?;
+        if let Some(timestamp) = parse_timestamp(&rest[open + 1..close]) {
+            push_word(&mut words, &mut text, &word_text, word_start);
+            word_text.clear();
+            word_start = timestamp;
+            timed = true;
+        }
+        rest = &rest[close + 1..];
+    }
+    word_text.push_str(&unescape(rest));
+    push_word(&mut words, &mut text, &word_text, word_start);
+
+    for index in 0..words.len() {
+        let next_start = words.get(index + 1).map_or(end, |next| next.start_ms);
+        words[index].duration_ms = (next_start - words[index].start_ms).max(0.0);
+    }
+    Ok(LyricLine {
+        index,
+        text: text.trim().into(),
+        start_ms: start,
+        duration_ms: end - start,
+        words: if timed { words } else { Vec::new() },
+    })
+}
+
+fn push_word(words: &mut Vec<LyricWord>, text: &mut String, raw: &str, start_ms: f32) {
+    text.push_str(raw);
+    let trimmed = raw.trim();
+    if !trimmed.is_empty() {
+        words.push(LyricWord {
+            text: trimmed.into(),
+            start_ms,
+            duration_ms: 0.0,
+        });
+    }
+}
+
+/// `hh:mm:ss.ttt`. Hours are always written so long recordings stay valid.
+pub fn format_timestamp(milliseconds: f32) -> String {
+    let milliseconds = milliseconds.max(0.0).round() as u64;
+    format!(
+        "{:02}:{:02}:{:02}.{:03}",
+        milliseconds / 3_600_000,
+        milliseconds / 60_000 % 60,
+        milliseconds / 1_000 % 60,
+        milliseconds % 1_000
+    )
+}
+
+/// Parses `hh:mm:ss.ttt` or `mm:ss.ttt` into milliseconds.
+pub fn parse_timestamp(text: &str) -> Option<f32> {
+    let (clock, fraction) = text.split_once('.')?;
+    if fraction.len() != 3 || !fraction.bytes().all(|byte| byte.is_ascii_digit()) {
+        return None;
+    }
+    let mut parts = clock.split(':').rev();
+    let seconds = parse_component(parts.next()?, 59)?;
+    let minutes = parse_component(parts.next()?, 59)?;
+    let hours = match parts.next() {
+        Some(hours) if !hours.is_empty() && hours.bytes().all(|byte| byte.is_ascii_digit()) => {
+            hours.parse::<u64>().ok()?
+        }
+        Some(_) => return None,
+        None => 0,
+    };
+    if parts.next().is_some() {
+        return None;
+    }
+    let total = ((hours * 60 + minutes) * 60 + seconds) * 1_000 + fraction.parse::<u64>().ok()?;
+    Some(total as f32)
+}
+
+fn parse_component(text: &str, max: u64) -> Option<u64> {
+    if text.len() != 2 || !text.bytes().all(|byte| byte.is_ascii_digit()) {
+        return None;
+    }
+    text.parse().ok().filter(|value| *value <= max)
+}
+
+fn escape(text: &str) -> String {
+    text.replace('&', "&amp;")
+        .replace('<', "&lt;")
+        .replace('>', "&gt;")
+}
+
+fn unescape(text: &str) -> String {
+    text.replace("&lt;", "<")
+        .replace("&gt;", ">")
+        .replace("&nbsp;", "\u{a0}")
+        .replace("&lrm;", "\u{200e}")
+        .replace("&rlm;", "\u{200f}")
+        .replace("&amp;", "&")
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::fixtures::{self, word};
+
+    #[test]
+    fn formats_and_parses_cue_timestamps() {
+        assert_eq!(format_timestamp(0.0), "00:00:00.000");
+        assert_eq!(format_timestamp(3_723_004.0), "01:02:03.004");
+        assert_eq!(parse_timestamp("01:02:03.004"), Some(3_723_004.0));
+        assert_eq!(parse_timestamp("02:03.004"), Some(123_004.0));
+        assert_eq!(parse_timestamp("02:60.000"), None);
+        assert_eq!(parse_timestamp("c.loud"), None);
+    }
+
+    #[test]
+    fn webvtt_round_trips_through_the_parser() {
+        let lines = vec![
+            LyricLine {
+                index: 0,
+                text: "把爱留在身边".into(),
+                start_ms: 1_000.0,
+                duration_ms: 1_700.0,
+                words: vec![
+                    word("把爱", 1_000.0, 500.0),
+                    word("留在", 1_500.0, 500.0),
+                    word("身边", 2_000.0, 700.0),
+                ],
+            },
+            LyricLine {
+                index: 1,
+                text: "Rock & <roll> tonight".into(),
+                start_ms: 3_000.0,
+                duration_ms: 1_500.0,
+                words: vec![
+                    word("Rock", 3_100.0, 300.0),
+                    word("&", 3_400.0, 200.0),
+                    word("<roll>", 3_600.0, 400.0),
+                    word("to", 4_000.0, 200.0),
+                    word("night", 4_200.0, 300.0),
+                ],
+            },
+            LyricLine {
+                index: 2,
+                text: "纯文本".into(),
+                start_ms: 5_000.0,
+                duration_ms: 1_500.0,
+                words: Vec::new(),
+            },
+        ];
+        let timeline = fixtures::timeline(lines.clone());
+        let exported = to_webvtt(&timeline);
+        assert!(exported.starts_with("WEBVTT\n\n1\n00:00:01.000 --> 00:00:02.700\n"));
+        assert!(exported.contains("把爱<00:00:01.500>留在<00:00:02.000>身边\n"));
+        assert!(exported.contains("<00:00:03.600>&lt;roll&gt; <00:00:04.000>to"));
+
+        assert_eq!(parse_webvtt(&exported), Ok(lines));
+    }
+
+    #[test]
+    fn parser_skips_notes_and_formatting_tags() {
+        let contents = "\u{feff}WEBVTT - lyrics\r\n\r\nNOTE exported elsewhere\r\n\r\n\
+                        intro\r\n00:01.000 --> 00:02.000 align:center\r\n\
+                        <v Singer><c.word>La</c> <00:01.500><c.word>la</c>\r\n";
+        let lines = parse_webvtt(contents).expect("parse webvtt");
+        assert_eq!(lines.len(), 1);
+        assert_eq!(lines[0].text, "La la");
+        assert_eq!(
+            lines[0].words,
+            [word("La", 1_000.0, 500.0), word("la", 1_500.0, 500.0)]
+        );
+        assert!(parse_webvtt("1\n00:01.000 --> 00:02.000\nla\n").is_err());
+    }
+}
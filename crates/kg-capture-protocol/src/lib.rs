@@ -3,7 +3,7 @@
 use ipc_channel::ipc::{IpcReceiver, IpcSender};
 use serde::{Deserialize, Serialize};
 
-pub const PROTOCOL_VERSION: u16 = 2;
+pub const PROTOCOL_VERSION: u16 = 3;
 pub const BOOTSTRAP_ENDPOINT_CAPACITY: usize = 512;
 pub const BOOTSTRAP_LOG_PATH_CAPACITY: usize = 512;
 
@@ -100,6 +100,8 @@ pub enum LyricSource {
     Standard,
     LiveShow,
     Fixture,
+    /// Parsed from a lyric file rather than captured from WeSing.
+    Imported,
 }
 
 #[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
@@ -134,6 +136,16 @@ pub struct PlaybackPosition {
     pub line_progress: f32,
 }
 
+/// Playback state inferred by the hook from successive render positions.
+#[derive(Clone, Copy, Debug, Eq, PartialEq, Serialize, Deserialize)]
+pub enum PlaybackState {
+    Playing,
+    Paused,
+    Seeking,
+    Stopped,
+    SongEnded,
+}
+
 #[derive(Clone, Debug, Serialize, Deserialize)]
 pub enum HostCommand {
     StartCapture,
@@ -155,9 +167,15 @@ pub enum HookEvent {
     CaptureStopped,
     Timeline(LyricTimeline),
     Playback(PlaybackPosition),
+    PlaybackState {
+        timeline_id: u64,
+        state: PlaybackState,
+    },
     Warning(String),
     Error(String),
-    Pong { sequence: u64 },
+    Pong {
+        sequence: u64,
+    },
 }
 
 /// First message sent through the one-shot bootstrap server. Transferring both
@@ -63,6 +63,7 @@ fn test_all() -> Result<(), String> {
 
     println!("running unit tests");
     run_cargo("test protocol", &["test", "-p", "kg-capture-protocol"])?;
+    run_cargo("test lyrics", &["test", "-p", "kg-capture-lyrics"])?;
     run_cargo(
         "test x64 host",
         &[
@@ -83,6 +84,16 @@ fn test_all() -> Result<(), String> {
             "i686-pc-windows-msvc",
         ],
     )?;
+    run_cargo(
+        "test x86 hook",
+        &[
+            "test",
+            "-p",
+            "kg-capture-hook",
+            "--target",
+            "i686-pc-windows-msvc",
+        ],
+    )?;
 
     println!("running strict architecture-specific lints");
     run_cargo(
@@ -95,6 +106,7 @@ fn test_all() -> Result<(), String> {
             "kg-capture-xtask",
             "--target",
             "x86_64-pc-windows-msvc",
+            "--all-targets",
             "--",
             "-D",
             "warnings",
@@ -112,6 +124,7 @@ fn test_all() -> Result<(), String> {
             "kg-capture-fixture",
             "--target",
             "i686-pc-windows-msvc",
+            "--all-targets",
             "--",
             "-D",
             "warnings",
@@ -321,17 +334,20 @@ impl Drop for SmokeFixture {
     }
 }
 
+#[cfg(windows)]
 fn hidden_command(program: &Path) -> Command {
+    use std::os::windows::process::CommandExt;
+    const CREATE_NO_WINDOW: u32 = 0x0800_0000;
     let mut command = Command::new(program);
-    #[cfg(windows)]
-    {
-        use std::os::windows::process::CommandExt;
-        const CREATE_NO_WINDOW: u32 = 0x0800_0000;
-        command.creation_flags(CREATE_NO_WINDOW);
-    }
+    command.creation_flags(CREATE_NO_WINDOW);
     command
 }
 
+#[cfg(not(windows))]
+fn hidden_command(program: &Path) -> Command {
+    Command::new(program)
+}
+
 fn build_and_stage() -> Result<(), String> {
     build_release_artifacts()?;
     let distribution = workspace_root().join("dist");
@@ -374,6 +390,10 @@ fn stage_artifacts(destination: &Path) -> Result<(), String> {
             root.join("target/x86_64-pc-windows-msvc/release/kg-capture.exe"),
             destination.join("kg-capture.exe"),
         ),
+        (
+            root.join("target/x86_64-pc-windows-msvc/release/kg-capture-render.exe"),
+            destination.join("kg-capture-render.exe"),
+        ),
         (
             root.join("target/i686-pc-windows-msvc/release/kg-capture-injector.exe"),
             destination.join("kg-capture-injector.exe"),
@@ -411,6 +431,7 @@ fn run_cargo(context: &str, arguments: &[&str]) -> Result<(), String> {
 fn verify_distribution(distribution: &Path) -> Result<(), String> {
     let expected = [
         ("kg-capture.exe", X64),
+        ("kg-capture-render.exe", X64),
         ("kg-capture-injector.exe", X86),
         ("kg_capture_hook.dll", X86),
     ];